@@ -0,0 +1,79 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    context::RequestContext,
+    events::{self, ServerEvent},
+    redact,
+    scope::RequestScope,
+    state::AppState,
+};
+
+#[derive(Debug, Serialize)]
+struct AccessLogEntry<'a> {
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    latency_ms: f64,
+    //null for streamed responses
+    response_bytes: Option<u64>,
+    request_id: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    //LOG_REDACT_HEADERS values masked
+    headers: Map<String, Value>,
+}
+
+//Middleware (inside context_middleware)
+//one JSON line per request on the `access` tracing target, written when the response
+//head is ready (streamed bodies are still being sent)
+pub async fn access_log_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started_at = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .map(str::to_string);
+    let headers = redact::redact_headers(request.headers(), &state.config.log_redact_headers);
+    let request_id = request
+        .extensions()
+        .get::<RequestScope>()
+        .and_then(|scope| scope.get::<RequestContext>())
+        .map(|context| context.request_id);
+    let response = next.run(request).await;
+    let entry = AccessLogEntry {
+        method: method.as_str(),
+        path: &path,
+        status: response.status().as_u16(),
+        latency_ms: started_at.elapsed().as_micros() as f64 / 1000.0,
+        response_bytes: response.body().size_hint().exact(),
+        request_id: request_id.as_deref(),
+        user_agent: user_agent.as_deref(),
+        headers,
+    };
+    if let Ok(line) = serde_json::to_string(&entry) {
+        tracing::info!(target: "access", "{}", line);
+    }
+    events::publish(&state, || ServerEvent::Request {
+        method: entry.method.to_string(),
+        path: entry.path.to_string(),
+        status: entry.status,
+        latency_ms: entry.latency_ms,
+        request_id: entry.request_id.map(str::to_string),
+    });
+    response
+}
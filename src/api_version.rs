@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{error::AppError, state::AppState};
+
+pub const ACCEPT_VERSION: HeaderName = HeaderName::from_static("accept-version");
+pub const X_API_VERSION: HeaderName = HeaderName::from_static("x-api-version");
+
+//API version the request is served with (request extension)
+#[derive(Debug, Clone)]
+pub struct ApiVersion {
+    pub version: String,
+    //path prefix of the version's route tree (`/api/v1`), empty when negotiated by header
+    pub root: String,
+}
+
+impl ApiVersion {
+    pub fn mounted(version: &str, root: &str) -> Self {
+        Self {
+            version: version.to_string(),
+            root: root.to_string(),
+        }
+    }
+}
+
+//Middleware
+//validates `Accept-Version` (or `X-API-Version`) against API_VERSIONS.
+//a missing header means the latest (last listed) version, echoed back in `X-API-Version`
+pub async fn api_version_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let supported = &state.config.api_versions;
+    let Some(latest) = supported.last() else {
+        return Ok(next.run(request).await);
+    };
+    let requested = request
+        .headers()
+        .get(ACCEPT_VERSION)
+        .or_else(|| request.headers().get(X_API_VERSION))
+        .map(|value| value.to_str().unwrap_or("").trim().to_string());
+    let version = match requested {
+        None => latest.clone(),
+        Some(requested) => match supported.iter().find(|version| **version == requested) {
+            Some(version) => version.clone(),
+            None => {
+                return Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    "UNSUPPORTED_VERSION",
+                    format!(
+                        "unsupported API version {:?}, supported versions: {}",
+                        requested,
+                        supported.join(", ")
+                    ),
+                ));
+            }
+        },
+    };
+    let value = HeaderValue::from_str(&version).ok();
+    request.extensions_mut().insert(ApiVersion {
+        version,
+        root: String::new(),
+    });
+    let mut response = next.run(request).await;
+    //a version tree (mounted_version_middleware) has already set its own
+    if let Some(value) = value {
+        response.headers_mut().entry(X_API_VERSION).or_insert(value);
+    }
+    Ok(response)
+}
+
+//Middleware
+//layered on a version's route tree (/api/v1, /api/v2): the path decides the version,
+//overriding what api_version_middleware negotiated from the headers
+pub async fn mounted_version_middleware(
+    State(version): State<ApiVersion>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let value = HeaderValue::from_str(&version.version).ok();
+    request.extensions_mut().insert(version);
+    let mut response = next.run(request).await;
+    if let Some(value) = value {
+        response.headers_mut().insert(X_API_VERSION, value);
+    }
+    response
+}
@@ -0,0 +1,296 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use utoipa::{
+    Modify,
+    openapi::{
+        OpenApi, PathItemType,
+        security::{ApiKey, ApiKeyValue, SecurityRequirement, SecurityScheme},
+    },
+};
+
+use crate::{
+    audit::Subject,
+    auth, checksum,
+    config::Config,
+    error::AppError,
+    ratelimit::{self, RateLimiter},
+    scope::RequestScope,
+    state::AppState,
+};
+
+pub const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+//name of the security scheme in the API document
+const SCHEME: &str = "api_key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+}
+
+impl Scope {
+    fn parse(scope: &str) -> Option<Self> {
+        match scope.trim() {
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+//one key: an API_KEYS entry `<name>:<key>:<scopes>[:<per minute>]` with the scopes
+//joined by `+` (e.g. `ci:s3cr3t:read+write:60`), or an object of the JSON array in a
+//`file:` store. rate_limit 0 (or none): unlimited
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKeyEntry {
+    pub name: String,
+    pub key: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub rate_limit: u32,
+}
+
+impl ApiKeyEntry {
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut parts = entry.trim().split(':');
+        let (Some(name), Some(key), Some(scopes)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+        let rate_limit = match parts.next() {
+            Some(rate) => rate.trim().parse().ok()?,
+            None => 0,
+        };
+        if name.is_empty() || key.is_empty() || parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_string(),
+            key: key.to_string(),
+            scopes: scopes
+                .split('+')
+                .map(Scope::parse)
+                .collect::<Option<Vec<Scope>>>()?,
+            rate_limit,
+        })
+    }
+}
+
+//the caller behind a valid key (handlers: `Option<Extension<ApiClient>>`)
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug)]
+struct StoredKey {
+    client: ApiClient,
+    limiter: Arc<RateLimiter<()>>,
+}
+
+//API_KEY_STORE: none | memory (the API_KEYS entries) | file:<path> (a JSON array of
+//entries). keys are held as SHA-256 digests, and a reload reads them again
+#[derive(Debug, Default)]
+pub struct ApiKeyStore {
+    enabled: bool,
+    keys: HashMap<String, StoredKey>,
+}
+
+impl ApiKeyStore {
+    pub fn from_config(config: &Config) -> Self {
+        let entries = match config.api_key_store.as_str() {
+            "none" | "" => return Self::default(),
+            "memory" => config.api_keys.clone(),
+            store => {
+                let path = store.strip_prefix("file:").unwrap_or(store);
+                match load(path) {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        //fails closed: every key is rejected until the file is fixed
+                        tracing::error!("API key store {} not loaded: {}", path, err);
+                        Vec::new()
+                    }
+                }
+            }
+        };
+        let keys = entries
+            .into_iter()
+            .map(|entry| {
+                let stored = StoredKey {
+                    limiter: RateLimiter::new("api key", entry.rate_limit, entry.rate_limit),
+                    client: ApiClient {
+                        name: entry.name,
+                        scopes: entry.scopes,
+                    },
+                };
+                (checksum::sha256_hex(entry.key.as_bytes()), stored)
+            })
+            .collect();
+        Self {
+            enabled: true,
+            keys,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn get(&self, key: &str) -> Option<&StoredKey> {
+        self.keys.get(&checksum::sha256_hex(key.as_bytes()))
+    }
+}
+
+fn load(path: &str) -> Result<Vec<ApiKeyEntry>, String> {
+    let json = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    serde_json::from_str(&json).map_err(|err| err.to_string())
+}
+
+//Middleware
+//with an API key store, requests outside AUTH_PUBLIC_PATHS need a known `x-api-key`
+//(401 otherwise) and are held to the key's rate limit (429). independent of the JWT
+//check: with both configured a request needs both. the ApiClient is left in the
+//request extensions and becomes the audit subject unless a token already set one
+pub async fn api_key_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let store = &state.api_keys;
+    if !store.enabled()
+        || auth::is_public(
+            &state.config.auth_public_paths,
+            request.method(),
+            request.uri().path(),
+        )
+    {
+        return next.run(request).await;
+    }
+    let key = request
+        .headers()
+        .get(X_API_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim);
+    let stored = match key {
+        Some(key) => store.get(key).ok_or_else(|| {
+            AppError::new(
+                StatusCode::UNAUTHORIZED,
+                "INVALID_API_KEY",
+                "unknown API key",
+            )
+        }),
+        None => Err(AppError::new(
+            StatusCode::UNAUTHORIZED,
+            "API_KEY_REQUIRED",
+            "an x-api-key header is required",
+        )),
+    };
+    let stored = match stored {
+        Ok(stored) => stored,
+        Err(err) => return err.into_response(),
+    };
+    let client = &stored.client;
+    if stored.limiter.enabled()
+        && let Err(wait) = stored.limiter.acquire(())
+    {
+        tracing::warn!("rate limit of API key {} exceeded", client.name);
+        return ratelimit::rate_limited(&format!("API key {}", client.name), wait);
+    }
+    if let Some(scope) = request.extensions().get::<RequestScope>()
+        && scope.get::<Subject>().is_none()
+    {
+        scope.set(Subject(format!("api-key:{}", client.name)));
+    }
+    request.extensions_mut().insert(client.clone());
+    next.run(request).await
+}
+
+//scopes a route requires, declared with its route layer
+#[derive(Debug, Clone, Copy)]
+pub struct RequiredScopes(pub &'static [Scope]);
+
+//Middleware (MethodRouter::layer, e.g. `RequiredScopes(&[Scope::Write])` on the
+//mutating methods)
+//403 INSUFFICIENT_SCOPE unless the request's API key has every required scope. a request
+//without an ApiClient (no key store, or a public path) passes
+pub async fn require_scopes_middleware(
+    State(RequiredScopes(required)): State<RequiredScopes>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(client) = request.extensions().get::<ApiClient>()
+        && let Some(missing) = required.iter().find(|scope| !client.scopes.contains(scope))
+    {
+        return AppError::new(
+            StatusCode::FORBIDDEN,
+            "INSUFFICIENT_SCOPE",
+            format!(
+                "API key {} lacks the {} scope",
+                client.name,
+                missing.as_str()
+            ),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+//adds the `api_key` security scheme to ApiDoc
+pub struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let scheme = ApiKeyValue::with_description(
+            X_API_KEY.as_str(),
+            "key from API_KEY_STORE; reads of messages and notes need the `read` scope, changes `write`",
+        );
+        components.add_security_scheme(SCHEME, SecurityScheme::ApiKey(ApiKey::Header(scheme)));
+    }
+}
+
+//with the key store on, every operation outside AUTH_PUBLIC_PATHS requires the scheme
+pub fn mark_spec(openapi: &mut OpenApi, config: &Config) {
+    if matches!(config.api_key_store.as_str(), "none" | "") {
+        return;
+    }
+    for (path, item) in openapi.paths.paths.iter_mut() {
+        for (kind, operation) in item.operations.iter_mut() {
+            if auth::is_public(&config.auth_public_paths, &method(kind), path) {
+                continue;
+            }
+            operation.security = Some(vec![SecurityRequirement::new(SCHEME, Vec::<String>::new())]);
+        }
+    }
+}
+
+fn method(kind: &PathItemType) -> Method {
+    match kind {
+        PathItemType::Get => Method::GET,
+        PathItemType::Post => Method::POST,
+        PathItemType::Put => Method::PUT,
+        PathItemType::Delete => Method::DELETE,
+        PathItemType::Options => Method::OPTIONS,
+        PathItemType::Head => Method::HEAD,
+        PathItemType::Patch => Method::PATCH,
+        PathItemType::Trace => Method::TRACE,
+        PathItemType::Connect => Method::CONNECT,
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{RawPathParams, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{context::RequestContext, scope::RequestScope, state::AppState};
+
+//authenticated caller, left in the RequestScope by whichever layer authenticated the
+//request (e.g. verify_signature_middleware); unauthenticated requests audit as anonymous
+#[derive(Debug, Clone)]
+pub struct Subject(pub String);
+
+//Middleware (route_layer: runs only after a route matched)
+//one `audit` target event per mutating request (POST/PUT/PATCH/DELETE) with who, what,
+//when and the outcome status; reads are not audited. route it to its own sink with a
+//tracing filter on the target
+pub async fn audit_middleware(
+    State(state): State<Arc<AppState>>,
+    params: Option<RawPathParams>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !matches!(
+        *request.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    ) {
+        return next.run(request).await;
+    }
+    let method = request.method().clone();
+    let route = state
+        .route(&request)
+        .map_or(request.uri().path(), |meta| meta.template.as_str())
+        .to_string();
+    //`name=value` pairs of the path parameters, e.g. `path=1`
+    let resource = params
+        .iter()
+        .flat_map(|params| params.iter())
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(",");
+    let scope = request.extensions().get::<RequestScope>().cloned();
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let response = next.run(request).await;
+    //read afterwards: the authenticating layer runs inside this one
+    let subject = scope
+        .as_ref()
+        .and_then(|scope| scope.get::<Subject>())
+        .map_or_else(|| "anonymous".to_string(), |Subject(subject)| subject);
+    let context = scope.and_then(|scope| scope.get::<RequestContext>());
+    tracing::info!(
+        target: "audit",
+        subject = %subject,
+        method = %method,
+        route = %route,
+        resource = %resource,
+        timestamp_ms = timestamp_ms as u64,
+        status = response.status().as_u16(),
+        request_id = context.as_ref().map(|context| context.request_id.as_str()),
+        client_ip = ?context.as_ref().and_then(|context| context.client_ip),
+        "{} {} => {}",
+        method,
+        route,
+        response.status().as_u16()
+    );
+    response
+}
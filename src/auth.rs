@@ -0,0 +1,278 @@
+use std::{
+    marker::PhantomData,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderValue, Method, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apikey::ApiClient,
+    audit::Subject,
+    checksum::{self, base64url_decode},
+    context::ClientIp,
+    error::{AppError, ErrorKind},
+    response::CacheControl,
+    scope::RequestScope,
+    state::AppState,
+};
+
+//decoded payload of a verified token (handlers: `Extension<Claims>`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    //expiry, unix seconds (required)
+    pub exp: u64,
+    //not before, unix seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+    //any other claims (iat, roles, ...)
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+//the verified caller (the Claims auth_middleware left), 401 without one.
+//`Option<CurrentUser>` for routes that also serve anonymous callers
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub Claims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Claims>()
+            .cloned()
+            .map(Self)
+            .ok_or_else(|| unauthorized("UNAUTHORIZED", "a bearer token is required".to_string()))
+    }
+}
+
+impl Claims {
+    //the `roles` claim is an array of strings (a single string is one role)
+    pub fn has_role(&self, role: &str) -> bool {
+        match self.extra.get("roles") {
+            Some(serde_json::Value::Array(roles)) => roles.iter().any(|known| known == role),
+            Some(serde_json::Value::String(known)) => known == role,
+            _ => false,
+        }
+    }
+}
+
+//a role RequireRole checks for
+pub trait Role {
+    const NAME: &'static str;
+}
+
+#[derive(Debug)]
+pub struct Admin;
+
+impl Role for Admin {
+    const NAME: &'static str = "admin";
+}
+
+//CurrentUser with the role R: 401 without claims, 403 FORBIDDEN without the role
+#[derive(Debug)]
+pub struct RequireRole<R>(pub Claims, PhantomData<R>);
+
+#[async_trait]
+impl<R, S> FromRequestParts<S> for RequireRole<R>
+where
+    R: Role,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let CurrentUser(claims) = CurrentUser::from_request_parts(parts, state).await?;
+        if !claims.has_role(R::NAME) {
+            return Err(AppError::of(
+                ErrorKind::Forbidden,
+                format!("this route requires the {:?} role", R::NAME),
+            ));
+        }
+        Ok(Self(claims, PhantomData))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+//HS256 compact JWS `<header>.<payload>.<signature>`. only HS256 is accepted, so
+//`alg: none` and algorithm-confusion tokens are rejected
+pub fn verify(token: &str, secret: &[u8], now: u64) -> Result<Claims, AppError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid_token("malformed token"));
+    };
+    let decode = |part: &str| base64url_decode(part).ok_or_else(|| invalid_token("not base64url"));
+    let jwt_header: JwtHeader =
+        serde_json::from_slice(&decode(header)?).map_err(|_| invalid_token("malformed header"))?;
+    if jwt_header.alg != "HS256" {
+        return Err(invalid_token("unsupported alg, only HS256 is accepted"));
+    }
+    let expected = checksum::hmac_sha256(secret, format!("{}.{}", header, payload).as_bytes());
+    if !checksum::constant_time_eq(&decode(signature)?, &expected) {
+        return Err(invalid_token("signature mismatch"));
+    }
+    let claims: Claims = serde_json::from_slice(&decode(payload)?)
+        .map_err(|err| invalid_token(&format!("malformed claims: {}", err)))?;
+    if claims.exp <= now {
+        return Err(unauthorized(
+            "TOKEN_EXPIRED",
+            "token has expired".to_string(),
+        ));
+    }
+    if claims.nbf.is_some_and(|nbf| nbf > now) {
+        return Err(invalid_token("token is not valid yet"));
+    }
+    Ok(claims)
+}
+
+fn invalid_token(reason: &str) -> AppError {
+    unauthorized("INVALID_TOKEN", format!("invalid bearer token: {}", reason))
+}
+
+fn unauthorized(code: &'static str, message: String) -> AppError {
+    AppError::new(StatusCode::UNAUTHORIZED, code, message)
+}
+
+//`/swagger-ui` covers `/swagger-ui/index.html` but not `/swagger-uix`; `/` only itself.
+//`GET /api/v1/sample` opens only reads (GET, and HEAD with it), writes still need a token
+pub fn is_public(public_paths: &[String], method: &Method, path: &str) -> bool {
+    public_paths.iter().any(|entry| {
+        let (methods, public) = public_path(entry);
+        let method_matches = match methods {
+            None => true,
+            Some(allowed) => {
+                allowed == method.as_str() || (allowed == "GET" && method == Method::HEAD)
+            }
+        };
+        method_matches
+            && (path == public
+                || (public != "/"
+                    && path
+                        .strip_prefix(public)
+                        .is_some_and(|rest| rest.starts_with('/'))))
+    })
+}
+
+//an AUTH_PUBLIC_PATHS entry `[METHOD ]/path`
+pub fn public_path(entry: &str) -> (Option<&str>, &str) {
+    match entry.split_once(' ') {
+        Some((method, path)) => (Some(method), path.trim()),
+        None => (None, entry),
+    }
+}
+
+//Middleware
+//with JWT_SECRET set, requests outside AUTH_PUBLIC_PATHS need `Authorization: Bearer <jwt>`
+//(401 otherwise). the claims are left in the request extensions, and `sub` becomes the
+//audit subject. unset JWT_SECRET disables authentication (local dev)
+pub async fn auth_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config;
+    let Some(secret) = config.jwt_secret.as_deref() else {
+        return next.run(request).await;
+    };
+    if is_public(
+        &config.auth_public_paths,
+        request.method(),
+        request.uri().path(),
+    ) {
+        return next.run(request).await;
+    }
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let claims = match token {
+        Some(token) => verify(token, secret.as_bytes(), now),
+        None => Err(unauthorized(
+            "UNAUTHORIZED",
+            "a bearer token is required".to_string(),
+        )),
+    };
+    match claims {
+        Ok(claims) => {
+            if let Some(scope) = request.extensions().get::<RequestScope>() {
+                scope.set(Subject(claims.sub.clone()));
+            }
+            request.extensions_mut().insert(claims);
+            next.run(request).await
+        }
+        Err(err) => {
+            let mut response = err.into_response();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            response
+        }
+    }
+}
+
+//who is calling, for per-caller keys: the token subject, else the API key, else the
+//client IP (None: nothing identifies the caller)
+pub fn caller(request: &Request, client_ip: Option<ClientIp>) -> Option<String> {
+    let extensions = request.extensions();
+    if let Some(claims) = extensions.get::<Claims>() {
+        Some(format!("sub:{}", claims.sub))
+    } else if let Some(client) = extensions.get::<ApiClient>() {
+        Some(format!("api-key:{}", client.name))
+    } else {
+        client_ip.map(|ClientIp(ip)| format!("ip:{}", ip))
+    }
+}
+
+//Middleware (route layer of the `authenticated` stack)
+//401 AUTHENTICATION_REQUIRED for a request without a verified caller (Claims or an
+//ApiClient), so the route stays closed even under AUTH_PUBLIC_PATHS. without JWT_SECRET
+//and an API key store there is no caller to require (local dev)
+pub async fn require_identity_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let configured = state.config.jwt_secret.is_some() || state.api_keys.enabled();
+    let extensions = request.extensions();
+    if configured && extensions.get::<Claims>().is_none() && extensions.get::<ApiClient>().is_none()
+    {
+        return unauthorized(
+            "AUTHENTICATION_REQUIRED",
+            "this route requires an authenticated caller".to_string(),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+//Handler
+//GET /admin/ping => `pong <sub>` for a caller with the admin role
+pub async fn admin_ping_handler(RequireRole(claims, _): RequireRole<Admin>) -> Response {
+    (CacheControl::NoStore, format!("pong {}", claims.sub)).into_response()
+}
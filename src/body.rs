@@ -0,0 +1,342 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Body as _, Frame, SizeHint};
+use http_body_util::{BodyExt, LengthLimitError};
+use tokio::time::{Instant, Sleep};
+
+use crate::{
+    error::{AppError, ErrorKind},
+    state::AppState,
+};
+
+//buffers a request body up to `limit` (BODY_LIMIT).
+//bytes are counted as they arrive, so chunked bodies without Content-Length
+//are aborted with 413 as soon as they cross the limit
+pub async fn read_request_body(body: Body, limit: usize) -> Result<Bytes, AppError> {
+    axum::body::to_bytes(body, limit)
+        .await
+        .map_err(|err| read_error(err, limit))
+}
+
+//reads the first `max` bytes of a body (at least one byte more when there are more, so
+//the caller can tell it was cut) and hands the body back with them put in front again.
+//the rest is streamed through unread
+pub async fn peek(mut body: Body, max: usize) -> Result<(Bytes, Body), axum::Error> {
+    let mut head = Vec::new();
+    let mut trailers = None;
+    while head.len() <= max {
+        let Some(frame) = body.frame().await else {
+            break;
+        };
+        match frame?.into_data() {
+            Ok(data) => head.extend_from_slice(&data),
+            Err(frame) => {
+                trailers = Some(frame);
+                break;
+            }
+        }
+    }
+    let head = Bytes::from(head);
+    let replayed = PeekedBody {
+        head: (!head.is_empty()).then(|| head.clone()),
+        trailers,
+        inner: body,
+    };
+    Ok((head, Body::new(replayed)))
+}
+
+//the peeked bytes (and trailers, if the body ended with them), then the unread rest.
+//an exact length stays exact (the unread rest plus the bytes put back)
+struct PeekedBody {
+    head: Option<Bytes>,
+    trailers: Option<Frame<Bytes>>,
+    inner: Body,
+}
+
+impl http_body::Body for PeekedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(head) = self.head.take() {
+            return Poll::Ready(Some(Ok(Frame::data(head))));
+        }
+        if let Some(trailers) = self.trailers.take() {
+            return Poll::Ready(Some(Ok(trailers)));
+        }
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.head.is_none() && self.trailers.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let head = self.head.as_ref().map_or(0, |head| head.len() as u64);
+        let mut hint = self.inner.size_hint();
+        if let Some(upper) = hint.upper() {
+            hint.set_upper(upper + head);
+        }
+        hint.set_lower(hint.lower() + head);
+        hint
+    }
+}
+
+//408 SLOW_BODY, 413 PAYLOAD_TOO_LARGE or 400 BODY_READ_FAILED for a failed body read
+pub fn read_error(err: axum::Error, limit: usize) -> AppError {
+    let err = err.into_inner();
+    if let Some(slow) = slow_body_error(err.as_ref()) {
+        slow
+    } else if err.downcast_ref::<LengthLimitError>().is_some() {
+        too_large(limit)
+    } else {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            "BODY_READ_FAILED",
+            format!("failed to read request body: {}", err),
+        )
+    }
+}
+
+pub fn too_large(limit: usize) -> AppError {
+    AppError::of(
+        ErrorKind::PayloadTooLarge,
+        format!("request body exceeds {} bytes", limit),
+    )
+}
+
+//the client sent its body slower than MIN_BODY_RATE (see MinRateBody)
+#[derive(Debug)]
+pub struct SlowBodyError {
+    pub received: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for SlowBodyError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "request body too slow: {} bytes in {:.1}s",
+            self.received,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for SlowBodyError {}
+
+//the body had not arrived completely by BODY_READ_TIMEOUT (see DeadlineBody)
+#[derive(Debug)]
+pub struct BodyTimeoutError {
+    pub received: u64,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for BodyTimeoutError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "request body not received within {}s ({} bytes so far)",
+            self.timeout.as_secs(),
+            self.received
+        )
+    }
+}
+
+impl std::error::Error for BodyTimeoutError {}
+
+//408 SLOW_BODY / BODY_READ_TIMEOUT when a body read failed because of MinRateBody or
+//DeadlineBody (the error may be wrapped several times, e.g. in a Bytes rejection)
+pub fn slow_body_error(err: &(dyn std::error::Error + 'static)) -> Option<AppError> {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(slow) = err.downcast_ref::<SlowBodyError>() {
+            return Some(AppError::new(
+                StatusCode::REQUEST_TIMEOUT,
+                "SLOW_BODY",
+                slow.to_string(),
+            ));
+        }
+        if let Some(timeout) = err.downcast_ref::<BodyTimeoutError>() {
+            return Some(AppError::new(
+                StatusCode::REQUEST_TIMEOUT,
+                "BODY_READ_TIMEOUT",
+                timeout.to_string(),
+            ));
+        }
+        source = err.source();
+    }
+    None
+}
+
+//fails the body once, after the grace period, fewer than `min_rate` bytes per second
+//have arrived on average. a timer wakes the read up even if the client sends nothing
+pub struct MinRateBody {
+    inner: Body,
+    min_rate: u64,
+    grace: Duration,
+    started: Instant,
+    received: u64,
+    check: Pin<Box<Sleep>>,
+}
+
+impl MinRateBody {
+    pub fn new(inner: Body, min_rate: u64, grace: Duration) -> Self {
+        let started = Instant::now();
+        Self {
+            inner,
+            min_rate,
+            grace,
+            started,
+            received: 0,
+            check: Box::pin(tokio::time::sleep_until(started + grace)),
+        }
+    }
+
+    //when the bytes received so far stop satisfying the floor
+    fn next_check(&self) -> Instant {
+        let covered = Duration::from_secs_f64(self.received as f64 / self.min_rate as f64);
+        self.started + covered.max(self.grace) + Duration::from_millis(1)
+    }
+}
+
+impl http_body::Body for MinRateBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.inner).poll_frame(cx) {
+            if let Some(Ok(frame)) = &frame
+                && let Some(data) = frame.data_ref()
+            {
+                self.received += data.len() as u64;
+            }
+            return Poll::Ready(frame);
+        }
+        while self.check.as_mut().poll(cx).is_ready() {
+            let elapsed = self.started.elapsed();
+            if (self.received as f64) < self.min_rate as f64 * elapsed.as_secs_f64() {
+                return Poll::Ready(Some(Err(axum::Error::new(SlowBodyError {
+                    received: self.received,
+                    elapsed,
+                }))));
+            }
+            let next = self.next_check();
+            self.check.as_mut().reset(next);
+        }
+        Poll::Pending
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+//records (in `done`) when the body has been read to the end, and fails it if that
+//hasn't happened `timeout` after it was wrapped (zero: never)
+pub struct DeadlineBody {
+    inner: Body,
+    timeout: Duration,
+    received: u64,
+    deadline: Option<Pin<Box<Sleep>>>,
+    done: Arc<AtomicBool>,
+}
+
+impl DeadlineBody {
+    pub fn new(inner: Body, timeout: Duration, done: Arc<AtomicBool>) -> Self {
+        Self {
+            inner,
+            timeout,
+            received: 0,
+            deadline: (!timeout.is_zero()).then(|| Box::pin(tokio::time::sleep(timeout))),
+            done,
+        }
+    }
+}
+
+impl http_body::Body for DeadlineBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.inner).poll_frame(cx) {
+            match &frame {
+                Some(Ok(frame)) => {
+                    if let Some(data) = frame.data_ref() {
+                        self.received += data.len() as u64;
+                    }
+                    if self.inner.is_end_stream() {
+                        self.done.store(true, Ordering::Relaxed);
+                    }
+                }
+                None => self.done.store(true, Ordering::Relaxed),
+                Some(Err(_)) => {}
+            }
+            return Poll::Ready(frame);
+        }
+        if let Some(deadline) = &mut self.deadline
+            && deadline.as_mut().poll(cx).is_ready()
+        {
+            self.deadline = None;
+            return Poll::Ready(Some(Err(axum::Error::new(BodyTimeoutError {
+                received: self.received,
+                timeout: self.timeout,
+            }))));
+        }
+        Poll::Pending
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+//Middleware
+//enforces MIN_BODY_RATE on request bodies (independent of the request deadline)
+pub async fn min_body_rate_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let min_rate = state.config.min_body_rate;
+    if min_rate == 0 || request.body().is_end_stream() {
+        return next.run(request).await;
+    }
+    let grace = state.config.min_body_rate_grace;
+    next.run(request.map(|body| Body::new(MinRateBody::new(body, min_rate, grace))))
+        .await
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{OriginalUri, Request, State},
+    http::{Extensions, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{config::Config, error::AppError, scope::RequestScope, state::AppState};
+
+//bytes collected into memory for one request, summed over every layer that buffers a
+//request or response body (a body re-read by two layers counts twice)
+#[derive(Debug, Clone, Copy, Default)]
+struct BufferedBytes(usize);
+
+//handle on the request's counter, taken from the request extensions before the body
+//is collected (response sides take it before next.run)
+#[derive(Debug, Clone, Default)]
+pub struct Buffered(Option<RequestScope>);
+
+impl Buffered {
+    pub fn of(extensions: &Extensions) -> Self {
+        Self(extensions.get::<RequestScope>().cloned())
+    }
+
+    pub fn add(&self, bytes: usize) {
+        if let Some(scope) = &self.0 {
+            scope.update::<BufferedBytes>(|buffered| buffered.0 += bytes);
+        }
+    }
+}
+
+//checks the counter once the inner layers are done, whether or not bodies are logged
+pub async fn budget_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if state.config.memory_budget_bytes == 0 {
+        return Ok(next.run(request).await);
+    }
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(request.uri().path(), |uri| uri.path())
+        .to_string();
+    let scope = request.extensions().get::<RequestScope>().cloned();
+    let response = next.run(request).await;
+    let buffered = scope
+        .and_then(|scope| scope.get::<BufferedBytes>())
+        .map_or(0, |buffered| buffered.0);
+    check(&state.config, &path, buffered)?;
+    Ok(response)
+}
+
+//diagnostic check of the bytes buffered for one request (request + response bodies)
+//MEMORY_BUDGET_BYTES=0 disables it, MEMORY_BUDGET_REJECT=true turns the warning into a 500
+fn check(config: &Config, path: &str, buffered: usize) -> Result<(), AppError> {
+    let budget = config.memory_budget_bytes;
+    if buffered <= budget {
+        return Ok(());
+    }
+    tracing::warn!(
+        path,
+        buffered,
+        budget,
+        "request buffered more bytes than the memory budget"
+    );
+    if config.memory_budget_reject {
+        return Err(AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "MEMORY_BUDGET_EXCEEDED",
+            format!("request buffered {} bytes, budget is {}", buffered, budget),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use tokio::sync::Semaphore;
+
+use crate::error::AppError;
+
+//per route group concurrency budget, so heavy endpoints can't starve the others
+#[derive(Debug)]
+pub struct Bulkhead {
+    name: &'static str,
+    semaphore: Semaphore,
+}
+
+impl Bulkhead {
+    pub fn new(name: &'static str, max_concurrency: usize) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            semaphore: Semaphore::new(max_concurrency),
+        })
+    }
+}
+
+//Middleware (MethodRouter::layer per route group)
+pub async fn bulkhead_middleware(
+    State(bulkhead): State<Arc<Bulkhead>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Ok(_permit) = bulkhead.semaphore.try_acquire() else {
+        tracing::warn!("bulkhead {} is full", bulkhead.name);
+        return Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "BULKHEAD_FULL",
+            format!("too many concurrent {} requests", bulkhead.name),
+        ));
+    };
+    Ok(next.run(request).await)
+}
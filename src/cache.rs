@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+
+use http_body::Body as _;
+
+use crate::{
+    apikey::X_API_KEY, budget, checksum, error::AppError, response_limit, state::AppState,
+};
+
+pub const X_CACHE: &str = "x-cache";
+
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: Instant,
+    //CACHE_TTL_SECS, or the response's max-age when shorter
+    ttl: Duration,
+    last_used: Instant,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        match entries.get_mut(key) {
+            Some(entry) if now.duration_since(entry.stored_at) < entry.ttl => {
+                entry.last_used = now;
+                Some(entry.clone())
+            }
+            Some(_) => {
+                //expired
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(
+        &self,
+        key: String,
+        max_age: Duration,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    ) {
+        let ttl = self.ttl.min(max_age);
+        if self.max_entries == 0 || ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        //LRU eviction
+        while entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+        let now = Instant::now();
+        entries.insert(
+            key,
+            CachedResponse {
+                status,
+                headers,
+                body,
+                stored_at: now,
+                ttl,
+                last_used: now,
+            },
+        );
+    }
+}
+
+//Middleware
+//caches 200 responses to safe methods that are explicitly shareable (Cache-Control
+//`public` or `max-age`, without no-store / no-cache / private), keyed by method + path +
+//query + Accept + the caller's credentials. a hit is answered before routing, so the
+//route layers (auth scopes, metrics gate, audit, ...) don't run for it
+pub async fn cache_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let cache = &state.cache;
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return Ok(next.run(request).await);
+    }
+    let headers = request.headers();
+    //a hash, the credentials themselves stay out of memory dumps and debug logs
+    let credentials = [header::AUTHORIZATION, X_API_KEY]
+        .iter()
+        .filter_map(|name| headers.get(name))
+        .fold(Vec::new(), |mut credentials, value| {
+            credentials.extend_from_slice(value.as_bytes());
+            credentials.push(0);
+            credentials
+        });
+    let key = format!(
+        "{} {} {} {}",
+        request.method(),
+        request.uri(),
+        headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or(""),
+        if credentials.is_empty() {
+            String::new()
+        } else {
+            checksum::sha256_hex(&credentials)
+        }
+    );
+
+    if let Some(cached) = cache.get(&key) {
+        tracing::debug!("cache hit: {}", key);
+        let mut response = Response::new(Body::from(cached.body));
+        *response.status_mut() = cached.status;
+        *response.headers_mut() = cached.headers;
+        response
+            .headers_mut()
+            .insert(X_CACHE, HeaderValue::from_static("HIT"));
+        return Ok(response);
+    }
+
+    let buffered = budget::Buffered::of(request.extensions());
+    let response = next.run(request).await;
+    //streamed (unknown length) bodies may never end, e.g. GET /_tap. responses without
+    //an explicit freshness (GET /readyz, /metrics, the message reads, ...) are per caller
+    let Some(max_age) = shareable(response.headers()) else {
+        return Ok(response);
+    };
+    if response.status() != StatusCode::OK || response.body().size_hint().exact().is_none() {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(bytes.len());
+    cache.insert(
+        key,
+        max_age,
+        parts.status,
+        parts.headers.clone(),
+        bytes.clone(),
+    );
+    parts
+        .headers
+        .insert(X_CACHE, HeaderValue::from_static("MISS"));
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
+
+//how long a response may be shared: `public` (CACHE_TTL_SECS) or `max-age=N`; None
+//without Cache-Control or with no-store / no-cache / private
+fn shareable(headers: &HeaderMap) -> Option<Duration> {
+    let mut public = false;
+    let mut max_age = None;
+    for directive in headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let directive = directive.trim().to_ascii_lowercase();
+        match directive.split_once('=') {
+            Some(("max-age", seconds)) => {
+                max_age = Some(Duration::from_secs(seconds.trim_matches('"').parse().ok()?));
+            }
+            _ if matches!(directive.as_str(), "no-store" | "no-cache" | "private") => {
+                return None;
+            }
+            _ if directive == "public" => public = true,
+            _ => {}
+        }
+    }
+    max_age.or(public.then_some(Duration::MAX))
+}
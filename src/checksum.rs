@@ -0,0 +1,198 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, HeaderName, HeaderValue},
+};
+use http_body::Frame;
+use sha2::{Digest, Sha256};
+
+pub const X_CHECKSUM: HeaderName = HeaderName::from_static("x-checksum");
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+pub fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    hex(&hmac_sha256(key, data))
+}
+
+//HMAC-SHA256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|key| key ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+//compares without short-circuiting on the first differing byte
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+//RFC 4648 base64url without padding
+pub fn base64url_encode(bytes: &[u8]) -> String {
+    encode(BASE64URL, bytes)
+}
+
+//RFC 4648 base64 with padding
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = encode(BASE64, bytes);
+    while !encoded.len().is_multiple_of(4) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+fn encode(alphabet: &[u8; 64], bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let block = chunk.iter().enumerate().fold(0u32, |block, (index, byte)| {
+            block | (u32::from(*byte) << (16 - 8 * index))
+        });
+        for index in 0..=chunk.len() {
+            encoded.push(alphabet[(block >> (18 - 6 * index)) as usize & 0x3f] as char);
+        }
+    }
+    encoded
+}
+
+//SHA-1 (RFC 3174). broken for signatures; only for protocols that require it
+//(the WebSocket handshake)
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] =
+                (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                    .rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+pub fn base64url_decode(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 4 == 1 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(value.len() * 3 / 4);
+    for chunk in value.as_bytes().chunks(4) {
+        let mut block = 0u32;
+        for (index, char) in chunk.iter().enumerate() {
+            let sextet = BASE64URL.iter().position(|known| known == char)? as u32;
+            block |= sextet << (18 - 6 * index);
+        }
+        for index in 0..chunk.len() - 1 {
+            decoded.push((block >> (16 - 8 * index)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+//streams the inner body and appends an `X-Checksum: sha256=<hex>` trailer
+//(hyper only sends trailers on chunked responses to clients that sent `TE: trailers`)
+pub struct ChecksumBody {
+    inner: Body,
+    hasher: Option<Sha256>,
+}
+
+impl ChecksumBody {
+    pub fn new(inner: Body) -> Self {
+        Self {
+            inner,
+            hasher: Some(Sha256::new()),
+        }
+    }
+}
+
+impl http_body::Body for ChecksumBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                if let (Some(data), Some(hasher)) = (frame.data_ref(), self.hasher.as_mut()) {
+                    hasher.update(data);
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err))),
+            None => match self.hasher.take() {
+                Some(hasher) => {
+                    let value = format!("sha256={}", hex(&hasher.finalize()));
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert(
+                        X_CHECKSUM,
+                        HeaderValue::from_str(&value).expect("hex is a valid header value"),
+                    );
+                    Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+                }
+                None => Poll::Ready(None),
+            },
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{io::Write, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use flate2::{
+    Compression,
+    write::{GzEncoder, ZlibEncoder},
+};
+use http_body::Body as _;
+
+use crate::{budget, error::AppError, response_limit, state::AppState, vary};
+
+//response content codings, in order of preference on equal q-values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Gzip,
+    Deflate,
+}
+
+impl Coding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    fn encode(self, bytes: &[u8], level: Compression) -> std::io::Result<Vec<u8>> {
+        let capacity = bytes.len() / 2;
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::with_capacity(capacity), level);
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            //HTTP `deflate` is the zlib format (RFC 9110 8.4.1.2)
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::with_capacity(capacity), level);
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+//Middleware
+//gzip/deflate-encodes responses of at least COMPRESSION_MIN_SIZE bytes for clients
+//accepting either, at COMPRESSION_LEVEL (0-9). tiny bodies like `pong` aren't worth the
+//CPU, and streamed (unknown length) bodies are passed through. brotli would need a crate
+//this template doesn't depend on
+pub async fn compression_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let coding = negotiate(request.headers());
+    let buffered = budget::Buffered::of(request.extensions());
+    let mut response = next.run(request).await;
+    let min_size = state.config.compression_min_size as u64;
+    let size = response.body().size_hint().exact();
+    //204/304 must stay without a body, even with COMPRESSION_MIN_SIZE=0
+    let compressible = !(size.is_none_or(|size| size == 0 || size < min_size)
+        || matches!(
+            response.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+        )
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+        || response.headers().contains_key(header::CONTENT_RANGE));
+    if !compressible {
+        return Ok(response);
+    }
+    //from here Accept-Encoding decides between the encoded and identity variants
+    vary::negotiated(&mut response, header::ACCEPT_ENCODING);
+    let Some(coding) = coding else {
+        return Ok(response);
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(bytes.len());
+    let level = Compression::new(state.config.compression_level.min(9));
+    let compressed = match coding.encode(&bytes, level) {
+        Ok(compressed) => compressed,
+        Err(err) => {
+            tracing::warn!("failed to compress response: {}", err);
+            return Ok(Response::from_parts(parts, Body::from(bytes)));
+        }
+    };
+    tracing::debug!(
+        "compressed response body ({}): {} => {} bytes",
+        coding.name(),
+        bytes.len(),
+        compressed.len()
+    );
+    parts.headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(coding.name()),
+    );
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+    Ok(Response::from_parts(parts, Body::from(compressed)))
+}
+
+//the `Accept-Encoding` coding with the highest q-value (gzip on ties); `*` stands for
+//codings not listed, `q=0` rules one out
+fn negotiate(headers: &HeaderMap) -> Option<Coding> {
+    let listed: Vec<(String, f32)> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = match params.next().unwrap_or("").to_ascii_lowercase() {
+                name if name == "x-gzip" => "gzip".to_string(),
+                name => name,
+            };
+            let q = params
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (name, q)
+        })
+        .collect();
+    let q = |coding: Coding| {
+        listed
+            .iter()
+            .find(|(name, _)| name == coding.name())
+            .or_else(|| listed.iter().find(|(name, _)| name == "*"))
+            .map_or(0.0, |(_, q)| *q)
+    };
+    let (gzip, deflate) = (q(Coding::Gzip), q(Coding::Deflate));
+    if gzip > 0.0 && gzip >= deflate {
+        Some(Coding::Gzip)
+    } else if deflate > 0.0 {
+        Some(Coding::Deflate)
+    } else {
+        None
+    }
+}
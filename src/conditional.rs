@@ -0,0 +1,139 @@
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+
+use crate::{budget, checksum, error::AppError, response_limit, state::AppState};
+
+//one CACHE_POLICIES entry: `<route>=<directives>` with the Cache-Control directives
+//joined by `+` (e.g. `/api/v1/sample/:path/list=public+max-age=60`)
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    pub path: String,
+    pub cache_control: HeaderValue,
+}
+
+impl FromStr for CachePolicy {
+    type Err = String;
+
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected <route>=<directives>, got {:?}", entry);
+        let (path, directives) = entry.split_once('=').ok_or_else(invalid)?;
+        let path = path.trim();
+        let directives: Vec<&str> = directives
+            .split('+')
+            .map(str::trim)
+            .filter(|directive| !directive.is_empty())
+            .collect();
+        if !path.starts_with('/') || directives.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            path: path.to_string(),
+            cache_control: HeaderValue::from_str(&directives.join(", ")).map_err(|_| invalid())?,
+        })
+    }
+}
+
+//Middleware (route layer)
+//CACHE_POLICIES: the route's Cache-Control replaces the handler's on 2xx responses
+pub async fn cache_policy_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let policy = state
+        .route(&request)
+        .and_then(|route| route.cache_control.clone());
+    let mut response = next.run(request).await;
+    if let Some(policy) = policy
+        && response.status().is_success()
+    {
+        response.headers_mut().insert(header::CACHE_CONTROL, policy);
+    }
+    response
+}
+
+//Middleware
+//ETAG_MAX_BYTES: a GET 200 with a known length up to the limit gets a strong ETag (a hash
+//of the body as sent, so every encoding has its own) unless the handler set one. a
+//matching If-None-Match turns it into a 304 without a body
+pub async fn etag_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let max = state.config.etag_max_bytes;
+    if max == 0 || request.method() != Method::GET {
+        return Ok(next.run(request).await);
+    }
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let buffered = budget::Buffered::of(request.extensions());
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+    if let Some(etag) = response.headers().get(header::ETAG) {
+        if matches(if_none_match.as_ref(), etag) {
+            return Ok(not_modified(response));
+        }
+        return Ok(response);
+    }
+    if response
+        .body()
+        .size_hint()
+        .exact()
+        .is_none_or(|len| len > max as u64)
+    {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(bytes.len());
+    let etag = format!("\"{}\"", &checksum::sha256_hex(&bytes)[..32]);
+    let etag = HeaderValue::from_str(&etag).expect("hex is a valid header value");
+    let matched = matches(if_none_match.as_ref(), &etag);
+    parts.headers.insert(header::ETAG, etag);
+    let response = Response::from_parts(parts, Body::from(bytes));
+    if matched {
+        return Ok(not_modified(response));
+    }
+    Ok(response)
+}
+
+//If-None-Match uses the weak comparison: `W/"x"` matches `"x"`
+fn matches(if_none_match: Option<&HeaderValue>, etag: &HeaderValue) -> bool {
+    let Some(if_none_match) = if_none_match.and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+//304 with the response's headers (ETag, Cache-Control, Vary, ...) but no body
+fn not_modified(response: Response) -> Response {
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    for name in [
+        header::CONTENT_LENGTH,
+        header::CONTENT_TYPE,
+        header::CONTENT_ENCODING,
+    ] {
+        parts.headers.remove(name);
+    }
+    Response::from_parts(parts, Body::empty())
+}
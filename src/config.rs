@@ -0,0 +1,818 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    env, fs, io,
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::http::{Method, Uri};
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::{
+    apikey::ApiKeyEntry,
+    auth,
+    conditional::CachePolicy,
+    deprecation::DeprecatedRoute,
+    normalize::TrailingSlash,
+    ratelimit::{Rate, RouteRateLimit},
+    schema::ResponseValidation,
+    timeout::RouteTimeout,
+};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // APP_ENV=development (or dev) enables the dev-only endpoints; unset is production
+    pub dev_mode: bool,
+    pub host: String,
+    pub port: u16,
+    pub log_level: tracing::Level,
+    // connection settings
+    pub keepalive: Duration,
+    pub header_read_timeout: Duration,
+    pub max_connections: usize,
+    // time given to in-flight requests after a shutdown signal
+    pub shutdown_grace: Duration,
+    // after the signal, keep accepting while /readyz answers 503 so load balancers take
+    // the instance out of rotation before the listener closes
+    pub pre_shutdown_delay: Duration,
+    // Retry-After of the /readyz 503
+    pub readiness_retry_after: Duration,
+    pub swagger_enabled: bool,
+    // GET /events (SSE feed of handled requests) and its keep-alive comment interval
+    pub events_enabled: bool,
+    pub events_keepalive: Duration,
+    // CORS (`*` allows any origin)
+    pub cors_allow_origins: Vec<String>,
+    pub cors_allow_credentials: bool,
+    pub cors_max_age: Duration,
+    // JSON field names masked in logged bodies (case-insensitive)
+    pub log_redact_fields: Vec<String>,
+    // log request/response bodies in sample_middleware, on the routes of the
+    // heavy-logging stack (default: on in dev mode), cut to LOG_BODY_MAX_BYTES
+    pub log_bodies: bool,
+    pub log_body_max_bytes: usize,
+    // header values masked in the access log (case-insensitive)
+    pub log_redact_headers: Vec<String>,
+    // POST /sample/:path `query` when the parameter is omitted
+    pub sample_query_default: String,
+    // path prefixes served without the detailed request/response logging
+    pub log_exclude_paths: Vec<String>,
+    // GET response cache
+    pub cache_ttl: Duration,
+    pub cache_max_entries: usize,
+    // cap on the inflated size of compressed request bodies
+    pub max_decompressed_bytes: usize,
+    pub max_response_bytes: usize,
+    // gzip responses of at least compression_min_size bytes at compression_level (0-9)
+    pub compression_level: u32,
+    pub compression_min_size: usize,
+    // JSON request bodies
+    pub json_max_depth: usize,
+    // MIME types accepted by the upload endpoint (`type/*` allowed)
+    pub upload_allowed_types: Vec<String>,
+    // parts (file or plain) in one multipart body (0 = unlimited)
+    pub max_upload_fields: usize,
+    // bytes in one multipart part (0 = unlimited), BODY_LIMIT still caps the whole body
+    pub max_field_bytes: usize,
+    // where POST /upload stores files (created when missing)
+    pub upload_dir: PathBuf,
+    // what GET /files/:name serves (defaults to UPLOAD_DIR)
+    pub files_dir: PathBuf,
+    // slow-loris floor for request bodies in bytes/s (0 = off), checked after the grace period
+    pub min_body_rate: u64,
+    pub min_body_rate_grace: Duration,
+    // handler timeout (0 = none): 503 HANDLER_TIMEOUT, or 408 while the body is still arriving
+    pub request_timeout: Duration,
+    // per-route overrides of REQUEST_TIMEOUT_SECS (`<route>=<secs>`)
+    pub route_timeouts: Vec<RouteTimeout>,
+    // strong ETags (If-None-Match => 304) for GET bodies up to this size (0 = off)
+    pub etag_max_bytes: usize,
+    // Cache-Control per route, replacing the handler's
+    pub cache_policies: Vec<CachePolicy>,
+    // the whole request body must arrive within this (0 = only the handler timeout applies)
+    pub body_read_timeout: Duration,
+    // reject bodies with duplicate object keys
+    pub strict_json: bool,
+    // decode legacy-charset request bodies (latin1, windows-1252, utf-16, ...) to UTF-8
+    pub enable_transcoding: bool,
+    // bulkheads (max concurrent requests per route group)
+    pub bulkhead_sample: usize,
+    pub bulkhead_raw: usize,
+    // per-IP rate limits per route template (requests per minute and burst size)
+    pub route_rate_limits: Vec<RouteRateLimit>,
+    // the limit of routes without a ROUTE_RATE_LIMITS entry (None: unlimited)
+    pub rate_limit_default: Option<Rate>,
+    // initial value of the degraded-mode flag (toggled at runtime via SIGUSR1 or POST /_degraded)
+    pub degraded_mode: bool,
+    // per-request buffering budget (0 = off)
+    pub memory_budget_bytes: usize,
+    pub memory_budget_reject: bool,
+    // routes answered with Deprecation/Sunset headers (marked deprecated in the spec too)
+    pub deprecated_routes: Vec<DeprecatedRoute>,
+    // routes requiring X-Nonce + X-Timestamp (anti-replay)
+    pub replay_protected_routes: Vec<String>,
+    pub replay_window: Duration,
+    // identical POSTs from one client within the window share a response (0 = off)
+    pub dedupe_window: Duration,
+    // how long the response to a POST with an Idempotency-Key is kept (0 = keys ignored)
+    pub idempotency_ttl: Duration,
+    // supported API versions, oldest first (the last one is the default). the route
+    // trees of the listed versions are mounted at /api/v<N>
+    pub api_versions: Vec<String>,
+    // per header value (431 beyond it)
+    pub max_header_value_bytes: usize,
+    // behind a TLS-terminating proxy: redirect/reject plain HTTP
+    pub require_https: bool,
+    // TLS termination on PORT (PEM files, both or neither); HTTP/2 is offered via ALPN
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    // with TLS: plain HTTP port answering with a 308 to https (0 = off)
+    pub http_redirect_port: u16,
+    // peers whose X-Forwarded-* headers are believed
+    pub trusted_proxies: Vec<IpAddr>,
+    // `/path/` handling: strict | redirect | rewrite
+    pub trailing_slash: TrailingSlash,
+    // JSON responses checked against ApiDoc (defaults to log in dev, off in production)
+    pub response_validation: ResponseValidation,
+    // shared secret for POST /webhook signatures (unset disables the endpoint)
+    pub webhook_secret: Option<String>,
+    // /proxy/* forwards to this http:// URL (unset disables the route)
+    pub proxy_upstream: Option<Uri>,
+    // until the upstream's response head (0 = none)
+    pub proxy_timeout: Duration,
+    // extra attempts of idempotent requests without a body
+    pub proxy_retries: u32,
+    // failures in a row that open the circuit breaker (0 = off), and for how long
+    pub proxy_breaker_threshold: u32,
+    pub proxy_breaker_cooldown: Duration,
+    // HMAC key of pagination cursors (unset: random per process)
+    pub cursor_secret: Option<String>,
+    // GET /metrics: bearer token and/or client IP allowlist (both unset: open)
+    pub metrics_token: Option<String>,
+    pub metrics_allowed_ips: Vec<IpAddr>,
+    // HS256 key of bearer JWTs (unset: authentication off)
+    pub jwt_secret: Option<String>,
+    // paths served without a token (`/a` also covers `/a/...`, `GET /a` only opens reads)
+    pub auth_public_paths: Vec<String>,
+    // x-api-key store: none | memory (API_KEYS) | file:<path> (a JSON array)
+    pub api_key_store: String,
+    pub api_keys: Vec<ApiKeyEntry>,
+    // 5xx error reporting: none | log | stderr | file:<path>
+    pub error_reporter: String,
+    // /messages storage: memory | file:<path> (a JSON file)
+    pub message_store: String,
+    // updates of existing resources must send If-Match (428 otherwise)
+    pub if_match_required: bool,
+    // initial maintenance mode (toggled at runtime via SIGUSR2 or POST /_maintenance)
+    pub maintenance_mode: bool,
+    pub maintenance_message: Option<String>,
+    pub maintenance_retry_after: Duration,
+    // path prefixes still served in maintenance mode
+    pub maintenance_exempt_paths: Vec<String>,
+    // largest accepted request body in bytes
+    pub body_limit: usize,
+    // settings file the values were layered over (None: no file)
+    pub config_file: Option<String>,
+    // what from_env couldn't use: invalid values, unknown settings file keys
+    load_problems: Vec<String>,
+}
+
+//CONFIG_FILE default (a missing default file is fine, a missing CONFIG_FILE is not)
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+//what one from_env reads besides the environment: the settings file's values by env key,
+//the keys looked up so far and the values that didn't parse
+#[derive(Debug, Default)]
+struct Source {
+    file: BTreeMap<String, String>,
+    used: RefCell<BTreeSet<String>>,
+    invalid: RefCell<Vec<String>>,
+}
+
+impl Source {
+    fn invalid(&self, problem: String) {
+        self.invalid.borrow_mut().push(problem);
+    }
+}
+
+impl Config {
+    //each setting is `APP_<KEY>`, else `<KEY>` from the environment, else `<key>` from
+    //CONFIG_FILE (tables are prefixes: `[cors] max_age_secs` is CORS_MAX_AGE_SECS), else
+    //the default below
+    pub fn from_env() -> Self {
+        let (config_file, source, mut load_problems) = load_file();
+        let dev_mode =
+            var(&source, "APP_ENV").is_ok_and(|env| matches!(env.as_str(), "development" | "dev"));
+        let upload_dir =
+            PathBuf::from(var(&source, "UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string()));
+        let mut config = Self {
+            dev_mode,
+            host: var(&source, "HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
+            port: env_parse(&source, "PORT", 5000),
+            log_level: env_parse(&source, "LOG_LEVEL", tracing::Level::DEBUG),
+            keepalive: Duration::from_secs(env_parse(&source, "KEEPALIVE_SECS", 75)),
+            header_read_timeout: Duration::from_secs(env_parse(
+                &source,
+                "HEADER_READ_TIMEOUT_SECS",
+                30,
+            )),
+            max_connections: env_parse(&source, "MAX_CONNECTIONS", 1024),
+            shutdown_grace: Duration::from_secs(env_parse(&source, "SHUTDOWN_GRACE_SECS", 10)),
+            pre_shutdown_delay: Duration::from_secs(env_parse(
+                &source,
+                "PRE_SHUTDOWN_DELAY_SECS",
+                if dev_mode { 0 } else { 5 },
+            )),
+            readiness_retry_after: Duration::from_secs(env_parse(
+                &source,
+                "READINESS_RETRY_AFTER_SECS",
+                5,
+            )),
+            swagger_enabled: env_parse(&source, "SWAGGER_ENABLED", true),
+            events_enabled: env_parse(&source, "EVENTS_ENABLED", dev_mode),
+            events_keepalive: Duration::from_secs(env_parse(&source, "EVENTS_KEEPALIVE_SECS", 15)),
+            cors_allow_origins: env_list(&source, "CORS_ALLOW_ORIGINS", "*"),
+            cors_allow_credentials: env_parse(&source, "CORS_ALLOW_CREDENTIALS", false),
+            cors_max_age: Duration::from_secs(env_parse(&source, "CORS_MAX_AGE_SECS", 3600)),
+            log_redact_fields: env_list(
+                &source,
+                "LOG_REDACT_FIELDS",
+                "password,token,authorization",
+            ),
+            log_bodies: env_parse(&source, "LOG_BODIES", dev_mode),
+            log_body_max_bytes: env_parse(&source, "LOG_BODY_MAX_BYTES", 4096),
+            log_redact_headers: env_list(
+                &source,
+                "LOG_REDACT_HEADERS",
+                "authorization,proxy-authorization,cookie,x-api-key,x-signature",
+            ),
+            sample_query_default: var(&source, "SAMPLE_QUERY_DEFAULT").unwrap_or_default(),
+            log_exclude_paths: env_list(&source, "LOG_EXCLUDE_PATHS", "/healthz,/readyz,/metrics"),
+            cache_ttl: Duration::from_secs(env_parse(&source, "CACHE_TTL_SECS", 60)),
+            cache_max_entries: env_parse(&source, "CACHE_MAX_ENTRIES", 1024),
+            max_decompressed_bytes: env_parse(&source, "MAX_DECOMPRESSED_BYTES", 1024 * 1024 * 100),
+            max_response_bytes: env_parse(&source, "MAX_RESPONSE_BYTES", 1024 * 1024 * 100),
+            compression_level: env_parse(&source, "COMPRESSION_LEVEL", 6),
+            compression_min_size: env_parse(&source, "COMPRESSION_MIN_SIZE", 1024),
+            json_max_depth: env_parse(&source, "JSON_MAX_DEPTH", 32),
+            upload_allowed_types: env_list(
+                &source,
+                "UPLOAD_ALLOWED_TYPES",
+                "image/png,image/jpeg,image/gif,application/pdf,text/plain",
+            ),
+            max_upload_fields: env_parse(&source, "MAX_UPLOAD_FIELDS", 32),
+            max_field_bytes: env_parse(&source, "MAX_FIELD_BYTES", 1024 * 1024 * 10), //10MB
+            files_dir: var(&source, "FILES_DIR").map_or_else(|_| upload_dir.clone(), PathBuf::from),
+            upload_dir,
+            min_body_rate: env_parse(&source, "MIN_BODY_RATE", 0),
+            min_body_rate_grace: Duration::from_secs(env_parse(
+                &source,
+                "MIN_BODY_RATE_GRACE_SECS",
+                5,
+            )),
+            request_timeout: Duration::from_secs(env_parse(&source, "REQUEST_TIMEOUT_SECS", 30)),
+            route_timeouts: env_list(&source, "ROUTE_TIMEOUTS", "")
+                .iter()
+                .filter_map(|entry| {
+                    let route = RouteTimeout::parse(entry);
+                    if route.is_none() {
+                        source.invalid(format!("invalid ROUTE_TIMEOUTS entry {:?}", entry));
+                    }
+                    route
+                })
+                .collect(),
+            etag_max_bytes: env_parse(&source, "ETAG_MAX_BYTES", 1024 * 1024),
+            cache_policies: env_list(&source, "CACHE_POLICIES", "")
+                .iter()
+                .filter_map(|entry| match entry.parse::<CachePolicy>() {
+                    Ok(policy) => Some(policy),
+                    Err(err) => {
+                        source.invalid(format!("invalid CACHE_POLICIES entry: {}", err));
+                        None
+                    }
+                })
+                .collect(),
+            body_read_timeout: Duration::from_secs(env_parse(&source, "BODY_READ_TIMEOUT_SECS", 0)),
+            strict_json: env_parse(&source, "STRICT_JSON", false),
+            enable_transcoding: env_parse(&source, "ENABLE_TRANSCODING", false),
+            bulkhead_sample: env_parse(&source, "BULKHEAD_SAMPLE", 64),
+            bulkhead_raw: env_parse(&source, "BULKHEAD_RAW", 8),
+            route_rate_limits: env_list(&source, "ROUTE_RATE_LIMITS", "")
+                .iter()
+                .filter_map(|entry| {
+                    let route = RouteRateLimit::parse(entry);
+                    if route.is_none() {
+                        source.invalid(format!("invalid ROUTE_RATE_LIMITS entry {:?}", entry));
+                    }
+                    route
+                })
+                .collect(),
+            rate_limit_default: match var(&source, "RATE_LIMIT_DEFAULT") {
+                Ok(value) if !value.trim().is_empty() => {
+                    let rate = Rate::parse(&value);
+                    if rate.is_none() {
+                        source.invalid(format!("invalid RATE_LIMIT_DEFAULT: {:?}", value));
+                    }
+                    rate.filter(|rate| rate.per_minute > 0)
+                }
+                _ => None,
+            },
+            degraded_mode: env_parse(&source, "DEGRADED_MODE", false),
+            memory_budget_bytes: env_parse(&source, "MEMORY_BUDGET_BYTES", 0),
+            memory_budget_reject: env_parse(&source, "MEMORY_BUDGET_REJECT", false),
+            deprecated_routes: env_list(&source, "DEPRECATED_ROUTES", "")
+                .iter()
+                .filter_map(|entry| {
+                    let route = DeprecatedRoute::parse(entry);
+                    if route.is_none() {
+                        source.invalid(format!("invalid DEPRECATED_ROUTES entry {:?}", entry));
+                    }
+                    route
+                })
+                .collect(),
+            replay_protected_routes: env_list(&source, "REPLAY_PROTECTED_ROUTES", ""),
+            replay_window: Duration::from_secs(env_parse(&source, "REPLAY_WINDOW_SECS", 300)),
+            dedupe_window: Duration::from_secs(env_parse(&source, "DEDUPE_WINDOW_SECS", 0)),
+            idempotency_ttl: Duration::from_secs(env_parse(
+                &source,
+                "IDEMPOTENCY_TTL_SECS",
+                24 * 60 * 60,
+            )),
+            api_versions: env_list(&source, "API_VERSIONS", "1,2"),
+            max_header_value_bytes: env_parse(&source, "MAX_HEADER_VALUE_BYTES", 8 * 1024),
+            require_https: env_parse(&source, "REQUIRE_HTTPS", false),
+            tls_cert_path: var(&source, "TLS_CERT_PATH")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            tls_key_path: var(&source, "TLS_KEY_PATH")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            http_redirect_port: env_parse(&source, "HTTP_REDIRECT_PORT", 0),
+            trusted_proxies: env_ip_list(&source, "TRUSTED_PROXIES", "127.0.0.1,::1"),
+            trailing_slash: env_parse(&source, "TRAILING_SLASH", TrailingSlash::Strict),
+            response_validation: env_parse(
+                &source,
+                "RESPONSE_VALIDATION",
+                if dev_mode {
+                    ResponseValidation::Log
+                } else {
+                    ResponseValidation::Off
+                },
+            ),
+            webhook_secret: var(&source, "WEBHOOK_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            proxy_upstream: var(&source, "PROXY_UPSTREAM")
+                .ok()
+                .filter(|upstream| !upstream.is_empty())
+                .and_then(|upstream| {
+                    let uri = upstream.parse().ok();
+                    if uri.is_none() {
+                        source.invalid(format!("invalid PROXY_UPSTREAM {:?}", upstream));
+                    }
+                    uri
+                }),
+            proxy_timeout: Duration::from_secs(env_parse(&source, "PROXY_TIMEOUT_SECS", 30)),
+            proxy_retries: env_parse(&source, "PROXY_RETRIES", 2),
+            proxy_breaker_threshold: env_parse(&source, "PROXY_BREAKER_THRESHOLD", 5),
+            proxy_breaker_cooldown: Duration::from_secs(env_parse(
+                &source,
+                "PROXY_BREAKER_COOLDOWN_SECS",
+                30,
+            )),
+            cursor_secret: var(&source, "CURSOR_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            metrics_token: var(&source, "METRICS_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            metrics_allowed_ips: env_ip_list(&source, "METRICS_ALLOWED_IPS", ""),
+            jwt_secret: var(&source, "JWT_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            auth_public_paths: env_list(
+                &source,
+                "AUTH_PUBLIC_PATHS",
+                "/,/swagger-ui,/api-docs,/metrics,/webhook,/shutdown-status,/healthz,/readyz",
+            ),
+            api_key_store: var(&source, "API_KEY_STORE").unwrap_or_else(|_| "none".to_string()),
+            api_keys: env_list(&source, "API_KEYS", "")
+                .iter()
+                .filter_map(|entry| {
+                    let key = ApiKeyEntry::parse(entry);
+                    if key.is_none() {
+                        //the entry holds a secret, so only its name is echoed
+                        source.invalid(format!(
+                            "invalid API_KEYS entry {:?}",
+                            entry.split(':').next().unwrap_or_default()
+                        ));
+                    }
+                    key
+                })
+                .collect(),
+            error_reporter: var(&source, "ERROR_REPORTER").unwrap_or_else(|_| "none".to_string()),
+            message_store: var(&source, "MESSAGE_STORE").unwrap_or_else(|_| "memory".to_string()),
+            if_match_required: env_parse(&source, "IF_MATCH_REQUIRED", false),
+            maintenance_mode: env_parse(&source, "MAINTENANCE_MODE", false),
+            maintenance_message: var(&source, "MAINTENANCE_MESSAGE")
+                .ok()
+                .filter(|message| !message.is_empty()),
+            maintenance_retry_after: Duration::from_secs(env_parse(
+                &source,
+                "MAINTENANCE_RETRY_AFTER_SECS",
+                300,
+            )),
+            maintenance_exempt_paths: env_list(
+                &source,
+                "MAINTENANCE_EXEMPT_PATHS",
+                "/healthz,/readyz,/shutdown-status",
+            ),
+            body_limit: env_parse(&source, "BODY_LIMIT", 1024 * 1024 * 100), //100MB
+            config_file: config_file.clone(),
+            load_problems: Vec::new(),
+        };
+        load_problems.append(&mut source.invalid.borrow_mut());
+        //every lookup is done: what the file set but nothing read is a typo
+        let used = source.used.borrow();
+        for key in source.file.keys().filter(|key| !used.contains(*key)) {
+            load_problems.push(format!(
+                "unknown setting {:?} in {}",
+                key.to_lowercase(),
+                config_file.as_deref().unwrap_or(DEFAULT_CONFIG_FILE)
+            ));
+        }
+        config.load_problems = load_problems;
+        config
+    }
+
+    //fails startup listing every problem, not just the first one
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "invalid configuration ({} problems):\n- {}",
+            problems.len(),
+            problems.join("\n- ")
+        )
+    }
+
+    //settings that are invalid on their own or contradict each other
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = self.load_problems.clone();
+        if self.body_limit == 0 {
+            problems.push("BODY_LIMIT must be at least 1".to_string());
+        }
+        for entry in &self.auth_public_paths {
+            let (method, path) = auth::public_path(entry);
+            if !path.starts_with('/')
+                || method.is_some_and(|method| method.parse::<Method>().is_err())
+            {
+                problems.push(format!(
+                    "AUTH_PUBLIC_PATHS entry {:?} is not `/path` or `METHOD /path`",
+                    entry
+                ));
+            }
+        }
+        if self.cors_allow_credentials && self.cors_any_origin() {
+            problems.push(
+                "CORS_ALLOW_CREDENTIALS=true cannot be combined with CORS_ALLOW_ORIGINS=*, list the allowed origins explicitly".to_string(),
+            );
+        }
+        if self.require_https && self.trusted_proxies.is_empty() && !self.tls_enabled() {
+            problems.push(
+                "REQUIRE_HTTPS=true needs TRUSTED_PROXIES (or TLS): X-Forwarded-Proto is only believed from a trusted proxy, so every request would be rejected".to_string(),
+            );
+        }
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            problems.push("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string());
+        }
+        for (key, path) in [
+            ("TLS_CERT_PATH", &self.tls_cert_path),
+            ("TLS_KEY_PATH", &self.tls_key_path),
+        ] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                problems.push(format!("{} {} is not a file", key, path.display()));
+            }
+        }
+        if self.http_redirect_port != 0 {
+            if !self.tls_enabled() {
+                problems
+                    .push("HTTP_REDIRECT_PORT needs TLS_CERT_PATH and TLS_KEY_PATH".to_string());
+            }
+            if self.http_redirect_port == self.port {
+                problems.push("HTTP_REDIRECT_PORT must differ from PORT".to_string());
+            }
+        }
+        if let Some(upstream) = &self.proxy_upstream
+            && (upstream.scheme_str() != Some("http") || upstream.authority().is_none())
+        {
+            problems.push(format!(
+                "PROXY_UPSTREAM must be an http://host[:port][/path] URL, got {}",
+                upstream
+            ));
+        }
+        if self.compression_level > 9 {
+            problems.push(format!(
+                "COMPRESSION_LEVEL must be 0-9, got {}",
+                self.compression_level
+            ));
+        }
+        if self.json_max_depth == 0 {
+            problems.push("JSON_MAX_DEPTH must be at least 1".to_string());
+        }
+        for (key, value) in [
+            ("BULKHEAD_SAMPLE", self.bulkhead_sample),
+            ("BULKHEAD_RAW", self.bulkhead_raw),
+        ] {
+            if value == 0 {
+                problems.push(format!(
+                    "{} must be at least 1, 0 would block every request",
+                    key
+                ));
+            }
+        }
+        if self.memory_budget_reject && self.memory_budget_bytes == 0 {
+            problems.push(
+                "MEMORY_BUDGET_REJECT=true has no effect without MEMORY_BUDGET_BYTES".to_string(),
+            );
+        }
+        if !self.replay_protected_routes.is_empty() && self.replay_window.is_zero() {
+            problems.push(
+                "REPLAY_WINDOW_SECS must be positive when REPLAY_PROTECTED_ROUTES is set"
+                    .to_string(),
+            );
+        }
+        if self.events_enabled && self.events_keepalive.is_zero() {
+            problems.push("EVENTS_KEEPALIVE_SECS must be positive".to_string());
+        }
+        if self.api_versions.is_empty() {
+            problems.push("API_VERSIONS must list at least one version".to_string());
+        }
+        for allowed in &self.upload_allowed_types {
+            if allowed.parse::<mime::Mime>().is_err() && !allowed.ends_with("/*") {
+                problems.push(format!(
+                    "UPLOAD_ALLOWED_TYPES entry {:?} is not a MIME type",
+                    allowed
+                ));
+            }
+        }
+        let reporter = self.error_reporter.as_str();
+        if !matches!(reporter, "none" | "" | "log" | "stderr")
+            && reporter.strip_prefix("file:").is_none_or(str::is_empty)
+        {
+            problems.push(format!(
+                "ERROR_REPORTER must be none, log, stderr or file:<path>, got {:?}",
+                reporter
+            ));
+        }
+        let store = self.api_key_store.as_str();
+        if !matches!(store, "none" | "" | "memory")
+            && store.strip_prefix("file:").is_none_or(str::is_empty)
+        {
+            problems.push(format!(
+                "API_KEY_STORE must be none, memory or file:<path>, got {:?}",
+                store
+            ));
+        }
+        if store == "memory" && self.api_keys.is_empty() {
+            problems.push("API_KEY_STORE=memory needs at least one API_KEYS entry".to_string());
+        }
+        let store = self.message_store.as_str();
+        if store != "memory" && store.strip_prefix("file:").is_none_or(str::is_empty) {
+            problems.push(format!(
+                "MESSAGE_STORE must be memory or file:<path>, got {:?}",
+                store
+            ));
+        }
+        problems
+    }
+
+    pub fn cors_any_origin(&self) -> bool {
+        self.cors_allow_origins.iter().any(|origin| origin == "*")
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    //effective configuration (secrets must be masked here)
+    pub fn log_summary(&self) {
+        tracing::info!(
+            config_file = ?self.config_file,
+            dev_mode = self.dev_mode,
+            host = %self.host,
+            port = self.port,
+            keepalive_secs = self.keepalive.as_secs(),
+            header_read_timeout_secs = self.header_read_timeout.as_secs(),
+            max_connections = self.max_connections,
+            shutdown_grace_secs = self.shutdown_grace.as_secs(),
+            pre_shutdown_delay_secs = self.pre_shutdown_delay.as_secs(),
+            readiness_retry_after_secs = self.readiness_retry_after.as_secs(),
+            cors_allow_origins = ?self.cors_allow_origins,
+            cors_allow_credentials = self.cors_allow_credentials,
+            cors_max_age_secs = self.cors_max_age.as_secs(),
+            body_limit = self.body_limit,
+            max_header_value_bytes = self.max_header_value_bytes,
+            max_decompressed_bytes = self.max_decompressed_bytes,
+            max_response_bytes = self.max_response_bytes,
+            compression_level = self.compression_level,
+            compression_min_size = self.compression_min_size,
+            json_max_depth = self.json_max_depth,
+            upload_allowed_types = ?self.upload_allowed_types,
+            max_upload_fields = self.max_upload_fields,
+            max_field_bytes = self.max_field_bytes,
+            upload_dir = %self.upload_dir.display(),
+            files_dir = %self.files_dir.display(),
+            min_body_rate = self.min_body_rate,
+            min_body_rate_grace_secs = self.min_body_rate_grace.as_secs(),
+            request_timeout_secs = self.request_timeout.as_secs(),
+            route_timeouts = ?self.route_timeouts.iter().map(|route| (&route.path, route.timeout.as_secs())).collect::<Vec<(&String, u64)>>(),
+            etag_max_bytes = self.etag_max_bytes,
+            cache_policies = ?self.cache_policies.iter().map(|policy| (&policy.path, &policy.cache_control)).collect::<Vec<_>>(),
+            body_read_timeout_secs = self.body_read_timeout.as_secs(),
+            strict_json = self.strict_json,
+            enable_transcoding = self.enable_transcoding,
+            bulkhead_sample = self.bulkhead_sample,
+            bulkhead_raw = self.bulkhead_raw,
+            route_rate_limits = ?self.route_rate_limits.iter().map(|route| (&route.path, route.rate.per_minute, route.rate.burst)).collect::<Vec<(&String, u32, u32)>>(),
+            rate_limit_default = ?self.rate_limit_default.map(|rate| (rate.per_minute, rate.burst)),
+            degraded_mode = self.degraded_mode,
+            memory_budget_bytes = self.memory_budget_bytes,
+            memory_budget_reject = self.memory_budget_reject,
+            deprecated_routes = ?self.deprecated_routes.iter().map(|route| &route.path).collect::<Vec<&String>>(),
+            cache_ttl_secs = self.cache_ttl.as_secs(),
+            cache_max_entries = self.cache_max_entries,
+            swagger_enabled = self.swagger_enabled,
+            events_enabled = self.events_enabled,
+            events_keepalive_secs = self.events_keepalive.as_secs(),
+            log_level = %self.log_level,
+            log_redact_fields = ?self.log_redact_fields,
+            log_bodies = self.log_bodies,
+            log_body_max_bytes = self.log_body_max_bytes,
+            log_redact_headers = ?self.log_redact_headers,
+            log_exclude_paths = ?self.log_exclude_paths,
+            sample_query_default = %self.sample_query_default,
+            require_https = self.require_https,
+            tls_cert_path = ?self.tls_cert_path,
+            tls_key_path = ?self.tls_key_path,
+            http_redirect_port = self.http_redirect_port,
+            trusted_proxies = ?self.trusted_proxies,
+            trailing_slash = ?self.trailing_slash,
+            response_validation = ?self.response_validation,
+            webhook_secret_set = self.webhook_secret.is_some(),
+            proxy_upstream = ?self.proxy_upstream.as_ref().map(Uri::to_string),
+            proxy_timeout_secs = self.proxy_timeout.as_secs(),
+            proxy_retries = self.proxy_retries,
+            proxy_breaker_threshold = self.proxy_breaker_threshold,
+            proxy_breaker_cooldown_secs = self.proxy_breaker_cooldown.as_secs(),
+            cursor_secret_set = self.cursor_secret.is_some(),
+            metrics_token_set = self.metrics_token.is_some(),
+            metrics_allowed_ips = ?self.metrics_allowed_ips,
+            jwt_secret_set = self.jwt_secret.is_some(),
+            auth_public_paths = ?self.auth_public_paths,
+            api_key_store = %self.api_key_store,
+            api_key_names = ?self.api_keys.iter().map(|key| &key.name).collect::<Vec<&String>>(),
+            error_reporter = %self.error_reporter,
+            message_store = %self.message_store,
+            if_match_required = self.if_match_required,
+            maintenance_mode = self.maintenance_mode,
+            maintenance_retry_after_secs = self.maintenance_retry_after.as_secs(),
+            maintenance_exempt_paths = ?self.maintenance_exempt_paths,
+            replay_protected_routes = ?self.replay_protected_routes,
+            replay_window_secs = self.replay_window.as_secs(),
+            dedupe_window_secs = self.dedupe_window.as_secs(),
+            idempotency_ttl_secs = self.idempotency_ttl.as_secs(),
+            api_versions = ?self.api_versions,
+            "effective configuration"
+        );
+    }
+}
+
+//APP_<KEY> or <KEY> from the environment, then the settings file. the key counts as
+//known either way: a file value overridden from the environment is not a typo
+fn var(source: &Source, key: &str) -> Result<String, env::VarError> {
+    source.used.borrow_mut().insert(key.to_string());
+    env::var(format!("APP_{}", key))
+        .or_else(|_| env::var(key))
+        .or_else(|err| source.file.get(key).cloned().ok_or(err))
+}
+
+//reads CONFIG_FILE => (the path read, its values, problems)
+fn load_file() -> (Option<String>, Source, Vec<String>) {
+    let explicit = env::var("CONFIG_FILE").ok().filter(|path| !path.is_empty());
+    let path = explicit.as_deref().unwrap_or(DEFAULT_CONFIG_FILE);
+    let mut source = Source::default();
+    let mut problems = Vec::new();
+    let loaded = match fs::read_to_string(path) {
+        Ok(content) => match content.parse::<DocumentMut>() {
+            Ok(document) => {
+                flatten(document.as_table(), "", &mut source.file, &mut problems);
+                Some(path.to_string())
+            }
+            Err(err) => {
+                problems.push(format!("{} is not valid TOML: {}", path, err));
+                None
+            }
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound && explicit.is_none() => None,
+        Err(err) => {
+            problems.push(format!("cannot read CONFIG_FILE {}: {}", path, err));
+            None
+        }
+    };
+    (loaded, source, problems)
+}
+
+//`[a] b = 1` => A_B = "1", arrays => comma separated (like the env lists)
+fn flatten(
+    table: &Table,
+    prefix: &str,
+    values: &mut BTreeMap<String, String>,
+    problems: &mut Vec<String>,
+) {
+    for (key, item) in table.iter() {
+        let key = format!("{}{}", prefix, key.to_uppercase().replace('-', "_"));
+        match item {
+            Item::Table(table) => flatten(table, &format!("{}_", key), values, problems),
+            Item::Value(Value::InlineTable(table)) => flatten(
+                &table.clone().into_table(),
+                &format!("{}_", key),
+                values,
+                problems,
+            ),
+            Item::Value(Value::Array(array)) => {
+                let items: Vec<String> = array.iter().map(scalar).collect();
+                values.insert(key, items.join(","));
+            }
+            Item::Value(value) => {
+                values.insert(key, scalar(value));
+            }
+            Item::ArrayOfTables(_) => problems.push(format!(
+                "{} can't be an array of tables",
+                key.to_lowercase()
+            )),
+            Item::None => {}
+        }
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.value().clone(),
+        Value::Integer(integer) => integer.value().to_string(),
+        Value::Float(float) => float.value().to_string(),
+        Value::Boolean(boolean) => boolean.value().to_string(),
+        Value::Datetime(datetime) => datetime.value().to_string(),
+        Value::Array(array) => array.iter().map(scalar).collect::<Vec<_>>().join(","),
+        //tables inside arrays have no env equivalent
+        Value::InlineTable(_) => String::new(),
+    }
+}
+
+//comma separated env var => Vec<String>
+fn env_list(source: &Source, key: &str, default: &str) -> Vec<String> {
+    var(source, key)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+//comma separated env var => Vec<IpAddr> (invalid entries are problems)
+fn env_ip_list(source: &Source, key: &str, default: &str) -> Vec<IpAddr> {
+    env_list(source, key, default)
+        .iter()
+        .filter_map(|ip| {
+            let parsed = ip.parse::<IpAddr>().ok();
+            if parsed.is_none() {
+                source.invalid(format!("invalid {} entry {:?}", key, ip));
+            }
+            parsed
+        })
+        .collect()
+}
+
+//env var => T (the default when unset; an invalid value is a problem for validate())
+fn env_parse<T: FromStr>(source: &Source, key: &str, default: T) -> T {
+    match var(source, key) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            source.invalid(format!("invalid {}: {:?}", key, value));
+            default
+        }),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+
+use tracing::Instrument;
+
+use crate::{
+    config::Config,
+    error::{AppError, ErrorKind},
+    scope::RequestScope,
+    state::AppState,
+};
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+//per-request data derived once by context_middleware and kept in the RequestScope
+//(also the extractor)
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub request_id: String,
+    //peer address, or the X-Forwarded-For client when the peer is a trusted proxy
+    pub client_ip: Option<IpAddr>,
+    //first Accept-Language tag
+    pub locale: Option<String>,
+    pub started_at: Instant,
+}
+
+impl RequestContext {
+    fn new(config: &Config, request: &Request) -> Self {
+        let headers = request.headers();
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| peer.ip());
+        Self {
+            request_id: headers
+                .get(X_REQUEST_ID)
+                .and_then(|id| id.to_str().ok())
+                .map(str::trim)
+                .filter(|id| !id.is_empty() && id.len() <= 128)
+                .map_or_else(generate_request_id, str::to_string),
+            client_ip: peer.map(|peer| client_ip(config, peer, headers)),
+            locale: headers
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|language| language.to_str().ok())
+                .and_then(|language| language.split(',').next())
+                .map(|tag| tag.split(';').next().unwrap_or(tag).trim().to_string())
+                .filter(|tag| !tag.is_empty() && tag != "*"),
+            started_at: Instant::now(),
+        }
+    }
+}
+
+//client address honoring TRUSTED_PROXIES (the same value as RequestContext::client_ip).
+//rejects with 500 CLIENT_IP_UNAVAILABLE when the peer address is unknown (the app was
+//not served through server::serve); use Option<ClientIp> where that is acceptable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+    Arc<AppState>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let resolved = parts
+            .extensions
+            .get::<RequestScope>()
+            .and_then(|scope| scope.get::<RequestContext>())
+            .and_then(|context| context.client_ip);
+        //outside context_middleware, resolve it from the peer address directly
+        let client_ip = resolved.or_else(|| {
+            let state = Arc::<AppState>::from_ref(state);
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| client_ip(&state.config, peer.ip(), &parts.headers))
+        });
+        client_ip.map(ClientIp).ok_or_else(|| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "CLIENT_IP_UNAVAILABLE",
+                "the client address is unavailable (no peer address on the connection)",
+            )
+        })
+    }
+}
+
+//random UUID (version 4 layout). the randomness comes from std's randomly keyed
+//SipHash over the time and a counter, which is enough for correlating log lines
+//(and for naming stored uploads)
+pub fn generate_request_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let random = |salt: u64| RandomState::new().hash_one((nanos, count, salt));
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&random(0).to_be_bytes());
+    bytes[8..].copy_from_slice(&random(1).to_be_bytes());
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+//walks X-Forwarded-For from the right, skipping trusted proxies
+pub fn client_ip(config: &Config, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    if !config.trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    let forwarded: Vec<IpAddr> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect();
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !config.trusted_proxies.contains(ip))
+        .or(forwarded.first())
+        .copied()
+        .unwrap_or(peer)
+}
+
+//Middleware
+pub async fn context_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let context = RequestContext::new(&state.config, &request);
+    //the (possibly generated) id is echoed on the request for inner layers, on the
+    //response for the client, and on a span around everything inside this layer
+    let request_id = HeaderValue::from_str(&context.request_id).ok();
+    if let Some(request_id) = &request_id {
+        request
+            .headers_mut()
+            .insert(X_REQUEST_ID, request_id.clone());
+    }
+    let span = tracing::info_span!("request", request_id = %context.request_id);
+    let scope = RequestScope::default();
+    scope.set(context);
+    request.extensions_mut().insert(scope);
+    let mut response = next.run(request).instrument(span).await;
+    if let Some(request_id) = request_id {
+        response.headers_mut().insert(X_REQUEST_ID, request_id);
+    }
+    response
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestContext
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<RequestScope>()
+            .and_then(|scope| scope.get::<RequestContext>())
+            .ok_or_else(|| {
+                AppError::of(
+                    ErrorKind::Internal,
+                    "request context is missing (context_middleware not installed)",
+                )
+            })
+    }
+}
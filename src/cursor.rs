@@ -0,0 +1,88 @@
+use std::{
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::StatusCode;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    checksum::{self, base64url_decode, base64url_encode, constant_time_eq},
+    error::AppError,
+    model::ResponseData,
+};
+
+//hex chars of the HMAC kept in a cursor
+const MAC_LEN: usize = 16;
+
+//opaque pagination position: `base64url(<offset>.<hmac>)`.
+//the HMAC covers the offset and the scope (e.g. the list's path), so clients
+//can neither edit a cursor nor reuse it on another list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub offset: usize,
+}
+
+//one page of a cursor-paginated list (`next_cursor` is null on the last page)
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(ResponseDataPage = CursorPage<ResponseData>)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl Cursor {
+    pub fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+
+    pub fn encode(&self, key: &[u8], scope: &str) -> String {
+        let offset = self.offset.to_string();
+        let mac = mac(key, scope, &offset);
+        base64url_encode(format!("{}.{}", offset, mac).as_bytes())
+    }
+
+    pub fn decode(value: &str, key: &[u8], scope: &str) -> Result<Self, AppError> {
+        let decoded = base64url_decode(value).ok_or_else(|| invalid_cursor("not base64url"))?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid_cursor("not UTF-8"))?;
+        let (offset, signature) = decoded
+            .split_once('.')
+            .ok_or_else(|| invalid_cursor("malformed"))?;
+        if !constant_time_eq(signature.as_bytes(), mac(key, scope, offset).as_bytes()) {
+            return Err(invalid_cursor("signature mismatch"));
+        }
+        let offset = offset
+            .parse()
+            .map_err(|_| invalid_cursor("malformed offset"))?;
+        Ok(Self { offset })
+    }
+}
+
+//CURSOR_SECRET, or a per-process key (cursors then expire on restart)
+pub fn key(secret: Option<&str>) -> Vec<u8> {
+    match secret {
+        Some(secret) => secret.as_bytes().to_vec(),
+        None => {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            checksum::sha256_hex(format!("{}:{}", process::id(), nanos).as_bytes()).into_bytes()
+        }
+    }
+}
+
+fn mac(key: &[u8], scope: &str, offset: &str) -> String {
+    let mut mac = checksum::hmac_sha256_hex(key, format!("{}\n{}", scope, offset).as_bytes());
+    mac.truncate(MAC_LEN);
+    mac
+}
+
+fn invalid_cursor(reason: &str) -> AppError {
+    AppError::new(
+        StatusCode::BAD_REQUEST,
+        "INVALID_CURSOR",
+        format!("invalid cursor: {}", reason),
+    )
+}
@@ -0,0 +1,149 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Instant;
+
+use crate::error::AppError;
+
+pub const X_REQUEST_DEADLINE: &str = "x-request-deadline";
+
+//per-request deadline (stored in request extensions)
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(pub Instant);
+
+impl Deadline {
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+}
+
+//Middleware
+//X-Request-Deadline: <RFC3339 timestamp> | <budget in ms>
+pub async fn deadline_middleware(mut request: Request, next: Next) -> Result<Response, AppError> {
+    let deadline = match request.headers().get(X_REQUEST_DEADLINE) {
+        Some(value) => {
+            let deadline = parse_deadline(value);
+            if deadline.is_none() {
+                tracing::warn!("ignore malformed {}: {:?}", X_REQUEST_DEADLINE, value);
+            }
+            deadline
+        }
+        None => None,
+    };
+    let Some(deadline) = deadline else {
+        return Ok(next.run(request).await);
+    };
+    let deadline = Deadline(deadline);
+    //already passed: the handler doesn't start (timeout_at would still poll it once)
+    if deadline.remaining().is_zero() {
+        return Err(exceeded());
+    }
+    tracing::debug!("request deadline in {:?}", deadline.remaining());
+    request.extensions_mut().insert(deadline);
+    tokio::time::timeout_at(deadline.0, next.run(request))
+        .await
+        .map_err(|_| exceeded())
+}
+
+fn exceeded() -> AppError {
+    AppError::new(
+        StatusCode::GATEWAY_TIMEOUT,
+        "DEADLINE_EXCEEDED",
+        "request deadline exceeded",
+    )
+}
+
+fn parse_deadline(value: &HeaderValue) -> Option<Instant> {
+    let value = value.to_str().ok()?.trim();
+    let now = Instant::now();
+    if let Ok(budget) = value.parse::<u64>() {
+        return Some(now + Duration::from_millis(budget));
+    }
+    let at = parse_rfc3339(value)?;
+    //a deadline in the past is exceeded immediately
+    let remaining = at
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO);
+    Some(now + remaining)
+}
+
+//RFC3339 (e.g. 2024-09-01T12:34:56.789Z, 2024-09-01T21:34:56+09:00) => SystemTime
+pub fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = value.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    //fraction of seconds
+    let mut rest = &value[19..];
+    let mut nanos: u32 = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+        let digits = &fraction[..len.min(9)];
+        nanos = digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32);
+        rest = &fraction[len..];
+    }
+
+    //offset
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours: i64 = rest[1..3].parse().ok()?;
+            let minutes: i64 = rest[4..6].parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+        _ => return None,
+    };
+
+    let seconds =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let seconds = u64::try_from(seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::new(seconds, nanos))
+}
+
+//days since 1970-01-01 (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,95 @@
+use std::{io::Read, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use crate::{body, budget, error::AppError, state::AppState};
+
+//Middleware
+//inflates gzip/deflate request bodies, aborting once the inflated size exceeds the cap
+//(DefaultBodyLimit only sees the compressed size). other codings get 415 with the
+//supported ones in `Accept-Encoding` (RFC 9110 12.5.3)
+pub async fn decompression_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let encoding = match request.headers().get(header::CONTENT_ENCODING) {
+        Some(encoding) => encoding.to_str().unwrap_or("").trim().to_ascii_lowercase(),
+        None => return Ok(next.run(request).await),
+    };
+    match encoding.as_str() {
+        "gzip" | "x-gzip" | "deflate" => {}
+        "identity" | "" => return Ok(next.run(request).await),
+        _ => return Ok(unsupported_encoding(&encoding)),
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let compressed = body::read_request_body(body, state.config.body_limit).await?;
+    let cap = state.config.max_decompressed_bytes;
+    let decoded = match encoding.as_str() {
+        "deflate" => inflate(DeflateDecoder::new(&compressed[..]), cap),
+        _ => inflate(GzDecoder::new(&compressed[..]), cap),
+    }?;
+    budget::Buffered::of(&parts.extensions).add(compressed.len() + decoded.len());
+    tracing::debug!(
+        "decompressed {} request body: {} => {} bytes",
+        encoding,
+        compressed.len(),
+        decoded.len()
+    );
+
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(decoded.len()));
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(decoded)))
+        .await)
+}
+
+fn unsupported_encoding(encoding: &str) -> Response {
+    let mut response = AppError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "UNSUPPORTED_CONTENT_ENCODING",
+        format!(
+            "unsupported Content-Encoding {:?}, expected gzip or deflate",
+            encoding
+        ),
+    )
+    .into_response();
+    response.headers_mut().insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static("gzip, deflate"),
+    );
+    response
+}
+
+fn inflate(decoder: impl Read, cap: usize) -> Result<Vec<u8>, AppError> {
+    let mut decoded = Vec::new();
+    //read one byte past the cap to detect the overflow without inflating everything
+    decoder
+        .take(cap as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|err| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_CONTENT_ENCODING",
+                format!("failed to decompress request body: {}", err),
+            )
+        })?;
+    if decoded.len() > cap {
+        return Err(AppError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "DECOMPRESSED_TOO_LARGE",
+            format!("decompressed request body exceeds {} bytes", cap),
+        ));
+    }
+    Ok(decoded)
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+
+use crate::{
+    auth, body, budget, context::ClientIp, error::AppError, response_limit, state::AppState, upload,
+};
+
+pub const X_DEDUPLICATED: &str = "x-deduplicated";
+
+//POSTs seen within DEDUPE_WINDOW_SECS, keyed by caller + URI + body hash
+#[derive(Debug)]
+pub struct DedupeStore {
+    window: Duration,
+    entries: Mutex<HashMap<[u8; 32], Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    expires_at: Instant,
+    slot: watch::Receiver<Slot>,
+}
+
+#[derive(Debug, Clone)]
+enum Slot {
+    //the first request is still running
+    Pending,
+    Done(StoredResponse),
+    //streamed response: the duplicate runs on its own
+    Skipped,
+}
+
+//a buffered response, handed out again for later requests
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl StoredResponse {
+    //`marker`: the header telling the client it got a stored response
+    pub fn into_response(self, marker: &'static str) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+            .headers_mut()
+            .insert(marker, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+impl DedupeStore {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //Err(receiver of the first request) for a duplicate, Ok(sender) for the first one
+    fn claim(&self, key: [u8; 32]) -> Result<watch::Sender<Slot>, watch::Receiver<Slot>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        if let Some(entry) = entries.get(&key) {
+            return Err(entry.slot.clone());
+        }
+        let (sender, receiver) = watch::channel(Slot::Pending);
+        entries.insert(
+            key,
+            Entry {
+                expires_at: now + self.window,
+                slot: receiver,
+            },
+        );
+        Ok(sender)
+    }
+}
+
+//Middleware
+//an identical POST (same caller, URI and body) within the window gets the first request's
+//response instead of running again, waiting for it if it is still in flight (double-clicks).
+//the caller is the token subject, API key or client IP; without any, nothing is deduplicated
+pub async fn dedupe_middleware(
+    State(state): State<Arc<AppState>>,
+    client_ip: Option<ClientIp>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if state.dedupe.window.is_zero()
+        || request.method() != Method::POST
+        || upload::is_multipart(request.headers())
+    {
+        return Ok(next.run(request).await);
+    }
+    let Some(caller) = auth::caller(&request, client_ip) else {
+        return Ok(next.run(request).await);
+    };
+    let (parts, body) = request.into_parts();
+    let bytes = body::read_request_body(body, state.config.body_limit).await?;
+    let buffered = budget::Buffered::of(&parts.extensions);
+    buffered.add(bytes.len());
+    let key: [u8; 32] = Sha256::new()
+        .chain_update(caller.as_bytes())
+        .chain_update([0])
+        .chain_update(parts.uri.to_string().as_bytes())
+        .chain_update([0])
+        .chain_update(&bytes)
+        .finalize()
+        .into();
+    let request = Request::from_parts(parts, Body::from(bytes));
+
+    let sender = match state.dedupe.claim(key) {
+        Ok(sender) => sender,
+        Err(mut receiver) => {
+            let slot = receiver
+                .wait_for(|slot| !matches!(slot, Slot::Pending))
+                .await
+                .map(|slot| slot.clone());
+            if let Ok(Slot::Done(stored)) = slot {
+                tracing::info!(
+                    caller = %caller,
+                    "deduplicated POST {}: returning the first response",
+                    request.uri()
+                );
+                return Ok(stored.into_response(X_DEDUPLICATED));
+            }
+            //the first request was dropped or streamed its response
+            return Ok(next.run(request).await);
+        }
+    };
+
+    let response = next.run(request).await;
+    if response.body().size_hint().exact().is_none() {
+        sender.send_replace(Slot::Skipped);
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(bytes.len());
+    sender.send_replace(Slot::Done(StoredResponse {
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: bytes.clone(),
+    }));
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
@@ -0,0 +1,69 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+
+use crate::state::AppState;
+
+//runtime switch that sheds optional work (body logging, Server-Timing) under load
+#[derive(Debug, Default)]
+pub struct DegradedMode(AtomicBool);
+
+#[derive(Debug, Serialize)]
+pub struct DegradedStatus {
+    pub degraded: bool,
+}
+
+impl DegradedMode {
+    pub fn new(enabled: bool) -> Self {
+        Self(AtomicBool::new(enabled))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    //returns the new value
+    pub fn toggle(&self) -> bool {
+        let enabled = !self.0.fetch_xor(true, Ordering::SeqCst);
+        tracing::warn!("degraded mode {}", if enabled { "on" } else { "off" });
+        enabled
+    }
+}
+
+//SIGUSR1 toggles degraded mode
+#[cfg(unix)]
+pub async fn watch_signal(state: Arc<AppState>) {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut signal = match signal(SignalKind::user_defined1()) {
+        Ok(signal) => signal,
+        Err(err) => {
+            tracing::warn!("cannot listen for SIGUSR1: {}", err);
+            return;
+        }
+    };
+    while signal.recv().await.is_some() {
+        state.degraded.toggle();
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn watch_signal(_state: Arc<AppState>) {}
+
+//Handler (dev only)
+//GET /_degraded => current value
+pub async fn status_handler(State(state): State<Arc<AppState>>) -> Json<DegradedStatus> {
+    Json(DegradedStatus {
+        degraded: state.degraded.is_enabled(),
+    })
+}
+
+//POST /_degraded => toggles the flag
+pub async fn toggle_handler(State(state): State<Arc<AppState>>) -> Json<DegradedStatus> {
+    Json(DegradedStatus {
+        degraded: state.degraded.toggle(),
+    })
+}
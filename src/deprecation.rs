@@ -0,0 +1,80 @@
+use std::{sync::Arc, time::SystemTime};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use utoipa::openapi::{Deprecated, OpenApi};
+
+use crate::{deadline, router, state::AppState};
+
+pub const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+//one DEPRECATED_ROUTES entry: `<route>` or `<route>=<RFC3339 sunset>`
+//(e.g. `/api/v1/sample/:path/raw=2027-01-01T00:00:00Z`)
+#[derive(Debug, Clone)]
+pub struct DeprecatedRoute {
+    pub path: String,
+    pub sunset: Option<SystemTime>,
+}
+
+impl DeprecatedRoute {
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (path, sunset) = match entry.split_once('=') {
+            Some((path, sunset)) => (path, Some(deadline::parse_rfc3339(sunset.trim())?)),
+            None => (entry, None),
+        };
+        let path = path.trim();
+        if !path.starts_with('/') {
+            return None;
+        }
+        Some(Self {
+            path: path.to_string(),
+            sunset,
+        })
+    }
+}
+
+//marks the operations of the deprecated routes in the generated spec
+pub fn mark_spec(openapi: &mut OpenApi, routes: &[DeprecatedRoute]) {
+    for route in routes {
+        match openapi
+            .paths
+            .paths
+            .get_mut(&router::openapi_path(&route.path))
+        {
+            Some(item) => {
+                for operation in item.operations.values_mut() {
+                    operation.deprecated = Some(Deprecated::True);
+                }
+            }
+            None => tracing::warn!("deprecated route {} is not in the API document", route.path),
+        }
+    }
+}
+
+//Middleware (route layer)
+//adds `Deprecation: true` and `Sunset` to responses from deprecated routes
+pub async fn deprecation_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = state
+        .route(&request)
+        .and_then(|route| route.deprecated.clone());
+    let mut response = next.run(request).await;
+    if let Some(route) = route {
+        let headers = response.headers_mut();
+        headers.insert(DEPRECATION, HeaderValue::from_static("true"));
+        if let Some(sunset) = route.sunset
+            && let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(sunset))
+        {
+            headers.insert(SUNSET, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,33 @@
+use axum::{
+    extract::{Path, rejection::PathRejection},
+    http::StatusCode,
+};
+
+use crate::error::AppError;
+
+//Handler (dev only)
+//GET /_error/:status => AppError with the requested status
+pub async fn error_handler(status: Result<Path<u16>, PathRejection>) -> AppError {
+    let status = match status {
+        Ok(Path(status)) => status,
+        Err(rejection) => {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_STATUS",
+                rejection.body_text(),
+            );
+        }
+    };
+    match StatusCode::from_u16(status) {
+        Ok(status) if (400..=599).contains(&status.as_u16()) => AppError::new(
+            status,
+            "TRIGGERED_ERROR",
+            format!("triggered {} error", status),
+        ),
+        _ => AppError::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_STATUS",
+            format!("status must be in 400..=599, got {}", status),
+        ),
+    }
+}
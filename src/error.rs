@@ -1,39 +1,232 @@
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header::InvalidHeaderValue},
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
 use serde_json::json;
 use utoipa::ToSchema;
 
+use crate::report::ReportedError;
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ResponseError {
+    code: String,
     message: String,
+    //JSON 本文のエラー位置 (malformed request bodies only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<ErrorLocation>,
+    //per-field problems (422 VALIDATION_FAILED only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<Vec<FieldError>>,
+    //X-Request-Id of the failed request (panics only, to quote in a bug report)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+//a request body field that failed validation (`field` is the JSON key)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+//position in the request body (line/column 1-based, offset 0-based bytes)
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct ErrorLocation {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+//error categories, each with its status and the generic code used when an error doesn't
+//name a more specific one (INVALID_CURSOR, BULKHEAD_FULL, ...). codes are part of the API:
+//clients match on them, so they never change once released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    BadRequest,
+    Validation,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RateLimited,
+    InsufficientStorage,
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn status(self) -> StatusCode {
+        match self {
+            Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Validation => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::BadRequest => "BAD_REQUEST",
+            Self::Validation => "VALIDATION_FAILED",
+            Self::Unauthorized => "UNAUTHORIZED",
+            Self::Forbidden => "FORBIDDEN",
+            Self::NotFound => "NOT_FOUND",
+            Self::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            Self::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            Self::RateLimited => "RATE_LIMITED",
+            Self::InsufficientStorage => "INSUFFICIENT_STORAGE",
+            Self::Internal => "INTERNAL_SERVER_ERROR",
+        }
+    }
+
+    //the category of an underlying error, from the first io / serde_json error in its chain
+    fn of(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+                return match err.kind() {
+                    std::io::ErrorKind::NotFound => Self::NotFound,
+                    std::io::ErrorKind::StorageFull
+                    | std::io::ErrorKind::QuotaExceeded
+                    | std::io::ErrorKind::FileTooLarge => Self::InsufficientStorage,
+                    _ => Self::Internal,
+                };
+            }
+            if let Some(err) = cause.downcast_ref::<serde_json::Error>() {
+                //syntax / data errors come from parsing a document (serializing our own
+                //types doesn't fail); io errors are ours
+                return if err.is_io() {
+                    Self::Internal
+                } else {
+                    Self::BadRequest
+                };
+            }
+        }
+        Self::Internal
+    }
 }
 
 #[derive(Debug)]
-pub struct AppError(anyhow::Error);
+pub struct AppError {
+    status: StatusCode,
+    code: &'static str,
+    error: anyhow::Error,
+    location: Option<ErrorLocation>,
+    fields: Option<Vec<FieldError>>,
+    request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            error: anyhow::Error::msg(message.into()),
+            location: None,
+            fields: None,
+            request_id: None,
+        }
+    }
+
+    //an error with its kind's generic code
+    pub fn of(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::new(kind.status(), kind.code(), message)
+    }
+
+    //422 listing every field that failed validation
+    pub fn validation(fields: Vec<FieldError>) -> Self {
+        let names: Vec<&str> = fields.iter().map(|field| field.field.as_str()).collect();
+        let mut err = Self::of(
+            ErrorKind::Validation,
+            format!("invalid fields: {}", names.join(", ")),
+        );
+        err.fields = Some(fields);
+        err
+    }
+
+    pub fn with_location(mut self, location: ErrorLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+}
+
+//anyhow::error => AppError への型変換 (`?` in handlers; the kind comes from the cause)
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        let kind = ErrorKind::of(&err);
+        Self {
+            status: kind.status(),
+            code: kind.code(),
+            error: err,
+            location: None,
+            fields: None,
+            request_id: None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
 
-//anyhow::error => AppError への型変換
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
+//building a response header from our own values
+impl From<InvalidHeaderValue> for AppError {
+    fn from(err: InvalidHeaderValue) -> Self {
+        anyhow::Error::from(err).into()
     }
 }
 
 //AppError => axum::response::Response への型変換
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        let message = self.error.to_string();
+        let mut response = (
+            self.status,
             Json(json!(ResponseError {
-                message: self.0.to_string(),
+                code: self.code.to_string(),
+                message: message.clone(),
+                location: self.location,
+                fields: self.fields,
+                request_id: self.request_id,
             })),
         )
-            .into_response()
+            .into_response();
+        //5xx はエラー報告ミドルウェアへ渡す
+        if self.status.is_server_error() {
+            response.extensions_mut().insert(ReportedError {
+                code: self.code,
+                message,
+            });
+        }
+        response
     }
 }
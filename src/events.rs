@@ -0,0 +1,141 @@
+use std::{
+    convert::Infallible,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use axum::{
+    extract::State,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{response::CacheControl, state::AppState};
+
+//slow subscribers lag (and get a `lagged` event) instead of holding requests back
+const EVENTS_CAPACITY: usize = 256;
+
+//how often an idle stream checks for shutdown
+const SHUTDOWN_POLL: Duration = Duration::from_secs(1);
+
+//one event of GET /events (the SSE event name is `type`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    //a handled request, published by access_log_middleware once the response head is ready
+    Request {
+        method: String,
+        path: String,
+        status: u16,
+        latency_ms: f64,
+        request_id: Option<String>,
+    },
+}
+
+impl ServerEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Request { .. } => "request",
+        }
+    }
+}
+
+pub fn channel() -> broadcast::Sender<ServerEvent> {
+    broadcast::channel(EVENTS_CAPACITY).0
+}
+
+//no-op without subscribers
+pub fn publish(state: &AppState, event: impl FnOnce() -> ServerEvent) {
+    if state.events.receiver_count() > 0 {
+        //the last subscriber may have just left
+        let _ = state.events.send(event());
+    }
+}
+
+//the stream outlives its handler, so it counts itself as in flight (like a WebSocket)
+struct Subscription {
+    receiver: broadcast::Receiver<ServerEvent>,
+    state: Arc<AppState>,
+    sent: u64,
+}
+
+impl Subscription {
+    fn new(state: Arc<AppState>) -> Self {
+        state.lifecycle.in_flight.fetch_add(1, Ordering::SeqCst);
+        tracing::info!("events subscriber joined");
+        Self {
+            receiver: state.events.subscribe(),
+            state,
+            sent: 0,
+        }
+    }
+}
+
+//dropped with the response body: the client went away or the stream ended
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.state
+            .lifecycle
+            .in_flight
+            .fetch_sub(1, Ordering::SeqCst);
+        tracing::info!(sent = self.sent, "events subscriber left");
+    }
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "Ops",
+    responses(
+        (status = 200, description = "text/event-stream of `request` events (a `lagged` event reports skipped ones), `:` keep-alive comments every EVENTS_KEEPALIVE_SECS", content_type = "text/event-stream"),
+    ),
+)]
+//EVENTS_ENABLED: streams ServerEvents until the client disconnects; ends when shutdown
+//starts so the drain doesn't wait for it
+pub async fn events_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let keepalive = state.config.events_keepalive;
+    let stream = futures_util::stream::unfold(Subscription::new(state), next_event);
+    (
+        CacheControl::NoStore,
+        Sse::new(stream).keep_alive(KeepAlive::new().interval(keepalive)),
+    )
+}
+
+//the next SSE event, None to end the stream
+async fn next_event(
+    mut subscription: Subscription,
+) -> Option<(Result<Event, Infallible>, Subscription)> {
+    let event = loop {
+        if subscription
+            .state
+            .lifecycle
+            .shutting_down
+            .load(Ordering::SeqCst)
+        {
+            return None;
+        }
+        match tokio::time::timeout(SHUTDOWN_POLL, subscription.receiver.recv()).await {
+            Err(_) => continue,
+            Ok(Ok(event)) => {
+                break Event::default()
+                    .event(event.name())
+                    .json_data(&event)
+                    .unwrap_or_default();
+            }
+            Ok(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                tracing::warn!("events subscriber lagged, {} events skipped", skipped);
+                break Event::default()
+                    .event("lagged")
+                    .data(format!("{{\"skipped\":{}}}", skipped));
+            }
+            Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+        }
+    };
+    subscription.sent += 1;
+    Some((Ok(event), subscription))
+}
@@ -0,0 +1,280 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    Form, Json, async_trait,
+    body::Bytes,
+    extract::{
+        FromRef, FromRequest, FromRequestParts, Path, Query, Request, path::ErrorKind,
+        rejection::PathRejection,
+    },
+    http::{StatusCode, header, request::Parts},
+};
+use percent_encoding::percent_decode;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    body,
+    error::{AppError, FieldError},
+    json,
+    scope::RequestScope,
+    state::AppState,
+    timing::ServerTiming,
+};
+
+//validation hook for extracted values
+pub trait Validate {
+    fn validate(&self) -> Result<(), String>;
+}
+
+//validation hook for request bodies: every failing field (empty: valid)
+pub trait ValidateFields {
+    fn validate_fields(&self) -> Vec<FieldError>;
+}
+
+//Query<T> that rejects percent-encoded sequences which are not valid UTF-8
+//(axum's Query silently replaces them with U+FFFD)
+#[derive(Debug)]
+pub struct StrictQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for StrictQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let raw = parts.uri.query().unwrap_or("");
+        for pair in raw.split('&') {
+            for component in pair.splitn(2, '=') {
+                let component = component.replace('+', " ");
+                if percent_decode(component.as_bytes()).decode_utf8().is_err() {
+                    return Err(AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        "INVALID_QUERY_ENCODING",
+                        format!("query component {:?} is not valid UTF-8", component),
+                    ));
+                }
+            }
+        }
+        let Query(query) = Query::<T>::try_from_uri(&parts.uri).map_err(|rejection| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_QUERY",
+                rejection.body_text(),
+            )
+        })?;
+        Ok(Self(query))
+    }
+}
+
+//Path<T> + T::validate() (both failures => 400 BAD_PATH)
+#[derive(Debug)]
+pub struct ValidatedPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedPath<T>
+where
+    T: DeserializeOwned + Validate + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(path) = Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(path_rejection)?;
+        path.validate()
+            .map_err(|message| AppError::new(StatusCode::BAD_REQUEST, "BAD_PATH", message))?;
+        Ok(Self(path))
+    }
+}
+
+//an integer that doesn't fit the parameter type => PATH_OUT_OF_RANGE, anything else => BAD_PATH
+fn path_rejection(rejection: PathRejection) -> AppError {
+    if let PathRejection::FailedToDeserializePathParams(err) = &rejection
+        && let ErrorKind::ParseErrorAtKey {
+            key,
+            value,
+            expected_type,
+        } = err.kind()
+        && is_integer_type(expected_type)
+        && is_integer_literal(value)
+    {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            "PATH_OUT_OF_RANGE",
+            format!("{} {} is out of range for {}", key, value, expected_type),
+        );
+    }
+    AppError::new(StatusCode::BAD_REQUEST, "BAD_PATH", rejection.body_text())
+}
+
+fn is_integer_type(name: &str) -> bool {
+    matches!(
+        name,
+        "i8" | "i16"
+            | "i32"
+            | "i64"
+            | "i128"
+            | "isize"
+            | "u8"
+            | "u16"
+            | "u32"
+            | "u64"
+            | "u128"
+            | "usize"
+    )
+}
+
+fn is_integer_literal(value: &str) -> bool {
+    let digits = value.strip_prefix(['-', '+']).unwrap_or(value);
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+//runs the inner extractor and records its duration as the `parse` Server-Timing phase
+#[derive(Debug)]
+pub struct Timed<E>(pub E);
+
+#[async_trait]
+impl<E, S> FromRequest<S> for Timed<E>
+where
+    E: FromRequest<S>,
+    S: Send + Sync,
+{
+    type Rejection = E::Rejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let scope = request.extensions().get::<RequestScope>().cloned();
+        let start = Instant::now();
+        let result = E::from_request(request, state).await;
+        if let Some(scope) = scope {
+            ServerTiming::record(&scope, "parse", start.elapsed());
+        }
+        Ok(Self(result?))
+    }
+}
+
+//body deserialized according to Content-Type
+//(application/json, application/x-www-form-urlencoded)
+#[derive(Debug)]
+pub struct Payload<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Payload<T>
+where
+    T: DeserializeOwned,
+    Arc<AppState>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.parse::<mime::Mime>().ok());
+        let Some(content_type) = content_type else {
+            return Err(unsupported_media_type("missing Content-Type"));
+        };
+        let app_state = Arc::<AppState>::from_ref(state);
+        let limit = app_state.config.body_limit;
+
+        if content_type.essence_str() == mime::APPLICATION_JSON.essence_str()
+            || content_type.suffix() == Some(mime::JSON)
+        {
+            let bytes = Bytes::from_request(request, state)
+                .await
+                .map_err(|rejection| {
+                    body_rejection(&rejection, rejection.status(), rejection.body_text(), limit)
+                })?;
+            json::check_complexity(&bytes, app_state.config.json_max_depth).map_err(|reason| {
+                AppError::new(StatusCode::BAD_REQUEST, "JSON_TOO_COMPLEX", reason)
+            })?;
+            if app_state.config.strict_json {
+                json::check_duplicate_keys(&bytes).map_err(|reason| {
+                    AppError::new(StatusCode::BAD_REQUEST, "DUPLICATE_JSON_KEY", reason)
+                })?;
+            }
+            let Json(payload) = Json::<T>::from_bytes(&bytes).map_err(|rejection| {
+                let err = invalid_body(rejection.status(), rejection.body_text());
+                match serde_json::from_slice::<T>(&bytes) {
+                    Err(json_err) => err.with_location(json::error_location(&bytes, &json_err)),
+                    Ok(_) => err,
+                }
+            })?;
+            Ok(Self(payload))
+        } else if content_type.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str()
+        {
+            let Form(payload) =
+                Form::<T>::from_request(request, state)
+                    .await
+                    .map_err(|rejection| {
+                        body_rejection(&rejection, rejection.status(), rejection.body_text(), limit)
+                    })?;
+            Ok(Self(payload))
+        } else {
+            Err(unsupported_media_type(&format!(
+                "unsupported Content-Type {:?}",
+                content_type.essence_str()
+            )))
+        }
+    }
+}
+
+//Payload<T> + T::validate_fields() (422 VALIDATION_FAILED listing the fields). the body
+//must parse first: malformed JSON or a missing field is still 400 INVALID_BODY
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + ValidateFields,
+    Arc<AppState>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Payload(payload) = Payload::<T>::from_request(request, state).await?;
+        let errors = payload.validate_fields();
+        if !errors.is_empty() {
+            return Err(AppError::validation(errors));
+        }
+        Ok(Self(payload))
+    }
+}
+
+fn invalid_body(status: StatusCode, message: String) -> AppError {
+    AppError::new(status, "INVALID_BODY", message)
+}
+
+//a failed body read in an axum extractor => the errors of body::read_request_body
+//(408 for a slow body, 413 PAYLOAD_TOO_LARGE past the limit), else 4xx INVALID_BODY
+fn body_rejection(
+    rejection: &(dyn std::error::Error + 'static),
+    status: StatusCode,
+    message: String,
+    limit: usize,
+) -> AppError {
+    if let Some(slow) = body::slow_body_error(rejection) {
+        slow
+    } else if status == StatusCode::PAYLOAD_TOO_LARGE {
+        body::too_large(limit)
+    } else {
+        invalid_body(status, message)
+    }
+}
+
+fn unsupported_media_type(message: &str) -> AppError {
+    AppError::of(
+        crate::error::ErrorKind::UnsupportedMediaType,
+        format!(
+            "{}, expected application/json or application/x-www-form-urlencoded",
+            message
+        ),
+    )
+}
@@ -0,0 +1,236 @@
+use std::{io::SeekFrom, ops::RangeInclusive, path::Path, sync::Arc};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{self, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{
+    error::{AppError, ErrorKind},
+    response::CacheControl,
+    state::AppState,
+};
+
+const CHUNK_BYTES: usize = 64 * 1024;
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/files/{name}",
+    tag = "Sample",
+    params(
+        ("name" = String, Path, description = "file name in FILES_DIR (e.g. the id from POST /upload)"),
+        ("Range" = Option<String>, Header, description = "one byte range (`bytes=0-99`, `bytes=100-`, `bytes=-100`)"),),
+    responses(
+        (status = 200, description = "the file, as an attachment", content_type = "application/octet-stream"),
+        (status = 206, description = "the requested range (Content-Range set)", content_type = "application/octet-stream"),
+        (status = 400, description = "invalid file name (e.g. path traversal)", body = ResponseError),
+        (status = 404, description = "Not Found", body = ResponseError),
+        (status = 416, description = "range outside the file", body = ResponseError),
+    ),
+)]
+//streams a file from FILES_DIR as `Content-Disposition: attachment`. a multi-range
+//request gets the whole file (allowed by RFC 9110)
+pub async fn download_handler(
+    State(state): State<Arc<AppState>>,
+    extract::Path(name): extract::Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    check_name(&name)?;
+    let dir = &state.config.files_dir;
+    let path = dir.join(&name);
+    //a symlink must not lead out of FILES_DIR either
+    let resolved = match (tokio::fs::canonicalize(&path).await, dir.canonicalize()) {
+        (Ok(resolved), Ok(dir)) if resolved.starts_with(&dir) => resolved,
+        (Ok(_), Ok(_)) => return Err(invalid_name(&name)),
+        _ => return Err(not_found(&name)),
+    };
+    let mut file = tokio::fs::File::open(&resolved)
+        .await
+        .map_err(|_| not_found(&name))?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(not_found(&name));
+    }
+    let size = metadata.len();
+    let range = match headers
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok())
+    {
+        Some(range) => match parse_range(range, size) {
+            Ok(range) => range,
+            Err(()) => return Ok(unsatisfiable(size)),
+        },
+        None => None,
+    };
+
+    let content_type = mime_guess::from_path(&name).first_or_octet_stream();
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(content_type.as_ref())?,
+    );
+    response_headers.insert(header::CONTENT_DISPOSITION, attachment(&name)?);
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(modified) = metadata.modified() {
+        response_headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(modified))?,
+        );
+    }
+    let (status, start, length) = match range {
+        Some(range) => {
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!(
+                    "bytes {}-{}/{}",
+                    range.start(),
+                    range.end(),
+                    size
+                ))?,
+            );
+            let length = range.end() - range.start() + 1;
+            (StatusCode::PARTIAL_CONTENT, *range.start(), length)
+        }
+        None => (StatusCode::OK, 0, size),
+    };
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    file.seek(SeekFrom::Start(start)).await?;
+    tracing::debug!("serving {} ({} of {} bytes)", name, length, size);
+    Ok((
+        status,
+        CacheControl::NoStore,
+        response_headers,
+        Body::from_stream(read_chunks(file, length)),
+    )
+        .into_response())
+}
+
+//`length` bytes from the current position, CHUNK_BYTES at a time
+fn read_chunks(
+    file: tokio::fs::File,
+    length: u64,
+) -> impl futures_util::Stream<Item = Result<Bytes, std::io::Error>> {
+    futures_util::stream::unfold((file, length), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+        let mut chunk = vec![0; CHUNK_BYTES.min(remaining as usize)];
+        match file.read(&mut chunk).await {
+            Ok(0) => {
+                tracing::warn!("file shrank while it was being served");
+                Some((Err(std::io::ErrorKind::UnexpectedEof.into()), (file, 0)))
+            }
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((Ok(Bytes::from(chunk)), (file, remaining - read as u64)))
+            }
+            Err(err) => Some((Err(err), (file, 0))),
+        }
+    })
+}
+
+//a plain file name: no separators, no `..`, no hidden files (Path decodes `%2F`)
+fn check_name(name: &str) -> Result<(), AppError> {
+    let plain = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\', '\0'])
+        && Path::new(name).file_name().is_some_and(|file| file == name);
+    if plain {
+        Ok(())
+    } else {
+        Err(invalid_name(name))
+    }
+}
+
+//`attachment; filename="..."`, plus `filename*` (RFC 6266) for non-ASCII names
+fn attachment(name: &str) -> Result<HeaderValue, AppError> {
+    let ascii: String = name
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let mut value = format!("attachment; filename=\"{}\"", ascii);
+    if ascii != name {
+        value.push_str(&format!(
+            "; filename*=UTF-8''{}",
+            utf8_percent_encode(name, NON_ALPHANUMERIC)
+        ));
+    }
+    Ok(HeaderValue::from_str(&value)?)
+}
+
+//Ok(None): not a single byte range, serve the whole file. Err: unsatisfiable
+fn parse_range(value: &str, size: u64) -> Result<Option<RangeInclusive<u64>>, ()> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let Ok(suffix) = suffix.parse::<u64>() else {
+                return Ok(None);
+            };
+            if suffix == 0 || size == 0 {
+                return Err(());
+            }
+            size.saturating_sub(suffix)..=size - 1
+        }
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return Ok(None);
+            };
+            let end = match end {
+                "" => u64::MAX,
+                end => match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return Ok(None),
+                },
+            };
+            if start >= size {
+                return Err(());
+            }
+            start..=end.min(size - 1)
+        }
+    };
+    Ok(Some(range))
+}
+
+fn invalid_name(name: &str) -> AppError {
+    AppError::new(
+        StatusCode::BAD_REQUEST,
+        "INVALID_FILE_NAME",
+        format!("invalid file name {:?}", name),
+    )
+}
+
+fn not_found(name: &str) -> AppError {
+    AppError::of(ErrorKind::NotFound, format!("no file named {:?}", name))
+}
+
+fn unsatisfiable(size: u64) -> Response {
+    let mut response = AppError::new(
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        "RANGE_NOT_SATISFIABLE",
+        format!("the file has {} bytes", size),
+    )
+    .into_response();
+    if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size)) {
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, StatusCode, Version, header},
+    middleware::Next,
+    response::Response,
+};
+
+use percent_encoding::percent_decode;
+
+use crate::{
+    error::{AppError, ErrorKind},
+    state::AppState,
+};
+
+//headers that must appear at most once (using the first value hides the ambiguity)
+const SINGLE_VALUE_HEADERS: [HeaderName; 2] = [header::AUTHORIZATION, header::CONTENT_TYPE];
+
+//Middleware
+pub async fn duplicate_header_middleware(
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    for name in SINGLE_VALUE_HEADERS.iter() {
+        if request.headers().get_all(name).iter().count() > 1 {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "DUPLICATE_HEADER",
+                format!("duplicate {} header", name),
+            ));
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+//Middleware
+//rejects a declared Content-Length over the body limit before anything reads the body.
+//hyper only sends `100 Continue` once the body is first polled, so a client waiting on
+//`Expect: 100-continue` receives this final 413 instead and never uploads the body.
+pub async fn content_length_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let limit = state.config.body_limit;
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit as u64) {
+        return Err(AppError::of(
+            ErrorKind::PayloadTooLarge,
+            format!("request body exceeds {} bytes", limit),
+        ));
+    }
+    Ok(next.run(request).await)
+}
+
+//Middleware
+//request bodies must be UTF-8 (the JSON default); a missing charset means UTF-8
+pub async fn charset_middleware(request: Request, next: Next) -> Result<Response, AppError> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<mime::Mime>().ok());
+    if let Some(charset) = content_type
+        .as_ref()
+        .and_then(|content_type| content_type.get_param(mime::CHARSET))
+    {
+        let charset = charset.as_str().to_ascii_lowercase();
+        if charset != "utf-8" && charset != "utf8" {
+            return Err(AppError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "UNSUPPORTED_CHARSET",
+                format!("unsupported charset {:?}, only utf-8 is accepted", charset),
+            ));
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+//Middleware
+//HTTP/1.1 requires exactly one Host header (HTTP/2 carries :authority instead)
+pub async fn host_middleware(request: Request, next: Next) -> Result<Response, AppError> {
+    let hosts = request.headers().get_all(header::HOST).iter().count();
+    if hosts > 1 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "MISSING_HOST",
+            "multiple Host headers",
+        ));
+    }
+    if hosts == 0 && request.version() == Version::HTTP_11 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "MISSING_HOST",
+            "HTTP/1.1 request without a Host header",
+        ));
+    }
+    Ok(next.run(request).await)
+}
+
+//Middleware
+//a single oversized value (e.g. a giant cookie) => 431 naming the header
+pub async fn header_value_size_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let max = state.config.max_header_value_bytes;
+    if let Some((name, value)) = request
+        .headers()
+        .iter()
+        .find(|(_, value)| value.len() > max)
+    {
+        return Err(AppError::new(
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "HEADER_VALUE_TOO_LARGE",
+            format!(
+                "{} header value is {} bytes, limit is {}",
+                name,
+                value.len(),
+                max
+            ),
+        ));
+    }
+    Ok(next.run(request).await)
+}
+
+//Middleware
+//Transfer-Encoding together with Content-Length is rejected (RFC 9112 §6.3 allows a
+//server to treat it as an error). hyper frames such a body by Transfer-Encoding, but a
+//proxy in front of us may have framed it by Content-Length; when the two disagree the
+//leftover bytes are parsed as a second, smuggled request.
+//limitation: hyper's h1 parser drops a Content-Length that comes after Transfer-Encoding
+//(it never reaches the header map), so only `Content-Length` before `Transfer-Encoding`
+//is rejected here. the other order is still framed by Transfer-Encoding alone, which
+//is safe for this server but not behind a proxy that frames by Content-Length
+pub async fn ambiguous_length_middleware(
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let headers = request.headers();
+    if headers.contains_key(header::TRANSFER_ENCODING)
+        && headers.contains_key(header::CONTENT_LENGTH)
+    {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "AMBIGUOUS_LENGTH",
+            "Transfer-Encoding and Content-Length must not be sent together",
+        ));
+    }
+    Ok(next.run(request).await)
+}
+
+//Middleware
+//a NUL byte (`%00`) anywhere in the decoded path or query => 400
+pub async fn null_byte_middleware(request: Request, next: Next) -> Result<Response, AppError> {
+    let uri = request.uri();
+    let has_null = |part: &str| percent_decode(part.as_bytes()).any(|byte| byte == 0);
+    if has_null(uri.path()) || uri.query().is_some_and(has_null) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_URL",
+            "the URL must not contain null bytes",
+        ));
+    }
+    Ok(next.run(request).await)
+}
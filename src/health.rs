@@ -0,0 +1,251 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, async_trait,
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    config::Config, context, degraded::DegradedMode, lifecycle::Lifecycle, message::Repository,
+    response::CacheControl, state::AppState,
+};
+
+//a check slower than this counts as failing
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+//one dependency of GET /readyz. a failing critical check makes the instance unready
+//(503); the others only degrade the report
+#[async_trait]
+pub trait HealthCheck: Send + Sync + fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    //Err: what is wrong, for the report
+    async fn check(&self) -> Result<(), String>;
+}
+
+//the registered readiness checks
+#[derive(Debug, Default)]
+pub struct HealthChecks(Vec<Arc<dyn HealthCheck>>);
+
+impl HealthChecks {
+    //the checks of this template; a deployment adds its own (a database ping, ...) here
+    pub fn standard(
+        config: &Config,
+        lifecycle: Arc<Lifecycle>,
+        messages: Arc<dyn Repository>,
+        degraded: Arc<DegradedMode>,
+    ) -> Self {
+        Self::default()
+            .register(Accepting(lifecycle))
+            .register(MessageStore(messages))
+            .register(DirWritable {
+                name: "upload_dir",
+                dir: config.upload_dir.clone(),
+            })
+            .register(NotDegraded(degraded))
+    }
+
+    pub fn register(mut self, check: impl HealthCheck + 'static) -> Self {
+        self.0.push(Arc::new(check));
+        self
+    }
+
+    //runs every check concurrently, each bounded by CHECK_TIMEOUT
+    pub async fn run(&self) -> Vec<CheckResult> {
+        futures_util::future::join_all(self.0.iter().map(|check| async move {
+            let started_at = Instant::now();
+            let result = match tokio::time::timeout(CHECK_TIMEOUT, check.check()).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("no answer within {:?}", CHECK_TIMEOUT)),
+            };
+            CheckResult {
+                name: check.name(),
+                critical: check.critical(),
+                ok: result.is_ok(),
+                error: result.err(),
+                duration_ms: started_at.elapsed().as_secs_f64() * 1000.0,
+            }
+        }))
+        .await
+    }
+}
+
+//not draining for shutdown (the load balancer should stop sending requests)
+#[derive(Debug)]
+struct Accepting(Arc<Lifecycle>);
+
+#[async_trait]
+impl HealthCheck for Accepting {
+    fn name(&self) -> &'static str {
+        "accepting"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        if self.0.shutting_down.load(Ordering::SeqCst) {
+            return Err("shutting down".to_string());
+        }
+        Ok(())
+    }
+}
+
+//MESSAGE_STORE is usable
+#[derive(Debug)]
+struct MessageStore(Arc<dyn Repository>);
+
+#[async_trait]
+impl HealthCheck for MessageStore {
+    fn name(&self) -> &'static str {
+        "message_store"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.health().map_err(|err| err.to_string())
+    }
+}
+
+//a file can be created (and removed again) in the directory
+#[derive(Debug)]
+struct DirWritable {
+    name: &'static str,
+    dir: PathBuf,
+}
+
+#[async_trait]
+impl HealthCheck for DirWritable {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let probe = self
+            .dir
+            .join(format!(".readyz-{}", context::generate_request_id()));
+        let result = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(&probe, b"").await?;
+            tokio::fs::remove_file(&probe).await
+        }
+        .await;
+        result.map_err(|err| format!("{} is not writable: {}", self.dir.display(), err))
+    }
+}
+
+//DEGRADED_MODE is off (non-critical: the optional work is skipped, requests still succeed)
+#[derive(Debug)]
+struct NotDegraded(Arc<DegradedMode>);
+
+#[async_trait]
+impl HealthCheck for NotDegraded {
+    fn name(&self) -> &'static str {
+        "degraded_mode"
+    }
+
+    fn critical(&self) -> bool {
+        false
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        if self.0.is_enabled() {
+            return Err("degraded mode is on".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckResult {
+    #[schema(value_type = String)]
+    pub name: &'static str,
+    pub critical: bool,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    //ready | degraded (a non-critical check failed) | unavailable
+    #[schema(value_type = String)]
+    pub status: &'static str,
+    pub checks: Vec<CheckResult>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Liveness {
+    #[schema(value_type = String)]
+    pub status: &'static str,
+    pub uptime_secs: u64,
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "Ops",
+    responses(
+        (status = 200, description = "the process is serving requests", body = Liveness),
+    ),
+)]
+//liveness: no dependency checks, a restart wouldn't fix a dependency
+pub async fn liveness_handler(State(state): State<Arc<AppState>>) -> Response {
+    let liveness = Liveness {
+        status: "alive",
+        uptime_secs: state.lifecycle.started_at.elapsed().as_secs(),
+    };
+    (StatusCode::OK, CacheControl::NoStore, Json(liveness)).into_response()
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "Ops",
+    responses(
+        (status = 200, description = "ready (or degraded: only non-critical checks fail)", body = Readiness),
+        (status = 503, description = "a critical check fails (Retry-After: READINESS_RETRY_AFTER_SECS)", body = Readiness),
+    ),
+)]
+pub async fn readiness_handler(State(state): State<Arc<AppState>>) -> Response {
+    let checks = state.health.run().await;
+    let (status, code) = if checks.iter().any(|check| check.critical && !check.ok) {
+        ("unavailable", StatusCode::SERVICE_UNAVAILABLE)
+    } else if checks.iter().any(|check| !check.ok) {
+        ("degraded", StatusCode::OK)
+    } else {
+        ("ready", StatusCode::OK)
+    };
+    for check in checks.iter().filter(|check| !check.ok) {
+        tracing::warn!(
+            "readiness check {} failed: {}",
+            check.name,
+            check.error.as_deref().unwrap_or("")
+        );
+    }
+    let mut response = (
+        code,
+        CacheControl::NoStore,
+        Json(Readiness { status, checks }),
+    )
+        .into_response();
+    if code == StatusCode::SERVICE_UNAVAILABLE {
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(state.config.readiness_retry_after.as_secs().max(1)),
+        );
+    }
+    response
+}
@@ -0,0 +1,68 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+
+use crate::{error::AppError, server::TlsConnection, state::AppState};
+
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+//Middleware
+//REQUIRE_HTTPS=true: plain HTTP GET/HEAD => 308 to the https URL, other methods => 400.
+//requests over our own TLS are https; X-Forwarded-Proto is only believed when the peer
+//is one of TRUSTED_PROXIES
+pub async fn require_https_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !state.config.require_https || is_https(&state, &request) {
+        return Ok(next.run(request).await);
+    }
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "HTTPS_REQUIRED",
+            format!("{} requests must use https", request.method()),
+        ));
+    }
+    //host_middleware has already rejected requests without a Host
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .ok_or_else(|| {
+            AppError::new(StatusCode::BAD_REQUEST, "HTTPS_REQUIRED", "https required")
+        })?;
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    Ok(Redirect::permanent(&format!("https://{}{}", host, path)).into_response())
+}
+
+pub fn is_https(state: &AppState, request: &Request) -> bool {
+    if request.extensions().get::<TlsConnection>().is_some() {
+        return true;
+    }
+    let trusted = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(peer)| state.config.trusted_proxies.contains(&peer.ip()));
+    if !trusted {
+        return false;
+    }
+    //the proxy closest to us appends last
+    request
+        .headers()
+        .get_all(X_FORWARDED_PROTO)
+        .iter()
+        .filter_map(|proto| proto.to_str().ok())
+        .flat_map(|proto| proto.split(','))
+        .next_back()
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
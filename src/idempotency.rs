@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    auth, body, budget, context::ClientIp, dedupe::StoredResponse, error::AppError, response_limit,
+    state::AppState,
+};
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+//longest accepted key (a UUID is 36)
+const MAX_KEY_LEN: usize = 255;
+
+//responses a retry should run again for instead of getting them replayed
+const RETRYABLE: [StatusCode; 4] = [
+    StatusCode::REQUEST_TIMEOUT,
+    StatusCode::CONFLICT,
+    StatusCode::TOO_EARLY,
+    StatusCode::TOO_MANY_REQUESTS,
+];
+
+//the responses of POSTs with an Idempotency-Key for IDEMPOTENCY_TTL_SECS, keyed by
+//caller + method + path + key
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<[u8; 32], Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    expires_at: Instant,
+    //hash of the request body: a retry has to send the same one
+    fingerprint: [u8; 32],
+    //None while the first request is running
+    response: Option<StoredResponse>,
+}
+
+enum Claim {
+    //the first request with the key: run it, then complete (or drop) the claim
+    First(ClaimGuard),
+    Replay(StoredResponse),
+    InFlight,
+    Mismatch,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn claim(self: &Arc<Self>, key: [u8; 32], fingerprint: [u8; 32]) -> Claim {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        if let Some(entry) = entries.get(&key) {
+            return if entry.fingerprint != fingerprint {
+                Claim::Mismatch
+            } else {
+                match &entry.response {
+                    Some(response) => Claim::Replay(response.clone()),
+                    None => Claim::InFlight,
+                }
+            };
+        }
+        entries.insert(
+            key,
+            Entry {
+                expires_at: now + self.ttl,
+                fingerprint,
+                response: None,
+            },
+        );
+        Claim::First(ClaimGuard {
+            store: self.clone(),
+            key,
+            done: false,
+        })
+    }
+}
+
+//releases the key unless a response was stored, so a retry can run again after the
+//first request failed, streamed its response or was dropped (client gone)
+struct ClaimGuard {
+    store: Arc<IdempotencyStore>,
+    key: [u8; 32],
+    done: bool,
+}
+
+impl ClaimGuard {
+    fn complete(mut self, response: StoredResponse) {
+        if let Some(entry) = self.store.entries.lock().unwrap().get_mut(&self.key) {
+            entry.response = Some(response);
+        }
+        self.done = true;
+    }
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        if !self.done {
+            self.store.entries.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+//Middleware (route layer of the `idempotent` stack)
+//a POST with an `Idempotency-Key` runs once per key: a retry with the same key and body
+//gets the stored response (marked `Idempotent-Replayed: true`), one while the first is
+//still running a 409, one with another body a 422. 5xx and retryable responses aren't
+//stored. keys are per caller (token subject, API key or client IP)
+pub async fn idempotency_middleware(
+    State(state): State<Arc<AppState>>,
+    client_ip: Option<ClientIp>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let store = &state.idempotency;
+    if store.ttl.is_zero() || request.method() != Method::POST {
+        return Ok(next.run(request).await);
+    }
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_IDEMPOTENCY_KEY",
+                format!(
+                    "Idempotency-Key must be 1-{} visible ASCII characters",
+                    MAX_KEY_LEN
+                ),
+            )
+        })?
+        .to_string();
+    let caller = auth::caller(&request, client_ip).unwrap_or_default();
+    //the full path: a nested tree sees its own without the prefix
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(request.uri().path(), |uri| uri.path())
+        .to_string();
+    let (parts, body) = request.into_parts();
+    let bytes = body::read_request_body(body, state.config.body_limit).await?;
+    let buffered = budget::Buffered::of(&parts.extensions);
+    buffered.add(bytes.len());
+    let scope: [u8; 32] = Sha256::new()
+        .chain_update(caller.as_bytes())
+        .chain_update([0])
+        .chain_update(parts.method.as_str().as_bytes())
+        .chain_update([0])
+        .chain_update(path.as_bytes())
+        .chain_update([0])
+        .chain_update(key.as_bytes())
+        .finalize()
+        .into();
+    let fingerprint: [u8; 32] = Sha256::digest(&bytes).into();
+    let request = Request::from_parts(parts, Body::from(bytes));
+
+    let guard = match store.claim(scope, fingerprint) {
+        Claim::First(guard) => guard,
+        Claim::Replay(stored) => {
+            tracing::info!("POST {} with Idempotency-Key {}: replaying", path, key);
+            return Ok(stored.into_response(IDEMPOTENT_REPLAYED));
+        }
+        Claim::InFlight => {
+            return Err(AppError::new(
+                StatusCode::CONFLICT,
+                "IDEMPOTENCY_KEY_IN_USE",
+                "a request with this Idempotency-Key is still being processed",
+            ));
+        }
+        Claim::Mismatch => {
+            return Err(AppError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "IDEMPOTENCY_KEY_REUSED",
+                "this Idempotency-Key was used for a request with another body",
+            ));
+        }
+    };
+
+    let response = next.run(request).await;
+    let status = response.status();
+    if status.is_server_error()
+        || RETRYABLE.contains(&status)
+        || response.body().size_hint().exact().is_none()
+    {
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(bytes.len());
+    guard.complete(StoredResponse {
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: bytes.clone(),
+    });
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
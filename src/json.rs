@@ -0,0 +1,210 @@
+//pre-parse checks run before serde_json (which has no depth/number limits of its own
+//besides its fixed recursion limit)
+
+use std::{borrow::Cow, collections::HashSet, fmt};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{MapAccess, SeqAccess, Visitor},
+};
+
+use crate::error::ErrorLocation;
+
+//largest integer exactly representable as f64 / in JavaScript
+pub const MAX_SAFE_NUMBER: f64 = 9_007_199_254_740_991.0;
+
+//Err(reason) when the document nests deeper than max_depth or contains a number
+//outside ±MAX_SAFE_NUMBER
+pub fn check_complexity(bytes: &[u8], max_depth: usize) -> Result<(), String> {
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            index += 1;
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(format!("nesting depth exceeds {}", max_depth));
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b'-' | b'0'..=b'9' => {
+                let start = index;
+                while index < bytes.len()
+                    && matches!(bytes[index], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                {
+                    index += 1;
+                }
+                let token = std::str::from_utf8(&bytes[start..index]).unwrap_or("");
+                //malformed numbers are left to serde_json
+                if let Ok(number) = token.parse::<f64>()
+                    && !(-MAX_SAFE_NUMBER..=MAX_SAFE_NUMBER).contains(&number)
+                {
+                    return Err(format!("number {} is outside the safe range", token));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+//where serde_json gave up on the document (line/column as reported, plus the byte offset)
+pub fn error_location(bytes: &[u8], err: &serde_json::Error) -> ErrorLocation {
+    let line_start: usize = bytes
+        .split(|byte| *byte == b'\n')
+        .take(err.line().saturating_sub(1))
+        .map(|line| line.len() + 1)
+        .sum();
+    ErrorLocation {
+        line: err.line(),
+        column: err.column(),
+        offset: (line_start + err.column().saturating_sub(1)).min(bytes.len()),
+    }
+}
+
+//Err(key) on the first object with a repeated key (serde_json keeps the last value)
+//malformed documents pass: serde_json reports them afterwards
+pub fn check_duplicate_keys(bytes: &[u8]) -> Result<(), String> {
+    match serde_json::from_slice::<UniqueKeys>(bytes) {
+        Ok(UniqueKeys(Some(key))) => Err(format!("duplicate key {:?}", key)),
+        _ => Ok(()),
+    }
+}
+
+//walks any JSON value, holding the first repeated object key found
+struct UniqueKeys(Option<String>);
+
+impl<'de> Deserialize<'de> for UniqueKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UniqueKeysVisitor)
+    }
+}
+
+struct UniqueKeysVisitor;
+
+impl<'de> Visitor<'de> for UniqueKeysVisitor {
+    type Value = UniqueKeys;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys(None))
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys(None))
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys(None))
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys(None))
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys(None))
+    }
+
+    fn visit_unit<E>(self) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys(None))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UniqueKeys, A::Error> {
+        let mut duplicate = None;
+        while let Some(UniqueKeys(found)) = seq.next_element()? {
+            duplicate = duplicate.or(found);
+        }
+        Ok(UniqueKeys(duplicate))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueKeys, A::Error> {
+        let mut keys = HashSet::new();
+        let mut duplicate = None;
+        while let Some(key) = map.next_key::<Cow<'de, str>>()? {
+            let UniqueKeys(found) = map.next_value()?;
+            if !keys.insert(key.clone()) {
+                duplicate = duplicate.or(Some(key.into_owned()));
+            }
+            duplicate = duplicate.or(found);
+        }
+        Ok(UniqueKeys(duplicate))
+    }
+}
+
+//re-indents a compact JSON document like serde_json::to_vec_pretty, keeping the key order
+//(going through serde_json::Value would sort the keys)
+pub fn pretty(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() * 2);
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let newline = |out: &mut Vec<u8>, depth: usize| {
+        out.push(b'\n');
+        out.extend(std::iter::repeat_n(b' ', depth * 2));
+    };
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        index += 1;
+        if in_string {
+            out.push(byte);
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => {
+                in_string = true;
+                out.push(byte);
+            }
+            b'{' | b'[' => {
+                out.push(byte);
+                //empty containers stay `{}` / `[]`
+                if matches!(bytes.get(index), Some(b'}' | b']')) {
+                    out.push(bytes[index]);
+                    index += 1;
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            b'}' | b']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(byte);
+            }
+            b',' => {
+                out.push(byte);
+                newline(&mut out, depth);
+            }
+            b':' => out.extend_from_slice(b": "),
+            b' ' | b'\t' | b'\n' | b'\r' => {}
+            _ => out.push(byte),
+        }
+    }
+    out
+}
@@ -0,0 +1,597 @@
+use std::sync::{Arc, Once};
+
+use apikey::SecurityAddon;
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    http::{HeaderName, HeaderValue, Method, header},
+    middleware::{from_fn, from_fn_with_state},
+    routing::{MethodFilter, get, on, post},
+};
+use config::Config;
+use reload::LiveApp;
+use router::{RouteRecorder, RouteTable};
+use schema::{ResponseValidation, SchemaValidator};
+use stack::LayerStack;
+use state::AppState;
+use tower_http::cors::{Any, CorsLayer};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::{SwaggerUi, Url};
+
+mod access_log;
+mod api_version;
+mod apikey;
+mod audit;
+mod auth;
+mod body;
+mod budget;
+mod bulkhead;
+mod cache;
+mod checksum;
+mod compression;
+mod conditional;
+pub mod config;
+mod context;
+mod cursor;
+mod deadline;
+mod decompression;
+mod dedupe;
+mod degraded;
+mod deprecation;
+mod dev;
+pub mod error;
+mod events;
+mod extract;
+mod files;
+mod guard;
+mod health;
+mod https;
+mod idempotency;
+mod json;
+pub mod lifecycle;
+mod maintenance;
+mod message;
+mod metrics;
+pub mod middleware;
+mod model;
+mod normalize;
+mod note;
+mod panic;
+mod precondition;
+mod proxy;
+pub mod ratelimit;
+mod redact;
+pub mod reload;
+mod replay;
+mod report;
+mod response;
+mod response_limit;
+mod router;
+mod routes;
+pub mod schema;
+mod scope;
+pub mod server;
+mod span;
+mod stack;
+pub mod state;
+mod tap;
+mod timeout;
+mod timing;
+mod transcode;
+mod upload;
+mod vary;
+mod warmup;
+mod webhook;
+mod ws;
+
+//the server for a validated config; returns after shutdown and the drain
+pub async fn run(config: Config) -> Result<(), anyhow::Error> {
+    // tracing
+    init_tracing(config.log_level);
+    config.log_summary();
+
+    // State
+    let state: Arc<AppState> = Arc::new(AppState::builder().config(config).build());
+    let app = Arc::new(LiveApp::new(state.clone(), build_router(state.clone())));
+    tokio::spawn(degraded::watch_signal(state.clone()));
+    tokio::spawn(maintenance::watch_signal(state.clone()));
+    tokio::spawn(reload::watch_signal(app.clone(), build_router));
+
+    // Server
+    let shutdown = lifecycle::announce_shutdown(
+        state.lifecycle.clone(),
+        state.config.pre_shutdown_delay,
+        lifecycle::shutdown_signal(),
+    );
+    server::run(&state.config, app.clone(), shutdown).await?;
+    lifecycle::drain(&state.lifecycle, app.state().config.shutdown_grace).await;
+    state.lifecycle.log_report();
+    Ok(())
+}
+
+//tracing (safe to call more than once, e.g. when tests build the app repeatedly)
+pub fn init_tracing(level: tracing::Level) {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let subscriber = tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(level)
+            //log the handler spans with their recorded status / body size when they close
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .finish();
+        //another subscriber may already be installed: keep it, and say so through it
+        if let Err(err) = tracing::subscriber::set_global_default(subscriber) {
+            tracing::warn!("keeping the installed tracing subscriber: {}", err);
+        }
+    });
+}
+
+//the app for the environment's config (`Config::from_env`), as tests drive it
+pub fn app() -> Router {
+    build_router(Arc::new(AppState::builder().build()))
+}
+
+//Router
+pub fn build_router(state: Arc<AppState>) -> Router {
+    panic::install_hook();
+    // CORS
+    let cors: CorsLayer = CorsLayer::new()
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+        .expose_headers([
+            header::CONTENT_DISPOSITION,
+            HeaderName::from_static(context::X_REQUEST_ID),
+        ])
+        .allow_methods([
+            Method::POST,
+            Method::GET,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_credentials(state.config.cors_allow_credentials)
+        .max_age(state.config.cors_max_age);
+    let cors: CorsLayer = if state.config.cors_any_origin() {
+        cors.allow_origin(Any)
+    } else {
+        cors.allow_origin(
+            state
+                .config
+                .cors_allow_origins
+                .iter()
+                .filter_map(|origin| origin.parse::<HeaderValue>().ok())
+                .collect::<Vec<HeaderValue>>(),
+        )
+    };
+
+    let routes: RouteRecorder = RouteRecorder::new()
+        .tag_handlers(state.config.dev_mode)
+        .route(
+            "/",
+            &[Method::GET],
+            "ping_handler",
+            get(routes::ping_handler),
+        )
+        .route(
+            "/webhook",
+            &[Method::POST],
+            "webhook_handler",
+            post(webhook::webhook_handler).layer(from_fn_with_state(
+                state.clone(),
+                webhook::verify_signature_middleware,
+            )),
+        )
+        .route(
+            "/healthz",
+            &[Method::GET],
+            "liveness_handler",
+            get(health::liveness_handler).with_state(state.clone()),
+        )
+        .route(
+            "/readyz",
+            &[Method::GET],
+            "readiness_handler",
+            get(health::readiness_handler).with_state(state.clone()),
+        )
+        .route(
+            "/shutdown-status",
+            &[Method::GET],
+            "shutdown_status_handler",
+            get(lifecycle::shutdown_status_handler).with_state(state.clone()),
+        )
+        .route(
+            "/metrics",
+            &[Method::GET],
+            "metrics_handler",
+            get(metrics::metrics_handler)
+                .with_state(state.clone())
+                .layer(from_fn_with_state(
+                    state.clone(),
+                    metrics::metrics_auth_middleware,
+                )),
+        )
+        .route(
+            "/admin/ping",
+            &[Method::GET],
+            "admin_ping_handler",
+            get(auth::admin_ping_handler),
+        );
+    // API versions (the resources; the ops routes above stay unversioned)
+    let (mut routes, mounted) = routes::mount(routes, &state);
+    if state.config.events_enabled {
+        routes = routes.route(
+            "/events",
+            &[Method::GET],
+            "events_handler",
+            get(events::events_handler).with_state(state.clone()),
+        );
+    }
+    if state.config.proxy_upstream.is_some() {
+        routes = routes.route(
+            "/proxy/*path",
+            &[
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+                Method::OPTIONS,
+            ],
+            "proxy_handler",
+            middleware::MiddlewareStacks::standard(&state).apply(
+                &[middleware::AUTHENTICATED],
+                on(
+                    MethodFilter::GET
+                        .or(MethodFilter::HEAD)
+                        .or(MethodFilter::POST)
+                        .or(MethodFilter::PUT)
+                        .or(MethodFilter::PATCH)
+                        .or(MethodFilter::DELETE)
+                        .or(MethodFilter::OPTIONS),
+                    proxy::proxy_handler,
+                )
+                .with_state(state.clone()),
+            ),
+        );
+    }
+    if state.config.dev_mode {
+        routes = routes
+            .route(
+                "/_error/:status",
+                &[Method::GET],
+                "error_handler",
+                get(dev::error_handler),
+            )
+            .route(
+                "/_degraded",
+                &[Method::GET, Method::POST],
+                "degraded_handler",
+                get(degraded::status_handler)
+                    .post(degraded::toggle_handler)
+                    .with_state(state.clone()),
+            )
+            .route(
+                "/_maintenance",
+                &[Method::GET, Method::POST],
+                "maintenance_handler",
+                get(maintenance::status_handler)
+                    .post(maintenance::toggle_handler)
+                    .with_state(state.clone()),
+            )
+            .route(
+                "/_tap",
+                &[Method::GET],
+                "tap_handler",
+                get(tap::tap_handler).with_state(state.clone()),
+            );
+        //a handler and a middleware that panic, in debug builds only
+        #[cfg(debug_assertions)]
+        {
+            routes = routes
+                .route(
+                    "/panic",
+                    &[Method::GET],
+                    "panic_handler",
+                    get(panic::panic_handler),
+                )
+                .route(
+                    "/panic/middleware",
+                    &[Method::GET],
+                    "panic_handler",
+                    get(panic::panic_handler).layer(from_fn(panic::panicking_middleware)),
+                );
+        }
+        routes = routes.with_routes_endpoint("/_routes");
+    }
+
+    let mut openapi = routes::combined_doc(&ApiDoc::openapi(), &mounted);
+    deprecation::mark_spec(&mut openapi, &state.config.deprecated_routes);
+    apikey::mark_spec(&mut openapi, &state.config);
+
+    if state
+        .routes
+        .set(RouteTable::build(routes.routes(), &openapi, &state.config))
+        .is_err()
+    {
+        tracing::debug!("route table is already built");
+    }
+    let mut stack = LayerStack::new(routes.into_router().fallback(normalize::not_found_handler));
+    if state.config.response_validation != ResponseValidation::Off {
+        stack = stack.route_layer(
+            "response_schema_middleware",
+            from_fn_with_state(
+                SchemaValidator::new(state.config.response_validation, &openapi),
+                schema::response_schema_middleware,
+            ),
+        );
+    }
+    let mut stack = stack
+        .route_layer(
+            "trace_routing_middleware",
+            from_fn(router::trace_routing_middleware),
+        )
+        .route_layer(
+            "cache_policy_middleware",
+            from_fn_with_state(state.clone(), conditional::cache_policy_middleware),
+        )
+        .route_layer(
+            "timeout_middleware",
+            from_fn_with_state(state.clone(), timeout::timeout_middleware),
+        )
+        .route_layer(
+            "rate_limit_middleware",
+            from_fn_with_state(state.clone(), ratelimit::rate_limit_middleware),
+        )
+        .route_layer(
+            "deprecation_middleware",
+            from_fn_with_state(state.clone(), deprecation::deprecation_middleware),
+        )
+        .route_layer(
+            "replay_middleware",
+            from_fn_with_state(state.clone(), replay::replay_middleware),
+        )
+        .route_layer(
+            "handler_span_middleware",
+            from_fn_with_state(state.clone(), span::handler_span_middleware),
+        )
+        .route_layer(
+            "metrics_middleware",
+            from_fn_with_state(state.clone(), metrics::metrics_middleware),
+        )
+        .route_layer(
+            "audit_middleware",
+            from_fn_with_state(state.clone(), audit::audit_middleware),
+        )
+        //route layers can't be added from here on
+        .map(|router| normalize::before_routing(router, state.clone()))
+        .layer(
+            "response_limit_middleware",
+            from_fn_with_state(state.clone(), response_limit::response_limit_middleware),
+        )
+        .layer(
+            "cache_middleware",
+            from_fn_with_state(state.clone(), cache::cache_middleware),
+        )
+        .layer(
+            "dedupe_middleware",
+            from_fn_with_state(state.clone(), dedupe::dedupe_middleware),
+        )
+        .layer(
+            "decompression_middleware",
+            from_fn_with_state(state.clone(), decompression::decompression_middleware),
+        )
+        .layer(
+            "pretty_json_middleware",
+            from_fn(response::pretty_json_middleware),
+        )
+        .layer(
+            "compression_middleware",
+            from_fn_with_state(state.clone(), compression::compression_middleware),
+        )
+        //outside compression, so the ETag is the hash of the encoded body
+        .layer(
+            "etag_middleware",
+            from_fn_with_state(state.clone(), conditional::etag_middleware),
+        )
+        .layer("vary_middleware", from_fn(vary::vary_middleware))
+        .layer(
+            "server_timing_middleware",
+            from_fn_with_state(state.clone(), timing::server_timing_middleware),
+        )
+        .layer(
+            "deadline_middleware",
+            from_fn(deadline::deadline_middleware),
+        )
+        .layer(
+            "api_version_middleware",
+            from_fn_with_state(state.clone(), api_version::api_version_middleware),
+        )
+        .layer("charset_middleware", from_fn(guard::charset_middleware))
+        .layer(
+            "transcode_middleware",
+            from_fn_with_state(state.clone(), transcode::transcode_middleware),
+        )
+        .layer(
+            "content_length_middleware",
+            from_fn_with_state(state.clone(), guard::content_length_middleware),
+        )
+        .layer(
+            "duplicate_header_middleware",
+            from_fn(guard::duplicate_header_middleware),
+        )
+        .layer(
+            "require_https_middleware",
+            from_fn_with_state(state.clone(), https::require_https_middleware),
+        )
+        .layer("host_middleware", from_fn(guard::host_middleware))
+        .layer(
+            "ambiguous_length_middleware",
+            from_fn(guard::ambiguous_length_middleware),
+        )
+        .layer("null_byte_middleware", from_fn(guard::null_byte_middleware))
+        .layer(
+            "header_value_size_middleware",
+            from_fn_with_state(state.clone(), guard::header_value_size_middleware),
+        )
+        .layer(
+            "min_body_rate_middleware",
+            from_fn_with_state(state.clone(), body::min_body_rate_middleware),
+        )
+        .layer(
+            "body_timeout_middleware",
+            from_fn_with_state(state.clone(), timeout::body_timeout_middleware),
+        );
+    if state.config.dev_mode {
+        //the warmup requests go through everything layered so far
+        let target = stack.router().clone();
+        let warmup_state = state.clone();
+        stack = stack.map(|router| {
+            router.route(
+                "/_warmup",
+                post(move || warmup::warmup_handler(warmup_state.clone(), target.clone())),
+            )
+        });
+    }
+    if state.config.swagger_enabled {
+        //the combined document plus one per version (the definition dropdown)
+        let mut swagger = SwaggerUi::new("/swagger-ui").url(
+            Url::new("all versions", "/api-docs/openapi.json"),
+            openapi.clone(),
+        );
+        for tree in &mounted {
+            swagger = swagger.url(
+                Url::new(tree.prefix, tree.doc),
+                routes::version_doc(&openapi, &mounted, tree.version),
+            );
+        }
+        stack = stack.map(|router| router.merge(swagger));
+    }
+    if state.config.dev_mode {
+        stack = stack.layer(
+            "tap_middleware",
+            from_fn_with_state(state.clone(), tap::tap_middleware),
+        );
+    }
+    let stack = stack
+        .layer(
+            "api_key_middleware",
+            from_fn_with_state(state.clone(), apikey::api_key_middleware),
+        )
+        .layer(
+            "auth_middleware",
+            from_fn_with_state(state.clone(), auth::auth_middleware),
+        )
+        .layer(
+            "panic_middleware",
+            from_fn_with_state(state.clone(), panic::panic_middleware),
+        )
+        .layer(
+            "error_report_middleware",
+            from_fn_with_state(state.clone(), report::error_report_middleware),
+        )
+        .layer(
+            "maintenance_middleware",
+            from_fn_with_state(state.clone(), maintenance::maintenance_middleware),
+        )
+        .layer(
+            "budget_middleware",
+            from_fn_with_state(state.clone(), budget::budget_middleware),
+        )
+        .layer(
+            "access_log_middleware",
+            from_fn_with_state(state.clone(), access_log::access_log_middleware),
+        )
+        .layer(
+            "context_middleware",
+            from_fn_with_state(state.clone(), context::context_middleware),
+        )
+        .layer(
+            "in_flight_middleware",
+            from_fn_with_state(state.clone(), lifecycle::in_flight_middleware),
+        )
+        .layer(
+            "accept_ranges_middleware",
+            from_fn(response::accept_ranges_middleware),
+        )
+        .layer(
+            "hop_by_hop_middleware",
+            from_fn(response::hop_by_hop_middleware),
+        )
+        .layer("cors", cors)
+        .layer("body_limit", DefaultBodyLimit::max(state.config.body_limit));
+    //dev only: GET /_middleware lists the layers above, innermost first
+    if state.config.dev_mode {
+        stack.with_middleware_endpoint("/_middleware")
+    } else {
+        stack.into_router()
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "axum-middleware-mytutorial",
+        version = "0.0.1",
+        description = "This is a axum-middleware-mytutorial API document.",
+        contact(
+            name = "Myxogastria0808",
+            email = "r.rstudio.c@gmail.com",
+            url = "https://yukiosada.work",
+        ),
+        license(
+            name = "WTFPL",
+            url = "http://www.wtfpl.net"
+        ),
+    ),
+    servers((url = "http://0.0.0.0:5000")),
+    tags(
+        (name = "Sample", description = "Sample API"),
+        (name = "Messages", description = "Stored messages (MESSAGE_STORE)"),
+        (name = "Ops", description = "Operational endpoints"),
+    ),
+    paths(
+        crate::routes::ping_handler,
+        crate::routes::sample::sample_handler,
+        crate::routes::sample::raw_sample_handler,
+        crate::routes::sample::list_sample_handler,
+        crate::routes::sample::page_sample_handler,
+        crate::routes::sample::slow_sample_handler,
+        crate::routes::sample::stream_sample_handler,
+        crate::upload::upload_handler,
+        crate::upload::store_upload_handler,
+        crate::files::download_handler,
+        crate::ws::ws_handler,
+        crate::webhook::webhook_handler,
+        crate::note::get_note_handler,
+        crate::note::put_note_handler,
+        crate::note::delete_note_handler,
+        crate::message::create_message_handler,
+        crate::message::list_messages_handler,
+        crate::message::get_message_handler,
+        crate::message::update_message_handler,
+        crate::message::delete_message_handler,
+        crate::lifecycle::shutdown_status_handler,
+        crate::health::liveness_handler,
+        crate::health::readiness_handler,
+        crate::events::events_handler,
+    ),
+    components(schemas(
+        crate::error::ResponseError,
+        crate::error::ErrorLocation,
+        crate::error::FieldError,
+        crate::model::RequestData,
+        crate::model::ResponseData,
+        crate::cursor::ResponseDataPage,
+        crate::note::Note,
+        crate::note::NoteRequest,
+        crate::message::Message,
+        crate::upload::UploadedFile,
+        crate::upload::StoredFile,
+        crate::upload::UploadForm,
+        crate::lifecycle::ShutdownStatus,
+        crate::health::Liveness,
+        crate::health::Readiness,
+        crate::health::CheckResult,
+    )),
+    modifiers(&SecurityAddon)
+)]
+struct ApiDoc;
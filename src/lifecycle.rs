@@ -0,0 +1,209 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+//server lifecycle flags shared by the shutdown path and the status endpoint
+#[derive(Debug)]
+pub struct Lifecycle {
+    pub shutting_down: AtomicBool,
+    pub in_flight: AtomicUsize,
+    //set by POST /_warmup
+    pub warmed: AtomicBool,
+    //totals for the shutdown report
+    pub started_at: Instant,
+    pub requests: AtomicU64,
+    pub client_errors: AtomicU64,
+    pub server_errors: AtomicU64,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self {
+            shutting_down: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            warmed: AtomicBool::new(false),
+            started_at: Instant::now(),
+            requests: AtomicU64::new(0),
+            client_errors: AtomicU64::new(0),
+            server_errors: AtomicU64::new(0),
+        }
+    }
+
+    //structured summary of the run, logged when main() returns
+    pub fn log_report(&self) {
+        tracing::info!(
+            requests = self.requests.load(Ordering::SeqCst),
+            client_errors = self.client_errors.load(Ordering::SeqCst),
+            server_errors = self.server_errors.load(Ordering::SeqCst),
+            uptime_secs = self.started_at.elapsed().as_secs(),
+            unfinished = self.in_flight.load(Ordering::SeqCst),
+            "shutdown report"
+        );
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ShutdownStatus {
+    pub shutting_down: bool,
+    //includes the status request itself
+    pub in_flight: usize,
+    pub warmed: bool,
+}
+
+//counts as in flight until dropped (even when the request future is dropped), so
+//drain waits for it
+pub struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl<'a> InFlightGuard<'a> {
+    pub fn enter(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+//Middleware
+pub async fn in_flight_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let lifecycle = &state.lifecycle;
+    let _guard = InFlightGuard::enter(&lifecycle.in_flight);
+    let response = next.run(request).await;
+    lifecycle.requests.fetch_add(1, Ordering::Relaxed);
+    if response.status().is_client_error() {
+        lifecycle.client_errors.fetch_add(1, Ordering::Relaxed);
+    } else if response.status().is_server_error() {
+        lifecycle.server_errors.fetch_add(1, Ordering::Relaxed);
+    }
+    response
+}
+
+//Ctrl+C or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::warn!("cannot listen for Ctrl+C: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::warn!("cannot listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown signal received");
+}
+
+//the server's shutdown future: once `signal` completes the server is marked as shutting
+//down (GET /readyz turns 503, /shutdown-status and the gauge report it), then
+//PRE_SHUTDOWN_DELAY_SECS pass with the listener still accepting before this completes
+//and accepting stops
+pub async fn announce_shutdown(
+    lifecycle: Arc<Lifecycle>,
+    delay: Duration,
+    signal: impl Future<Output = ()>,
+) {
+    signal.await;
+    lifecycle.shutting_down.store(true, Ordering::SeqCst);
+    if !delay.is_zero() {
+        tracing::info!(
+            "reporting not ready for {}s before closing the listener",
+            delay.as_secs()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+//waits (up to `grace`) for in-flight requests, logging the remaining count once a
+//second. requests still running afterwards are dropped with the runtime
+pub async fn drain(lifecycle: &Lifecycle, grace: Duration) {
+    //normally set by announce_shutdown already
+    lifecycle.shutting_down.store(true, Ordering::SeqCst);
+    let started_at = Instant::now();
+    let deadline = started_at + grace;
+    let mut in_flight = lifecycle.in_flight.load(Ordering::SeqCst);
+    if in_flight > 0 {
+        tracing::info!(
+            "draining {} in-flight requests (up to {}s)",
+            in_flight,
+            grace.as_secs()
+        );
+    }
+    let mut next_report = started_at + Duration::from_secs(1);
+    while in_flight > 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        in_flight = lifecycle.in_flight.load(Ordering::SeqCst);
+        if in_flight > 0 && Instant::now() >= next_report {
+            tracing::info!("still draining: {} requests in flight", in_flight);
+            next_report += Duration::from_secs(1);
+        }
+    }
+    if in_flight > 0 {
+        tracing::warn!(
+            "shutdown grace of {}s expired, dropping {} in-flight requests",
+            grace.as_secs(),
+            in_flight
+        );
+    } else {
+        tracing::info!("drained in {}ms", started_at.elapsed().as_millis());
+    }
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/shutdown-status",
+    tag = "Ops",
+    responses(
+        (status = 200, description = "OK", body = ShutdownStatus),
+    ),
+)]
+pub async fn shutdown_status_handler(State(state): State<Arc<AppState>>) -> Json<ShutdownStatus> {
+    Json(ShutdownStatus {
+        shutting_down: state.lifecycle.shutting_down.load(Ordering::SeqCst),
+        in_flight: state.lifecycle.in_flight.load(Ordering::SeqCst),
+        warmed: state.lifecycle.warmed.load(Ordering::SeqCst),
+    })
+}
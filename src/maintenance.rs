@@ -0,0 +1,130 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, state::AppState};
+
+//always reachable, otherwise maintenance mode couldn't be switched off again
+const MAINTENANCE_PATH: &str = "/_maintenance";
+
+//MAINTENANCE_MODE: every non-exempt route answers 503 MAINTENANCE (toggled without a restart)
+#[derive(Debug)]
+pub struct Maintenance {
+    enabled: AtomicBool,
+    message: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+impl Maintenance {
+    pub fn new(enabled: bool, message: Option<String>) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            message: Mutex::new(message),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn status(&self) -> MaintenanceStatus {
+        MaintenanceStatus {
+            enabled: self.is_enabled(),
+            message: self.message.lock().unwrap().clone(),
+        }
+    }
+}
+
+//SIGUSR2 toggles maintenance mode (the dev endpoint isn't available in production)
+#[cfg(unix)]
+pub async fn watch_signal(state: Arc<AppState>) {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut signal = match signal(SignalKind::user_defined2()) {
+        Ok(signal) => signal,
+        Err(err) => {
+            tracing::warn!("cannot listen for SIGUSR2: {}", err);
+            return;
+        }
+    };
+    while signal.recv().await.is_some() {
+        let enabled = !state.maintenance.enabled.fetch_xor(true, Ordering::SeqCst);
+        tracing::warn!("maintenance mode {}", if enabled { "on" } else { "off" });
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn watch_signal(_state: Arc<AppState>) {}
+
+//Middleware
+pub async fn maintenance_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let exempt = path == MAINTENANCE_PATH
+        || state
+            .config
+            .maintenance_exempt_paths
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()));
+    if exempt || !state.maintenance.is_enabled() {
+        return next.run(request).await;
+    }
+    let message = state
+        .maintenance
+        .message
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| "the service is under maintenance".to_string());
+    let mut response =
+        AppError::new(StatusCode::SERVICE_UNAVAILABLE, "MAINTENANCE", message).into_response();
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(state.config.maintenance_retry_after.as_secs()),
+    );
+    response
+}
+
+//Handler (dev only)
+//GET /_maintenance => current state
+pub async fn status_handler(State(state): State<Arc<AppState>>) -> Json<MaintenanceStatus> {
+    Json(state.maintenance.status())
+}
+
+//POST /_maintenance {"enabled": true, "message": "..."} => switches the mode
+pub async fn toggle_handler(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<MaintenanceRequest>,
+) -> Json<MaintenanceStatus> {
+    let maintenance = &state.maintenance;
+    *maintenance.message.lock().unwrap() = body.message;
+    maintenance.enabled.store(body.enabled, Ordering::SeqCst);
+    tracing::warn!(
+        "maintenance mode {}",
+        if body.enabled { "on" } else { "off" }
+    );
+    Json(maintenance.status())
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Extension, Json,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    api_version::ApiVersion,
+    error::{AppError, ErrorKind},
+    extract::{Validate, ValidatedJson, ValidatedPath},
+    model::RequestData,
+    response::{CacheControl, NoContent},
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Message {
+    pub id: u64,
+    pub name: String,
+    pub message: String,
+    pub created_at_ms: u64,
+    pub updated_at_ms: u64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct MessagePath {
+    /// message id
+    #[param(minimum = 1)]
+    pub id: u64,
+}
+
+impl Validate for MessagePath {
+    fn validate(&self) -> Result<(), String> {
+        if self.id == 0 {
+            return Err("id must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+//message storage backend (a SQL database would implement this)
+pub trait Repository: Send + Sync + fmt::Debug {
+    fn create(&self, data: RequestData) -> Result<Message, anyhow::Error>;
+    fn get(&self, id: u64) -> Result<Option<Message>, anyhow::Error>;
+    //ordered by id
+    fn list(&self) -> Result<Vec<Message>, anyhow::Error>;
+    //None: no message with this id
+    fn update(&self, id: u64, data: RequestData) -> Result<Option<Message>, anyhow::Error>;
+    //false: no message with this id
+    fn delete(&self, id: u64) -> Result<bool, anyhow::Error>;
+    //GET /readyz (e.g. the database answers)
+    fn health(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Messages {
+    by_id: BTreeMap<u64, Message>,
+    last_id: u64,
+}
+
+impl Messages {
+    fn create(&mut self, data: RequestData) -> Message {
+        self.last_id += 1;
+        let now = now_ms();
+        let message = Message {
+            id: self.last_id,
+            name: data.name,
+            message: data.message,
+            created_at_ms: now,
+            updated_at_ms: now,
+        };
+        self.by_id.insert(message.id, message.clone());
+        message
+    }
+
+    fn update(&mut self, id: u64, data: RequestData) -> Option<Message> {
+        let message = self.by_id.get_mut(&id)?;
+        message.name = data.name;
+        message.message = data.message;
+        message.updated_at_ms = now_ms();
+        Some(message.clone())
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+//lost on restart
+#[derive(Debug, Default)]
+pub struct MemoryRepository(Mutex<Messages>);
+
+impl Repository for MemoryRepository {
+    fn create(&self, data: RequestData) -> Result<Message, anyhow::Error> {
+        Ok(self.0.lock().unwrap().create(data))
+    }
+
+    fn get(&self, id: u64) -> Result<Option<Message>, anyhow::Error> {
+        Ok(self.0.lock().unwrap().by_id.get(&id).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<Message>, anyhow::Error> {
+        Ok(self.0.lock().unwrap().by_id.values().cloned().collect())
+    }
+
+    fn update(&self, id: u64, data: RequestData) -> Result<Option<Message>, anyhow::Error> {
+        Ok(self.0.lock().unwrap().update(id, data))
+    }
+
+    fn delete(&self, id: u64) -> Result<bool, anyhow::Error> {
+        Ok(self.0.lock().unwrap().by_id.remove(&id).is_some())
+    }
+}
+
+//all messages as one JSON array, rewritten (via a temporary file and a rename) after
+//every change. fine for a tutorial-sized table, not for concurrent processes
+#[derive(Debug)]
+pub struct FileRepository {
+    path: PathBuf,
+    messages: Mutex<Messages>,
+}
+
+impl FileRepository {
+    //a missing file starts empty
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let by_id: BTreeMap<u64, Message> = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<Vec<Message>>(&bytes)?
+                .into_iter()
+                .map(|message| (message.id, message))
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let last_id = by_id.keys().next_back().copied().unwrap_or(0);
+        Ok(Self {
+            path,
+            messages: Mutex::new(Messages { by_id, last_id }),
+        })
+    }
+
+    fn save(&self, messages: &Messages) -> Result<(), anyhow::Error> {
+        let json = serde_json::to_vec(&messages.by_id.values().collect::<Vec<&Message>>())?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Repository for FileRepository {
+    //the change is kept in memory only if it was written
+    fn create(&self, data: RequestData) -> Result<Message, anyhow::Error> {
+        let mut messages = self.messages.lock().unwrap();
+        let message = messages.create(data);
+        if let Err(err) = self.save(&messages) {
+            messages.by_id.remove(&message.id);
+            return Err(err);
+        }
+        Ok(message)
+    }
+
+    fn get(&self, id: u64) -> Result<Option<Message>, anyhow::Error> {
+        Ok(self.messages.lock().unwrap().by_id.get(&id).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<Message>, anyhow::Error> {
+        Ok(self
+            .messages
+            .lock()
+            .unwrap()
+            .by_id
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn update(&self, id: u64, data: RequestData) -> Result<Option<Message>, anyhow::Error> {
+        let mut messages = self.messages.lock().unwrap();
+        let Some(previous) = messages.by_id.get(&id).cloned() else {
+            return Ok(None);
+        };
+        let message = messages.update(id, data);
+        if let Err(err) = self.save(&messages) {
+            messages.by_id.insert(id, previous);
+            return Err(err);
+        }
+        Ok(message)
+    }
+
+    fn delete(&self, id: u64) -> Result<bool, anyhow::Error> {
+        let mut messages = self.messages.lock().unwrap();
+        let Some(previous) = messages.by_id.remove(&id) else {
+            return Ok(false);
+        };
+        if let Err(err) = self.save(&messages) {
+            messages.by_id.insert(id, previous);
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    //the next save can create the file (best effort: the directory exists and isn't read-only)
+    fn health(&self) -> Result<(), anyhow::Error> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        let metadata = fs::metadata(dir)?;
+        if !metadata.is_dir() || metadata.permissions().readonly() {
+            anyhow::bail!("{} is not a writable directory", dir.display());
+        }
+        Ok(())
+    }
+}
+
+//MESSAGE_STORE: memory | file:<path> (an unreadable file falls back to memory)
+pub fn from_config(store: &str) -> Arc<dyn Repository> {
+    match store.strip_prefix("file:") {
+        Some(path) => match FileRepository::open(path) {
+            Ok(repository) => Arc::new(repository),
+            Err(err) => {
+                tracing::warn!("cannot load {}: {}, keeping messages in memory", path, err);
+                Arc::new(MemoryRepository::default())
+            }
+        },
+        None => Arc::new(MemoryRepository::default()),
+    }
+}
+
+fn not_found(id: u64) -> AppError {
+    AppError::of(ErrorKind::NotFound, format!("no message with id {}", id))
+}
+
+//Handler
+#[utoipa::path(
+    post,
+    path = "/messages",
+    tag = "Messages",
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "retries with the same key (and body) get the first response, marked Idempotent-Replayed: true"),
+    ),
+    request_body(
+        description = "RequestData",
+        content = RequestData,
+    ),
+    responses(
+        (status = 201, description = "Created (Location: /api/v<N>/messages/{id})", body = Message),
+        (status = 400, description = "Bad Request", body = ResponseError),
+        (status = 409, description = "a request with the Idempotency-Key is still running", body = ResponseError),
+        (status = 422, description = "Invalid fields (listed in `fields`), or the Idempotency-Key was used with another body", body = ResponseError),
+    ),
+)]
+pub async fn create_message_handler(
+    State(state): State<Arc<AppState>>,
+    api_version: Option<Extension<ApiVersion>>,
+    ValidatedJson(data): ValidatedJson<RequestData>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let message = state.messages.create(data)?;
+    let root = api_version.map_or(String::new(), |Extension(version)| version.root);
+    Ok((
+        StatusCode::CREATED,
+        CacheControl::NoStore,
+        [(
+            header::LOCATION,
+            format!("{}/messages/{}", root, message.id),
+        )],
+        Json(message),
+    )
+        .into_response())
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/messages",
+    tag = "Messages",
+    responses(
+        (status = 200, description = "OK (ordered by id)", body = [Message]),
+    ),
+)]
+pub async fn list_messages_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let messages = state.messages.list()?;
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(messages)).into_response())
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/messages/{id}",
+    tag = "Messages",
+    params(MessagePath),
+    responses(
+        (status = 200, description = "OK", body = Message),
+        (status = 404, description = "Not Found", body = ResponseError),
+    ),
+)]
+pub async fn get_message_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(MessagePath { id }): ValidatedPath<MessagePath>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let message = state.messages.get(id)?.ok_or_else(|| not_found(id))?;
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(message)).into_response())
+}
+
+//Handler
+#[utoipa::path(
+    put,
+    path = "/messages/{id}",
+    tag = "Messages",
+    params(MessagePath),
+    request_body(
+        description = "RequestData",
+        content = RequestData,
+    ),
+    responses(
+        (status = 200, description = "OK", body = Message),
+        (status = 404, description = "Not Found", body = ResponseError),
+        (status = 422, description = "Invalid fields (listed in `fields`)", body = ResponseError),
+    ),
+)]
+pub async fn update_message_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(MessagePath { id }): ValidatedPath<MessagePath>,
+    ValidatedJson(data): ValidatedJson<RequestData>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let message = state
+        .messages
+        .update(id, data)?
+        .ok_or_else(|| not_found(id))?;
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(message)).into_response())
+}
+
+//Handler
+#[utoipa::path(
+    delete,
+    path = "/messages/{id}",
+    tag = "Messages",
+    params(MessagePath),
+    responses(
+        (status = 204, description = "Deleted (no body)"),
+        (status = 404, description = "Not Found", body = ResponseError),
+    ),
+)]
+pub async fn delete_message_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(MessagePath { id }): ValidatedPath<MessagePath>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    if !state.messages.delete(id)? {
+        return Err(not_found(id));
+    }
+    Ok((CacheControl::NoStore, NoContent).into_response())
+}
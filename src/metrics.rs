@@ -0,0 +1,343 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use http_body::Body as _;
+
+use crate::{
+    checksum,
+    context::ClientIp,
+    error::{AppError, ErrorKind},
+    response::CacheControl,
+    scope::RequestScope,
+    state::AppState,
+};
+
+//upper bounds of the histogram buckets (`+Inf` is implied)
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const SIZE_BUCKETS: [f64; 7] = [
+    100.0,
+    1_000.0,
+    10_000.0,
+    100_000.0,
+    1_000_000.0,
+    10_000_000.0,
+    100_000_000.0,
+];
+
+//size of the request body as buffered by a middleware (e.g. sample_middleware), left in
+//the RequestScope for http_request_body_bytes; bodies nobody buffered aren't counted
+#[derive(Debug, Clone, Copy)]
+pub struct BufferedBodySize(pub u64);
+
+//per (route, method, status) request series, filled by metrics_middleware
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<SeriesKey, Series>>,
+    //requests that panicked (panic_middleware)
+    panics: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SeriesKey {
+    route: String,
+    method: String,
+    status: u16,
+}
+
+#[derive(Debug)]
+struct Series {
+    latency: Histogram,
+    request_size: Histogram,
+    request_body: Histogram,
+    response_size: Histogram,
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    //cumulative counts would need every bucket on each observe, so these are per bucket
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn write(&self, body: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                body,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            body,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(body, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(body, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+impl Metrics {
+    fn observe(
+        &self,
+        key: SeriesKey,
+        latency: Duration,
+        request_size: u64,
+        request_body: Option<u64>,
+        response_size: Option<u64>,
+    ) {
+        let mut series = self.series.lock().unwrap();
+        let series = series.entry(key).or_insert_with(|| Series {
+            latency: Histogram::new(&LATENCY_BUCKETS),
+            request_size: Histogram::new(&SIZE_BUCKETS),
+            request_body: Histogram::new(&SIZE_BUCKETS),
+            response_size: Histogram::new(&SIZE_BUCKETS),
+        });
+        series.latency.observe(latency.as_secs_f64());
+        series.request_size.observe(request_size as f64);
+        if let Some(size) = request_body {
+            series.request_body.observe(size as f64);
+        }
+        //streamed responses have no size yet
+        if let Some(size) = response_size {
+            series.response_size.observe(size as f64);
+        }
+    }
+
+    pub fn record_panic(&self) {
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn write(&self, body: &mut String) {
+        let _ = writeln!(
+            body,
+            "# HELP http_panics_total requests that panicked (answered with a 500)"
+        );
+        let _ = writeln!(body, "# TYPE http_panics_total counter");
+        let _ = writeln!(
+            body,
+            "http_panics_total {}",
+            self.panics.load(Ordering::Relaxed)
+        );
+        let series = self.series.lock().unwrap();
+        let _ = writeln!(
+            body,
+            "# HELP http_requests_total requests by route, method and status"
+        );
+        let _ = writeln!(body, "# TYPE http_requests_total counter");
+        for (key, series) in series.iter() {
+            let _ = writeln!(
+                body,
+                "http_requests_total{{{}}} {}",
+                key.labels(),
+                series.latency.count
+            );
+        }
+        for (name, help, histogram) in [
+            (
+                "http_request_duration_seconds",
+                "time until the response head was ready",
+                (|series: &Series| &series.latency) as fn(&Series) -> &Histogram,
+            ),
+            (
+                "http_request_size_bytes",
+                "request body size (Content-Length, 0 when unknown)",
+                |series: &Series| &series.request_size,
+            ),
+            (
+                "http_request_body_bytes",
+                "buffered request body size (unbuffered bodies are not counted)",
+                |series: &Series| &series.request_body,
+            ),
+            (
+                "http_response_size_bytes",
+                "response body size (streamed responses are not counted)",
+                |series: &Series| &series.response_size,
+            ),
+        ] {
+            let _ = writeln!(body, "# HELP {} {}", name, help);
+            let _ = writeln!(body, "# TYPE {} histogram", name);
+            for (key, series) in series.iter() {
+                histogram(series).write(body, name, &key.labels());
+            }
+        }
+    }
+}
+
+impl SeriesKey {
+    fn labels(&self) -> String {
+        format!(
+            "route=\"{}\",method=\"{}\",status=\"{}\"",
+            escape_label(&self.route),
+            self.method,
+            self.status
+        )
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//Middleware (route_layer: unmatched requests have no route label and aren't recorded)
+//records count, latency and body sizes per route template, method and status
+pub async fn metrics_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started_at = Instant::now();
+    let route = state
+        .route(&request)
+        .map_or(request.uri().path(), |meta| meta.template.as_str())
+        .to_string();
+    let method = request.method().to_string();
+    let request_size = request.body().size_hint().exact().unwrap_or(0);
+    let scope = request.extensions().get::<RequestScope>().cloned();
+    let response = next.run(request).await;
+    //read afterwards: the buffering layers run inside this one
+    let request_body = scope
+        .and_then(|scope| scope.get::<BufferedBodySize>())
+        .map(|BufferedBodySize(size)| size);
+    state.metrics.observe(
+        SeriesKey {
+            route,
+            method,
+            status: response.status().as_u16(),
+        },
+        started_at.elapsed(),
+        request_size,
+        request_body,
+        response.body().size_hint().exact(),
+    );
+    response
+}
+
+//Middleware (GET /metrics only)
+//checks METRICS_ALLOWED_IPS (403) and `Authorization: Bearer <METRICS_TOKEN>` (401).
+//open when neither is configured (local dev)
+pub async fn metrics_auth_middleware(
+    State(state): State<Arc<AppState>>,
+    client_ip: Option<ClientIp>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let config = &state.config;
+    if !config.metrics_allowed_ips.is_empty()
+        && !client_ip.is_some_and(|ClientIp(ip)| config.metrics_allowed_ips.contains(&ip))
+    {
+        return Err(AppError::of(
+            ErrorKind::Forbidden,
+            "client is not allowed to read metrics",
+        ));
+    }
+    if let Some(token) = config.metrics_token.as_deref() {
+        let presented = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        if !presented.is_some_and(|presented| {
+            checksum::constant_time_eq(presented.as_bytes(), token.as_bytes())
+        }) {
+            let mut response = AppError::of(
+                ErrorKind::Unauthorized,
+                "a valid metrics bearer token is required",
+            )
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            return Ok(response);
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+//Handler
+//Prometheus text exposition of the lifecycle and runtime-mode flags. no-store: a cached
+//copy would be served to callers the gate never checked
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let lifecycle = &state.lifecycle;
+    let gauges = [
+        (
+            "in_flight_requests",
+            "requests currently being served",
+            lifecycle.in_flight.load(Ordering::SeqCst) as u64,
+        ),
+        (
+            "shutting_down",
+            "1 while draining for shutdown",
+            lifecycle.shutting_down.load(Ordering::SeqCst) as u64,
+        ),
+        (
+            "warmed",
+            "1 after POST /_warmup",
+            lifecycle.warmed.load(Ordering::SeqCst) as u64,
+        ),
+        (
+            "degraded_mode",
+            "1 while degraded mode is on",
+            state.degraded.is_enabled() as u64,
+        ),
+        (
+            "maintenance_mode",
+            "1 while maintenance mode is on",
+            state.maintenance.is_enabled() as u64,
+        ),
+    ];
+    let mut body = String::new();
+    for (name, help, value) in gauges {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} gauge", name);
+        let _ = writeln!(body, "{} {}", name, value);
+    }
+    state.metrics.write(&mut body);
+    (
+        CacheControl::NoStore,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        body,
+    )
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{OriginalUri, Request, State},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, Route},
+};
+use tower::{Layer, Service};
+
+use crate::{
+    auth, body, budget, error::AppError, idempotency, metrics::BufferedBodySize, redact,
+    response_limit, scope::RequestScope, state::AppState, upload,
+};
+
+//the names of the standard stacks
+pub const AUTHENTICATED: &str = "authenticated";
+pub const IDEMPOTENT: &str = "idempotent";
+pub const HEAVY_LOGGING: &str = "heavy-logging";
+
+type ApplyLayer = Arc<dyn Fn(MethodRouter<()>) -> MethodRouter<()> + Send + Sync>;
+
+//a named list of route layers a route opts into (MiddlewareStacks::apply). the layers
+//run in the order they were added, after the shared stack of build_router (they are
+//route layers of the method router, so only a matched method reaches them)
+#[derive(Clone)]
+pub struct MiddlewareStack {
+    name: &'static str,
+    layers: Vec<(&'static str, ApplyLayer)>,
+}
+
+impl MiddlewareStack {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn layer<L>(mut self, name: &'static str, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request, Error = Infallible> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let apply: ApplyLayer = Arc::new(move |method_router: MethodRouter<()>| {
+            method_router.route_layer(layer.clone())
+        });
+        self.layers.push((name, apply));
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn apply(&self, mut method_router: MethodRouter<()>) -> MethodRouter<()> {
+        //route_layer wraps what is there, so the first layer goes on last
+        for (layer, apply) in self.layers.iter().rev() {
+            method_router = apply(method_router).route_layer(middleware::from_fn_with_state(
+                (self.name, *layer),
+                trace_middleware,
+            ));
+        }
+        method_router
+    }
+}
+
+//the stacks routes can opt into, by name
+#[derive(Clone, Default)]
+pub struct MiddlewareStacks(HashMap<&'static str, MiddlewareStack>);
+
+impl MiddlewareStacks {
+    //routes outside every stack only get the shared layers (and are public as far as
+    //AUTH_PUBLIC_PATHS says)
+    pub fn standard(state: &Arc<AppState>) -> Self {
+        Self::default()
+            .register(MiddlewareStack::new(AUTHENTICATED).layer(
+                "require_identity_middleware",
+                middleware::from_fn_with_state(state.clone(), auth::require_identity_middleware),
+            ))
+            .register(MiddlewareStack::new(IDEMPOTENT).layer(
+                "idempotency_middleware",
+                middleware::from_fn_with_state(state.clone(), idempotency::idempotency_middleware),
+            ))
+            .register(MiddlewareStack::new(HEAVY_LOGGING).layer(
+                "sample_middleware",
+                middleware::from_fn_with_state(state.clone(), sample_middleware),
+            ))
+    }
+
+    //replaces a stack of the same name
+    pub fn register(mut self, stack: MiddlewareStack) -> Self {
+        self.0.insert(stack.name, stack);
+        self
+    }
+
+    //wraps `method_router` in the named stacks; they run in the listed order (e.g.
+    //`[AUTHENTICATED, HEAVY_LOGGING]`: nothing is logged for a rejected caller).
+    //panics on an unknown name, like axum does on a conflicting route
+    pub fn apply(&self, names: &[&str], mut method_router: MethodRouter<()>) -> MethodRouter<()> {
+        for name in names.iter().rev() {
+            let stack = self
+                .0
+                .get(name)
+                .unwrap_or_else(|| panic!("unknown middleware stack {:?}", name));
+            method_router = stack.apply(method_router);
+        }
+        method_router
+    }
+}
+
+//the stack layers a request went through, in order (`<stack>/<layer>`), as a response
+//extension; absent for routes outside every stack
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MiddlewareTrace(pub Vec<String>);
+
+//request extension shared by the trace layers of one request
+#[derive(Clone, Default)]
+struct TraceRecorder(Arc<Mutex<Vec<String>>>);
+
+//Middleware (in front of each stack layer)
+//records the layer as entered; on the way out the outermost one leaves the complete
+//trace, including layers that answered without calling the next one
+async fn trace_middleware(
+    State((stack, layer)): State<(&'static str, &'static str)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let recorder = request
+        .extensions_mut()
+        .get_or_insert_default::<TraceRecorder>()
+        .clone();
+    recorder
+        .0
+        .lock()
+        .unwrap()
+        .push(format!("{}/{}", stack, layer));
+    let mut response = next.run(request).await;
+    let trace = recorder.0.lock().unwrap().clone();
+    response.extensions_mut().insert(MiddlewareTrace(trace));
+    response
+}
+
+//Middleware (the heavy-logging stack)
+pub async fn sample_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    //body logging is optional debug work (LOG_BODIES), skipped in degraded mode, for
+    //noisy paths and for multipart uploads. up to LOG_BODY_MAX_BYTES of each body is read and
+    //logged, the body is handed on with those bytes put back and the rest streamed through
+    //the full path: a nested tree sees its own without the prefix
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(request.uri().path(), |uri| uri.path())
+        .to_string();
+    let excluded = state
+        .config
+        .log_exclude_paths
+        .iter()
+        .any(|prefix| path.starts_with(prefix.as_str()));
+    if !state.config.log_bodies
+        || excluded
+        || upload::is_multipart(request.headers())
+        || state.degraded.is_enabled()
+    {
+        return Ok(next.run(request).await);
+    }
+    //preprocess
+    tracing::info!("Preprocess");
+    let max = state.config.log_body_max_bytes;
+    let (parts, body) = request.into_parts();
+    let size = http_body::Body::size_hint(&body).exact();
+    let (head, body) = body::peek(body, max)
+        .await
+        .map_err(|err| body::read_error(err, state.config.body_limit))?;
+    let buffered = budget::Buffered::of(&parts.extensions);
+    buffered.add(head.len());
+    if let Some(scope) = parts.extensions.get::<RequestScope>() {
+        scope.set(BufferedBodySize(head.len() as u64));
+    }
+    //method, path and headers are in the access log
+    tracing::info!(
+        "request: {}",
+        redact::redact_body(
+            &head,
+            size,
+            &parts.headers,
+            &state.config.log_redact_fields,
+            max
+        )
+    );
+    let request = Request::from_parts(parts, body);
+    //handler
+    tracing::info!("Handler");
+    let response = next.run(request).await;
+    //postprocess
+    tracing::info!("Postprocess");
+    //streamed bodies are passed through untouched (an event stream may take its time to
+    //send LOG_BODY_MAX_BYTES, or never end)
+    if http_body::Body::size_hint(response.body())
+        .exact()
+        .is_none()
+    {
+        tracing::info!("response: streamed");
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let size = http_body::Body::size_hint(&body).exact();
+    let (head, body) = body::peek(body, max)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(head.len());
+    tracing::info!(
+        "response: {}",
+        redact::redact_body(
+            &head,
+            size,
+            &parts.headers,
+            &state.config.log_redact_fields,
+            max
+        )
+    );
+    Ok(Response::from_parts(parts, body))
+}
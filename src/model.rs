@@ -1,13 +1,137 @@
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::FieldError,
+    extract::{Validate, ValidateFields},
+};
+
+const SAMPLE_PATH_RANGE: RangeInclusive<i32> = 1..=i32::MAX;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct SamplePath {
+    /// sample path (positive)
+    #[param(minimum = 1)]
+    pub path: i32,
+}
+
+impl Validate for SamplePath {
+    fn validate(&self) -> Result<(), String> {
+        if SAMPLE_PATH_RANGE.contains(&self.path) {
+            Ok(())
+        } else {
+            Err(format!(
+                "path must be in {}..={}, got {}",
+                SAMPLE_PATH_RANGE.start(),
+                SAMPLE_PATH_RANGE.end(),
+                self.path
+            ))
+        }
+    }
+}
+
+//the `///` lines are the parameter descriptions in the API document
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SampleQuery {
+    /// query (SAMPLE_QUERY_DEFAULT when omitted)
+    #[param(nullable = false)]
+    pub query: Option<String>,
+    /// comma separated response fields to keep
+    pub fields: Option<String>,
+    /// true: include the received request headers (sensitive ones omitted)
+    #[serde(default)]
+    #[param(required = false, default = false)]
+    pub echo_headers: bool,
+}
+
+pub const MAX_LIST_COUNT: usize = 1_000_000;
+
+fn default_list_count() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    /// number of items
+    #[serde(default = "default_list_count")]
+    #[param(required = false, default = 10, maximum = 1000000)]
+    pub count: usize,
+}
+
+//GET /sample/:path/slow (a handler that outlives its timeout on demand)
+pub const MAX_SLOW_DELAY_MS: u64 = 10 * 60 * 1000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SlowQuery {
+    /// how long the handler sleeps before answering
+    #[param(maximum = 600000)]
+    pub delay_ms: u64,
+}
+
+//items behind GET /sample/:path/page
+pub const SAMPLE_PAGE_ITEMS: usize = 100;
+pub const MAX_PAGE_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// next_cursor of the previous page (opaque)
+    pub cursor: Option<String>,
+    /// items per page
+    #[serde(default = "default_list_count")]
+    #[param(required = false, default = 10, minimum = 1, maximum = 100)]
+    pub limit: usize,
+}
+
+pub const MAX_NAME_CHARS: usize = 100;
+pub const MAX_MESSAGE_CHARS: usize = 4096;
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct RequestData {
+    // non-blank, at most MAX_NAME_CHARS characters
+    #[schema(min_length = 1, max_length = 100)]
     pub name: String,
+    // at most MAX_MESSAGE_CHARS characters
+    #[schema(max_length = 4096)]
     pub message: String,
 }
 
-#[derive(Debug, Serialize, ToSchema)]
+impl ValidateFields for RequestData {
+    fn validate_fields(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.name.trim().is_empty() {
+            errors.push(FieldError::new("name", "must not be empty"));
+        } else if self.name.chars().count() > MAX_NAME_CHARS {
+            errors.push(FieldError::new(
+                "name",
+                format!("must be at most {} characters", MAX_NAME_CHARS),
+            ));
+        }
+        if self.message.chars().count() > MAX_MESSAGE_CHARS {
+            errors.push(FieldError::new(
+                "message",
+                format!("must be at most {} characters", MAX_MESSAGE_CHARS),
+            ));
+        }
+        errors
+    }
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
 pub struct ResponseData {
     pub message: String,
+    // raw body variant only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    // ?echo_headers=true only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
 }
@@ -0,0 +1,136 @@
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, Uri, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use http_body::Body as _;
+use tower::Layer;
+
+use crate::{
+    error::{AppError, ErrorKind},
+    state::AppState,
+};
+
+//TRAILING_SLASH: what `/sample/1/` gets (`/` itself is left alone). strict: a 404 like
+//any unknown path, redirect: 308 to `/sample/1` (method and body are kept), rewrite:
+//served as `/sample/1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    Strict,
+    Redirect,
+    Rewrite,
+}
+
+impl FromStr for TrailingSlash {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Ok(Self::Strict),
+            "redirect" => Ok(Self::Redirect),
+            "rewrite" => Ok(Self::Rewrite),
+            _ => Err(format!(
+                "expected strict, redirect or rewrite, got {:?}",
+                value
+            )),
+        }
+    }
+}
+
+//runs normalize_middleware before routing: a Router::layer only runs once a route
+//matched, too late to change the path. the outer router has nothing but the fallback,
+//so layers added to it afterwards still wrap every request
+pub fn before_routing(router: Router, state: Arc<AppState>) -> Router {
+    Router::new()
+        .fallback_service(middleware::from_fn_with_state(state, normalize_middleware).layer(router))
+}
+
+//Middleware (before routing, see before_routing)
+//applies TRAILING_SLASH, and gives the router's bare 405s an error body
+pub async fn normalize_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let mode = state.config.trailing_slash;
+    if mode != TrailingSlash::Strict
+        && let Some(uri) = without_trailing_slash(request.uri())
+    {
+        if mode == TrailingSlash::Redirect {
+            return redirect(&uri);
+        }
+        tracing::debug!("rewriting {} to {}", request.uri(), uri);
+        *request.uri_mut() = uri;
+    }
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if response.status() == StatusCode::METHOD_NOT_ALLOWED
+        && response.body().size_hint().exact() == Some(0)
+    {
+        return method_not_allowed(&method, &path, response.headers().get(header::ALLOW));
+    }
+    response
+}
+
+//Handler (router fallback)
+pub async fn not_found_handler(method: Method, uri: Uri) -> AppError {
+    AppError::of(
+        ErrorKind::NotFound,
+        format!("no route for {} {}", method, uri.path()),
+    )
+}
+
+//405 METHOD_NOT_ALLOWED naming the allowed methods, with the router's Allow header
+pub fn method_not_allowed(method: &Method, path: &str, allow: Option<&HeaderValue>) -> Response {
+    let allowed = allow
+        .and_then(|allow| allow.to_str().ok())
+        .unwrap_or_default();
+    let mut response = AppError::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "METHOD_NOT_ALLOWED",
+        format!(
+            "{} is not allowed on {} (allowed: {})",
+            method, path, allowed
+        ),
+    )
+    .into_response();
+    if let Some(allow) = allow {
+        response.headers_mut().insert(header::ALLOW, allow.clone());
+    }
+    response
+}
+
+//`/a/b//?q` => `/a/b?q`; None when there is nothing to trim
+fn without_trailing_slash(uri: &Uri) -> Option<Uri> {
+    let path = uri.path();
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.len() == path.len() || trimmed.is_empty() {
+        return None;
+    }
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", trimmed, query),
+        None => trimmed.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+fn redirect(uri: &Uri) -> Response {
+    let location = uri
+        .path_and_query()
+        .map_or("/", |location| location.as_str());
+    match HeaderValue::from_str(location) {
+        Ok(location) => (
+            StatusCode::PERMANENT_REDIRECT,
+            [(header::LOCATION, location)],
+        )
+            .into_response(),
+        Err(_) => AppError::of(ErrorKind::BadRequest, "invalid request path").into_response(),
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, ErrorKind},
+    extract::{Payload, ValidatedPath},
+    model::SamplePath,
+    precondition,
+    response::{CacheControl, NoContent},
+    state::AppState,
+};
+
+//in-memory notes keyed by sample path (versioned for If-Match)
+#[derive(Debug, Default)]
+pub struct NoteStore(Mutex<HashMap<i32, Note>>);
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Note {
+    pub text: String,
+    pub version: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NoteRequest {
+    pub text: String,
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/sample/{path}/note",
+    tag = "Sample",
+    params(SamplePath),
+    responses(
+        (status = 200, description = "OK (ETag: the note version)", body = Note),
+        (status = 404, description = "Not Found", body = ResponseError),
+    ),
+)]
+pub async fn get_note_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let note = state.notes.0.lock().unwrap().get(&path).cloned();
+    let Some(note) = note else {
+        return Err(AppError::of(
+            ErrorKind::NotFound,
+            format!("no note for path {}", path),
+        ));
+    };
+    Ok((
+        StatusCode::OK,
+        CacheControl::NoStore,
+        [(header::ETAG, precondition::etag(note.version))],
+        Json(note),
+    )
+        .into_response())
+}
+
+//Handler
+#[utoipa::path(
+    put,
+    path = "/sample/{path}/note",
+    tag = "Sample",
+    params(
+        SamplePath,
+        ("If-Match" = Option<String>, Header, description = "ETag from GET (required with IF_MATCH_REQUIRED=true once the note exists)"),),
+    request_body(
+        description = "NoteRequest",
+        content = NoteRequest,
+    ),
+    responses(
+        (status = 200, description = "OK (ETag: the new version)", body = Note),
+        (status = 412, description = "If-Match does not match", body = ResponseError),
+        (status = 428, description = "If-Match is required", body = ResponseError),
+    ),
+)]
+pub async fn put_note_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    headers: HeaderMap,
+    Payload(body): Payload<NoteRequest>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    //check and update under one lock, so two writers can't both pass the precondition
+    let note = {
+        let mut notes = state.notes.0.lock().unwrap();
+        let current = notes
+            .get(&path)
+            .map(|note| precondition::etag(note.version));
+        precondition::check_if_match(&headers, current.as_deref(), state.config.if_match_required)?;
+        let version = notes.get(&path).map_or(1, |note| note.version + 1);
+        let note = Note {
+            text: body.text,
+            version,
+        };
+        notes.insert(path, note.clone());
+        note
+    };
+    Ok((
+        StatusCode::OK,
+        CacheControl::NoStore,
+        [(header::ETAG, precondition::etag(note.version))],
+        Json(note),
+    )
+        .into_response())
+}
+
+//Handler
+#[utoipa::path(
+    delete,
+    path = "/sample/{path}/note",
+    tag = "Sample",
+    params(
+        SamplePath,
+        ("If-Match" = Option<String>, Header, description = "ETag from GET (required with IF_MATCH_REQUIRED=true)"),),
+    responses(
+        (status = 204, description = "Deleted (no body)"),
+        (status = 404, description = "Not Found", body = ResponseError),
+        (status = 412, description = "If-Match does not match", body = ResponseError),
+        (status = 428, description = "If-Match is required", body = ResponseError),
+    ),
+)]
+pub async fn delete_note_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let mut notes = state.notes.0.lock().unwrap();
+    let Some(note) = notes.get(&path) else {
+        return Err(AppError::of(
+            ErrorKind::NotFound,
+            format!("no note for path {}", path),
+        ));
+    };
+    let current = precondition::etag(note.version);
+    precondition::check_if_match(&headers, Some(&current), state.config.if_match_required)?;
+    notes.remove(&path);
+    Ok((CacheControl::NoStore, NoContent).into_response())
+}
@@ -0,0 +1,102 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Once},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::FutureExt;
+
+use crate::{
+    context::RequestContext,
+    error::{AppError, ErrorKind},
+    scope::RequestScope,
+    state::AppState,
+};
+
+thread_local! {
+    //backtrace of the last panic on this thread, left by the hook for panic_middleware
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+//chains a hook in front of the current one that captures the backtrace where the panic
+//happened (it is gone once the unwinding reaches the middleware). once per process
+pub fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            BACKTRACE.with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::force_capture()));
+            previous(info);
+        }));
+    });
+}
+
+//Middleware (inside error_report/context so a panic is reported with its request id)
+//a panic in a handler or in any middleware inside this layer becomes a JSON 500 with the
+//request id instead of a dropped connection. the payload and backtrace are logged, not
+//returned, and counted in http_panics_total
+pub async fn panic_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestScope>()
+        .and_then(|scope| scope.get::<RequestContext>())
+        .map(|context| context.request_id);
+    let payload = match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => return response,
+        Err(payload) => payload,
+    };
+    let backtrace = BACKTRACE
+        .with(|backtrace| backtrace.borrow_mut().take())
+        .map_or_else(
+            || "unavailable".to_string(),
+            |backtrace| backtrace.to_string(),
+        );
+    tracing::error!(
+        "request panicked: {}\nbacktrace:\n{}",
+        detail(payload.as_ref()),
+        backtrace
+    );
+    state.metrics.record_panic();
+    let error = AppError::of(
+        ErrorKind::Internal,
+        "the server panicked while handling the request",
+    );
+    match request_id {
+        Some(request_id) => error.with_request_id(request_id),
+        None => error,
+    }
+    .into_response()
+}
+
+fn detail(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".to_string())
+}
+
+//Handler (debug builds, dev only)
+//GET /panic => panics, for trying out panic_middleware
+#[cfg(debug_assertions)]
+pub async fn panic_handler() -> Response {
+    panic!("GET /panic was requested")
+}
+
+//Middleware (debug builds, the route layer of GET /panic/middleware)
+//panics before the handler runs: panic_middleware also covers the layers inside it
+#[cfg(debug_assertions)]
+pub async fn panicking_middleware(_request: Request, _next: Next) -> Response {
+    panic!("a middleware of GET /panic/middleware panicked")
+}
@@ -0,0 +1,55 @@
+use axum::http::{HeaderMap, StatusCode, header};
+
+use crate::error::AppError;
+
+//`"<version>"` strong entity tag
+pub fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+//If-Match for updates (optimistic concurrency).
+//`current`: ETag of the stored resource (None if it doesn't exist yet).
+//strict (IF_MATCH_REQUIRED): updating an existing resource without If-Match => 428
+pub fn check_if_match(
+    headers: &HeaderMap,
+    current: Option<&str>,
+    strict: bool,
+) -> Result<(), AppError> {
+    let values: Vec<&str> = headers
+        .get_all(header::IF_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if values.is_empty() {
+        if strict && current.is_some() {
+            return Err(AppError::new(
+                StatusCode::PRECONDITION_REQUIRED,
+                "PRECONDITION_REQUIRED",
+                "If-Match is required to update this resource",
+            ));
+        }
+        return Ok(());
+    }
+    let Some(current) = current else {
+        return Err(precondition_failed("the resource does not exist"));
+    };
+    //strong comparison: weak tags (W/"...") never match
+    if values.iter().any(|tag| *tag == "*" || *tag == current) {
+        return Ok(());
+    }
+    Err(precondition_failed(&format!(
+        "If-Match does not match the current ETag {}",
+        current
+    )))
+}
+
+fn precondition_failed(message: &str) -> AppError {
+    AppError::new(
+        StatusCode::PRECONDITION_FAILED,
+        "PRECONDITION_FAILED",
+        message,
+    )
+}
@@ -0,0 +1,303 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use http_body::Body as _;
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::TokioExecutor,
+};
+
+use crate::{
+    context::{ClientIp, X_FORWARDED_FOR},
+    error::{AppError, ErrorKind},
+    https::{self, X_FORWARDED_PROTO},
+    state::AppState,
+};
+
+pub const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+//the route prefix replaced by PROXY_UPSTREAM's path
+pub const PREFIX: &str = "/proxy";
+
+//upstream answers retried (and counted by the breaker) like an unreachable upstream
+const UNAVAILABLE: [StatusCode; 3] = [
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+//first retry delay, doubled after every attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+//the outbound side of /proxy: a pooled HTTP/1 client and the upstream's circuit breaker.
+//kept across reloads, the settings are read from the current config per request
+#[derive(Debug)]
+pub struct Proxy {
+    client: Client<HttpConnector, Body>,
+    breaker: CircuitBreaker,
+}
+
+impl Default for Proxy {
+    fn default() -> Self {
+        Self {
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            breaker: CircuitBreaker::default(),
+        }
+    }
+}
+
+//opens after PROXY_BREAKER_THRESHOLD failures in a row: requests are answered with a 503
+//without trying the upstream. after PROXY_BREAKER_COOLDOWN_SECS one request is let
+//through; its success closes the breaker, a failure opens it for another cooldown
+#[derive(Debug, Default)]
+struct CircuitBreaker(Mutex<BreakerState>);
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    //Err: how long it stays open
+    fn admit(&self, cooldown: Duration) -> Result<(), Duration> {
+        let mut state = self.0.lock().unwrap();
+        let Some(open_until) = state.open_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if now < open_until {
+            return Err(open_until - now);
+        }
+        //the trial request; the others wait for its outcome for another cooldown
+        state.open_until = Some(now + cooldown);
+        Ok(())
+    }
+
+    fn record(&self, success: bool, threshold: u32, cooldown: Duration) {
+        let mut state = self.0.lock().unwrap();
+        if success {
+            if state.open_until.take().is_some() {
+                tracing::info!("proxy upstream is back, circuit breaker closed");
+            }
+            state.failures = 0;
+            return;
+        }
+        state.failures += 1;
+        if threshold != 0 && state.failures >= threshold {
+            if state.open_until.is_none() {
+                tracing::warn!(
+                    "proxy upstream failed {} times in a row, circuit breaker open for {}s",
+                    state.failures,
+                    cooldown.as_secs()
+                );
+            }
+            state.open_until = Some(Instant::now() + cooldown);
+        }
+    }
+}
+
+enum Failure {
+    Unreachable(String),
+    TimedOut,
+}
+
+//Handler (any method)
+//`/proxy/<path>?<query>` => PROXY_UPSTREAM + `/<path>?<query>` with the method, the
+//headers minus hop-by-hop ones (plus X-Forwarded-*) and the body streamed both ways.
+//requests without a body of idempotent methods are retried up to PROXY_RETRIES times
+//after a connection failure, a timeout (PROXY_TIMEOUT_SECS, until the response head) or
+//a 502/503/504. other requests are sent once: their body can't be replayed
+pub async fn proxy_handler(
+    State(state): State<Arc<AppState>>,
+    client_ip: Option<ClientIp>,
+    request: Request,
+) -> Result<Response, AppError> {
+    let config = &state.config;
+    let Some(upstream) = config.proxy_upstream.as_ref() else {
+        return Err(AppError::of(
+            ErrorKind::NotFound,
+            "PROXY_UPSTREAM is not configured",
+        ));
+    };
+    let uri = target(upstream, request.uri())?;
+    let forwarded = forwarded_headers(&state, &request, client_ip);
+    let (mut parts, body) = request.into_parts();
+    strip_hop_by_hop(&mut parts.headers);
+    parts.headers.remove(header::HOST);
+    parts.headers.extend(forwarded);
+    parts.uri = uri;
+    parts.extensions = Default::default();
+    let replayable = matches!(
+        parts.method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    ) && body.size_hint().exact() == Some(0);
+    let attempts = if replayable {
+        config.proxy_retries + 1
+    } else {
+        1
+    };
+
+    let proxy = &state.proxy;
+    let (threshold, cooldown) = (
+        config.proxy_breaker_threshold,
+        config.proxy_breaker_cooldown,
+    );
+    let mut body = Some(body);
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        if threshold != 0
+            && let Err(open_for) = proxy.breaker.admit(cooldown)
+        {
+            let mut response = AppError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "UPSTREAM_CIRCUIT_OPEN",
+                "the upstream is failing, requests are paused",
+            )
+            .into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(open_for.as_secs_f64().ceil().max(1.0) as u64),
+            );
+            return Ok(response);
+        }
+        //the streamed body goes with the first attempt, retries only happen without one
+        let outbound = body.take().unwrap_or_else(Body::empty);
+        let sent = proxy
+            .client
+            .request(Request::from_parts(parts.clone(), outbound));
+        let result = if config.proxy_timeout.is_zero() {
+            sent.await
+                .map_err(|err| Failure::Unreachable(err.to_string()))
+        } else {
+            match tokio::time::timeout(config.proxy_timeout, sent).await {
+                Ok(result) => result.map_err(|err| Failure::Unreachable(err.to_string())),
+                Err(_) => Err(Failure::TimedOut),
+            }
+        };
+        let success = result
+            .as_ref()
+            .is_ok_and(|response| !UNAVAILABLE.contains(&response.status()));
+        proxy.breaker.record(success, threshold, cooldown);
+        if success || attempt >= attempts {
+            return match result {
+                Ok(response) => {
+                    let (mut parts, body) = response.into_parts();
+                    strip_hop_by_hop(&mut parts.headers);
+                    Ok(Response::from_parts(parts, Body::new(body)))
+                }
+                Err(Failure::TimedOut) => Err(AppError::new(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "UPSTREAM_TIMEOUT",
+                    format!(
+                        "the upstream did not answer within {}s",
+                        config.proxy_timeout.as_secs()
+                    ),
+                )),
+                Err(Failure::Unreachable(err)) => {
+                    tracing::warn!("proxy request to {} failed: {}", parts.uri, err);
+                    Err(AppError::new(
+                        StatusCode::BAD_GATEWAY,
+                        "UPSTREAM_UNAVAILABLE",
+                        "the upstream could not be reached",
+                    ))
+                }
+            };
+        }
+        tracing::info!(
+            "proxy attempt {}/{} to {} failed, retrying",
+            attempt,
+            attempts,
+            parts.uri
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+//the upstream URI for a request under PREFIX (the path is passed on still encoded)
+fn target(upstream: &Uri, uri: &Uri) -> Result<Uri, AppError> {
+    let rest = uri.path().strip_prefix(PREFIX).unwrap_or(uri.path());
+    let base = upstream.path().trim_end_matches('/');
+    let query = uri
+        .query()
+        .map(|query| format!("?{}", query))
+        .unwrap_or_default();
+    format!(
+        "{}://{}{}{}{}",
+        upstream.scheme_str().unwrap_or("http"),
+        upstream
+            .authority()
+            .map_or("", |authority| authority.as_str()),
+        base,
+        rest,
+        query
+    )
+    .parse()
+    .map_err(|_| AppError::of(ErrorKind::BadRequest, "the path cannot be proxied"))
+}
+
+//X-Forwarded-For (the client appended), -Proto and -Host of the inbound request
+fn forwarded_headers(
+    state: &AppState,
+    request: &Request,
+    client_ip: Option<ClientIp>,
+) -> HeaderMap {
+    let headers = request.headers();
+    let mut forwarded = HeaderMap::new();
+    if let Some(ClientIp(ip)) = client_ip {
+        let chain = headers
+            .get(X_FORWARDED_FOR)
+            .and_then(|value| value.to_str().ok())
+            .map_or_else(|| ip.to_string(), |chain| format!("{}, {}", chain, ip));
+        if let Ok(chain) = HeaderValue::from_str(&chain) {
+            forwarded.insert(X_FORWARDED_FOR, chain);
+        }
+    }
+    let proto = if https::is_https(state, request) {
+        "https"
+    } else {
+        "http"
+    };
+    forwarded.insert(X_FORWARDED_PROTO, HeaderValue::from_static(proto));
+    if let Some(host) = headers.get(header::HOST) {
+        forwarded.insert(X_FORWARDED_HOST, host.clone());
+    }
+    forwarded
+}
+
+//headers of one connection (RFC 9110 7.6.1), including the ones Connection names
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let named: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+    for name in named {
+        headers.remove(name);
+    }
+    for name in [
+        header::CONNECTION,
+        HeaderName::from_static("keep-alive"),
+        header::PROXY_AUTHENTICATE,
+        header::PROXY_AUTHORIZATION,
+        header::TE,
+        header::TRAILER,
+        header::TRANSFER_ENCODING,
+        header::UPGRADE,
+    ] {
+        headers.remove(name);
+    }
+}
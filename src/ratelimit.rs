@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    context::ClientIp,
+    error::{AppError, ErrorKind},
+    state::AppState,
+};
+
+//idle (refilled) buckets are dropped once this many clients are tracked
+const PRUNE_AT: usize = 10_000;
+
+//a rate limit: `<per minute>[/<burst>]` (the burst defaults to the rate, `0` is off)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl Rate {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (per_minute, burst) = match value.trim().split_once('/') {
+            Some((per_minute, burst)) => {
+                (per_minute.trim().parse().ok()?, burst.trim().parse().ok()?)
+            }
+            None => {
+                let per_minute = value.trim().parse().ok()?;
+                (per_minute, per_minute)
+            }
+        };
+        //a burst of 0 would block every request
+        if per_minute > 0 && burst == 0 {
+            return None;
+        }
+        Some(Self { per_minute, burst })
+    }
+}
+
+//one ROUTE_RATE_LIMITS entry: `<route>=<per minute>[/<burst>]` (e.g.
+//`/api/v1/sample/:path=10/20`; `=0` exempts the route from RATE_LIMIT_DEFAULT)
+#[derive(Debug, Clone)]
+pub struct RouteRateLimit {
+    pub path: String,
+    pub rate: Rate,
+}
+
+impl RouteRateLimit {
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (path, rate) = entry.rsplit_once('=')?;
+        let path = path.trim();
+        if !path.starts_with('/') {
+            return None;
+        }
+        Some(Self {
+            path: path.to_string(),
+            rate: Rate::parse(rate)?,
+        })
+    }
+}
+
+//token buckets keyed by client IP (or another key, e.g. the API key): `per_minute`
+//tokens are added per minute up to `burst`, and each request takes one
+#[derive(Debug)]
+pub struct RateLimiter<K = IpAddr> {
+    name: String,
+    per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    //per_minute = 0 disables the limiter
+    pub fn new(name: impl Into<String>, per_minute: u32, burst: u32) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.into(),
+            per_second: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.per_second > 0.0
+    }
+
+    //Err(time until the next token)
+    pub fn acquire(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_AT {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+}
+
+//Middleware (route layer)
+//the matched route's limiter (its ROUTE_RATE_LIMITS entry, else RATE_LIMIT_DEFAULT), one
+//bucket per client IP: 429 RATE_LIMITED with Retry-After once it is used up
+pub async fn rate_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    client_ip: Result<ClientIp, AppError>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = state
+        .route(&request)
+        .and_then(|route| route.rate_limit.as_ref())
+    else {
+        return next.run(request).await;
+    };
+    //only a limited route needs the client address
+    let ip = match client_ip {
+        Ok(ClientIp(ip)) => ip,
+        Err(err) => return err.into_response(),
+    };
+    if let Err(wait) = limiter.acquire(ip) {
+        tracing::warn!(client_ip = %ip, "rate limit of {} exceeded", limiter.name);
+        return rate_limited(&limiter.name, wait);
+    }
+    next.run(request).await
+}
+
+//429 RATE_LIMITED with Retry-After (whole seconds, at least 1)
+pub fn rate_limited(name: &str, wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = AppError::of(
+        ErrorKind::RateLimited,
+        format!(
+            "too many {} requests, retry after {} seconds",
+            name, retry_after
+        ),
+    )
+    .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
@@ -0,0 +1,196 @@
+use axum::http::{HeaderMap, header};
+use percent_encoding::percent_decode_str;
+use serde_json::{Map, Value};
+
+use crate::checksum;
+
+const MASK: &str = "***";
+
+//body (or its first bytes, see body::peek) => log string. JSON and urlencoded forms are
+//redacted, other text is logged as is and binary bodies as base64. a body longer than
+//`max` (LOG_BODY_MAX_BYTES) is logged up to there and marked as truncated; `size` is its
+//full length when known
+pub fn redact_body(
+    head: &[u8],
+    size: Option<u64>,
+    headers: &HeaderMap,
+    fields: &[String],
+    max: usize,
+) -> String {
+    let essence = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map(|mime| mime.essence_str().to_string());
+    let form = essence.as_deref() == Some(mime::APPLICATION_WWW_FORM_URLENCODED.as_ref());
+    if head.len() <= max {
+        if let Ok(mut value) = serde_json::from_slice::<Value>(head) {
+            redact_value(&mut value, fields);
+            return value.to_string();
+        }
+        return match std::str::from_utf8(head) {
+            Ok(text) if form => redact_form(text, fields),
+            Ok(text) => text.to_string(),
+            Err(_) => format!("base64:{}", checksum::base64_encode(head)),
+        };
+    }
+    let cut = &head[..max];
+    let json = essence
+        .as_deref()
+        .is_some_and(|essence| essence == "application/json" || essence.ends_with("+json"))
+        || cut.trim_ascii_start().starts_with(b"{")
+        || cut.trim_ascii_start().starts_with(b"[");
+    let logged = match utf8_prefix(cut) {
+        Some(text) if form => redact_form(text, fields),
+        Some(text) if json => redact_json_prefix(text, fields),
+        Some(text) => text.to_string(),
+        None => format!("base64:{}", checksum::base64_encode(cut)),
+    };
+    let size = size.map_or_else(|| format!("more than {}", max), |size| size.to_string());
+    format!("{}... (truncated, {} bytes)", logged, size)
+}
+
+//`bytes` as text, allowing a character cut in half at the end
+fn utf8_prefix(bytes: &[u8]) -> Option<&str> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text),
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&bytes[..err.valid_up_to()]).ok()
+        }
+        Err(_) => None,
+    }
+}
+
+//redact_value for a JSON text cut off at LOG_BODY_MAX_BYTES, which can't be parsed: the
+//value after a key in `fields` is masked, also when it runs past the cut
+fn redact_json_prefix(text: &str, fields: &[String]) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut last_string = None;
+    let mut at = 0;
+    while let Some(c) = text[at..].chars().next() {
+        if c == '"' {
+            let len = string_len(&text[at..]);
+            last_string = Some(&text[at + 1..(at + len - 1).max(at + 1)]);
+            redacted.push_str(&text[at..at + len]);
+            at += len;
+            continue;
+        }
+        redacted.push(c);
+        at += c.len_utf8();
+        if c == ':'
+            && last_string
+                .is_some_and(|key| fields.iter().any(|field| field.eq_ignore_ascii_case(key)))
+        {
+            redacted.push_str(&format!("\"{}\"", MASK));
+            at += value_len(&text[at..]);
+        }
+        if !c.is_whitespace() {
+            last_string = None;
+        }
+    }
+    redacted
+}
+
+//length of the JSON string at the start of `text`, quotes included (all of it when cut)
+fn string_len(text: &str) -> usize {
+    let mut escaped = false;
+    for (at, byte) in text.bytes().enumerate().skip(1) {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'"' => return at + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+//length of the JSON value at the start of `text`, leading whitespace included
+fn value_len(text: &str) -> usize {
+    let start = text.len() - text.trim_start().len();
+    let value = &text[start..];
+    let mut depth = 0usize;
+    let mut at = 0;
+    while let Some(&byte) = value.as_bytes().get(at) {
+        match byte {
+            b'"' => {
+                at += string_len(&value[at..]);
+                if depth == 0 {
+                    return start + at;
+                }
+                continue;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if depth <= 1 => return start + at + depth,
+            b'}' | b']' => depth -= 1,
+            b',' if depth == 0 => return start + at,
+            _ if depth == 0 && byte.is_ascii_whitespace() => return start + at,
+            _ => {}
+        }
+        at += 1;
+    }
+    text.len()
+}
+
+//`a=1&password=x` => `a=1&password=***` (keys compared decoded, `+` is a space)
+fn redact_form(form: &str, fields: &[String]) -> String {
+    form.split('&')
+        .map(|pair| {
+            let raw_key = pair.split_once('=').map_or(pair, |(key, _)| key);
+            let decoded = raw_key.replace('+', " ");
+            let key = percent_decode_str(&decoded).decode_utf8_lossy();
+            if fields.iter().any(|field| field.eq_ignore_ascii_case(&key)) {
+                format!("{}={}", raw_key, MASK)
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+fn redact_value(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if fields.iter().any(|field| field.eq_ignore_ascii_case(key)) {
+                    *value = Value::String(MASK.to_string());
+                } else {
+                    redact_value(value, fields);
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                redact_value(value, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+//headers => JSON object for logs, with the `names` values masked (case-insensitive)
+pub fn redact_headers(headers: &HeaderMap, names: &[String]) -> Map<String, Value> {
+    let mut redacted = Map::new();
+    for (name, value) in headers {
+        let value = if names
+            .iter()
+            .any(|sensitive| sensitive.eq_ignore_ascii_case(name.as_str()))
+        {
+            MASK.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        //repeated headers are joined like a single comma-separated field
+        match redacted.get_mut(name.as_str()) {
+            Some(Value::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            }
+            _ => {
+                redacted.insert(name.to_string(), Value::String(value));
+            }
+        }
+    }
+    redacted
+}
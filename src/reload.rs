@@ -0,0 +1,71 @@
+use std::sync::{Arc, RwLock};
+
+use axum::Router;
+
+use crate::{config::Config, state::AppState};
+
+//the router new requests are served by, with the AppState it was built from.
+//server::serve takes one clone per request, so a request keeps the config snapshot it
+//arrived with for its whole lifetime, even if a reload swaps in a new one meanwhile
+#[derive(Debug)]
+pub struct LiveApp {
+    current: RwLock<(Arc<AppState>, Router)>,
+}
+
+impl LiveApp {
+    pub fn new(state: Arc<AppState>, router: Router) -> Self {
+        Self {
+            current: RwLock::new((state, router)),
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        self.current.read().unwrap().1.clone()
+    }
+
+    pub fn state(&self) -> Arc<AppState> {
+        self.current.read().unwrap().0.clone()
+    }
+
+    //rebuilds the app from the environment (.env re-read, overriding earlier values).
+    //an invalid config is logged and the current one kept. the listener, connection
+    //settings and log level are read once at startup and need a restart
+    pub fn reload(&self, build: fn(Arc<AppState>) -> Router) {
+        if let Err(err) = dotenvy::dotenv_override() {
+            tracing::debug!("no .env to reload: {}", err);
+        }
+        let config = Config::from_env();
+        if let Err(err) = config.validate() {
+            tracing::warn!(
+                "config reload rejected, keeping the current config: {}",
+                err
+            );
+            return;
+        }
+        config.log_summary();
+        let state = Arc::new(self.state().reload(config));
+        //built outside the lock, requests keep being served meanwhile
+        let router = build(state.clone());
+        *self.current.write().unwrap() = (state, router);
+        tracing::info!("config reloaded");
+    }
+}
+
+//SIGHUP reloads the config
+#[cfg(unix)]
+pub async fn watch_signal(live: Arc<LiveApp>, build: fn(Arc<AppState>) -> Router) {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut signal = match signal(SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(err) => {
+            tracing::warn!("cannot listen for SIGHUP: {}", err);
+            return;
+        }
+    };
+    while signal.recv().await.is_some() {
+        live.reload(build);
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn watch_signal(_live: Arc<LiveApp>, _build: fn(Arc<AppState>) -> Router) {}
@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{error::AppError, state::AppState};
+
+pub const X_NONCE: &str = "x-nonce";
+pub const X_TIMESTAMP: &str = "x-timestamp";
+
+//nonces seen within the replay window
+#[derive(Debug)]
+pub struct NonceStore {
+    window: Duration,
+    seen: Mutex<HashMap<String, Instant>>,
+}
+
+impl NonceStore {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //false if the nonce was already used
+    //(a nonce is kept for twice the window: a timestamp can be up to `window` old or ahead)
+    fn insert(&self, nonce: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        let now = Instant::now();
+        seen.retain(|_, expires_at| *expires_at > now);
+        if seen.contains_key(nonce) {
+            return false;
+        }
+        seen.insert(nonce.to_string(), now + self.window * 2);
+        true
+    }
+}
+
+//Middleware (route layer)
+//requires a fresh X-Timestamp (unix seconds) and an unused X-Nonce on REPLAY_PROTECTED_ROUTES
+pub async fn replay_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let protected = state
+        .route(&request)
+        .is_some_and(|route| route.replay_protected);
+    if protected {
+        check(&state, request.headers())?;
+    }
+    Ok(next.run(request).await)
+}
+
+fn check(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    let window = state.config.replay_window;
+    let timestamp = header_str(headers, X_TIMESTAMP)?
+        .parse::<u64>()
+        .map_err(|_| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_TIMESTAMP",
+                "X-Timestamp must be unix seconds",
+            )
+        })?;
+    let nonce = header_str(headers, X_NONCE)?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if now.abs_diff(timestamp) > window.as_secs() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "STALE_REQUEST",
+            format!(
+                "X-Timestamp is more than {} seconds away from the server time",
+                window.as_secs()
+            ),
+        ));
+    }
+    //checked after the timestamp, so stale requests don't fill the store
+    if !state.nonces.insert(nonce) {
+        return Err(AppError::new(
+            StatusCode::UNAUTHORIZED,
+            "REPLAYED_NONCE",
+            "X-Nonce has already been used",
+        ));
+    }
+    Ok(())
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, AppError> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "MISSING_REPLAY_HEADERS",
+                format!("{} header is required", name),
+            )
+        })
+}
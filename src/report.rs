@@ -0,0 +1,131 @@
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+
+use crate::{context::RequestContext, scope::RequestScope, state::AppState};
+
+//5xx AppError details, left in the response extensions by AppError::into_response
+#[derive(Debug, Clone)]
+pub struct ReportedError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorEvent<'a> {
+    pub status: u16,
+    pub code: &'a str,
+    pub message: &'a str,
+    pub request_id: Option<&'a str>,
+    pub method: &'a str,
+    pub path: &'a str,
+}
+
+//error tracking backend (a Sentry client would implement this)
+pub trait ErrorReporter: Send + Sync + fmt::Debug {
+    fn report(&self, event: &ErrorEvent);
+}
+
+#[derive(Debug)]
+pub struct NoopReporter;
+
+impl ErrorReporter for NoopReporter {
+    fn report(&self, _event: &ErrorEvent) {}
+}
+
+//writes each event as one JSON line
+pub struct LogReporter {
+    sink: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl fmt::Debug for LogReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogReporter")
+            .field("sink", &self.sink.as_ref().map_or("tracing", |_| "writer"))
+            .finish()
+    }
+}
+
+impl ErrorReporter for LogReporter {
+    fn report(&self, event: &ErrorEvent) {
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+        match &self.sink {
+            None => tracing::error!(target: "error_report", "{}", line),
+            Some(sink) => {
+                let mut sink = sink.lock().unwrap();
+                if let Err(err) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+                    tracing::warn!("failed to write error report: {}", err);
+                }
+            }
+        }
+    }
+}
+
+//ERROR_REPORTER: none | log (tracing) | stderr | file:<path>
+pub fn from_config(reporter: &str) -> Arc<dyn ErrorReporter> {
+    let sink: Box<dyn Write + Send> = match reporter {
+        "none" | "" => return Arc::new(NoopReporter),
+        "log" => return Arc::new(LogReporter { sink: None }),
+        "stderr" => Box::new(io::stderr()),
+        other => match other
+            .strip_prefix("file:")
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        {
+            Some(Ok(file)) => Box::new(file),
+            Some(Err(err)) => {
+                tracing::warn!("cannot open {}: {}, reporting to the log", other, err);
+                return Arc::new(LogReporter { sink: None });
+            }
+            None => {
+                tracing::warn!("unknown ERROR_REPORTER {:?}, reporting to the log", other);
+                return Arc::new(LogReporter { sink: None });
+            }
+        },
+    };
+    Arc::new(LogReporter {
+        sink: Some(Mutex::new(sink)),
+    })
+}
+
+//Middleware (inside context_middleware)
+//hands 5xx AppErrors to the configured reporter, once per response
+pub async fn error_report_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method: Method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let request_id = request
+        .extensions()
+        .get::<RequestScope>()
+        .and_then(|scope| scope.get::<RequestContext>())
+        .map(|context| context.request_id);
+    let mut response = next.run(request).await;
+    if let Some(error) = response.extensions_mut().remove::<ReportedError>()
+        && response.status() >= StatusCode::INTERNAL_SERVER_ERROR
+    {
+        state.reporter.report(&ErrorEvent {
+            status: response.status().as_u16(),
+            code: error.code,
+            message: &error.message,
+            request_id: request_id.as_deref(),
+            method: method.as_str(),
+            path: &path,
+        });
+    }
+    response
+}
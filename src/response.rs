@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{budget, error::AppError, json, response_limit, vary};
+
+//never echoed back to the client
+const SENSITIVE_HEADERS: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    HeaderName::from_static("x-api-key"),
+];
+
+//Cache-Control directive for handler responses
+//e.g. `(StatusCode::OK, CacheControl::NoStore, Json(body))`
+#[derive(Debug, Clone, Copy)]
+pub enum CacheControl {
+    //mutating endpoints: never cached by intermediaries
+    NoStore,
+    //read endpoints: cacheable for N seconds
+    MaxAge(u64),
+}
+
+impl IntoResponseParts for CacheControl {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let value = match self {
+            Self::NoStore => HeaderValue::from_static("no-store"),
+            Self::MaxAge(seconds) => HeaderValue::from_str(&format!("max-age={}", seconds))
+                .expect("max-age is a valid header value"),
+        };
+        res.headers_mut().insert(header::CACHE_CONTROL, value);
+        Ok(res)
+    }
+}
+
+//204 No Content: no body and no Content-Type
+//(e.g. `(CacheControl::NoStore, NoContent)`)
+#[derive(Debug, Clone, Copy)]
+pub struct NoContent;
+
+impl IntoResponse for NoContent {
+    fn into_response(self) -> Response {
+        StatusCode::NO_CONTENT.into_response()
+    }
+}
+
+//whether a write created the resource or changed an existing one
+//e.g. `(outcome.status(), outcome, Json(body))`
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    //201 with `Location: <path>`
+    Created(String),
+    Updated,
+}
+
+impl Outcome {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Created(_) => StatusCode::CREATED,
+            Self::Updated => StatusCode::OK,
+        }
+    }
+}
+
+impl IntoResponseParts for Outcome {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        if let Self::Created(location) = self
+            && let Ok(location) = HeaderValue::from_str(&location)
+        {
+            res.headers_mut().insert(header::LOCATION, location);
+        }
+        Ok(res)
+    }
+}
+
+//JSON array streamed one item at a time (`[`, items separated by `,`, `]`).
+//items are serialized only when hyper polls for the next chunk, so a slow client
+//applies backpressure and memory stays at about one item
+pub struct JsonArray<I>(pub I);
+
+impl<I> IntoResponse for JsonArray<I>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Serialize,
+{
+    fn into_response(self) -> Response {
+        let chunks = futures_util::stream::unfold(
+            (self.0, true, false),
+            |(mut items, first, done)| async move {
+                if done {
+                    return None;
+                }
+                let mut chunk = Vec::new();
+                chunk.push(if first { b'[' } else { b',' });
+                match items.next() {
+                    Some(item) => {
+                        if let Err(err) = serde_json::to_writer(&mut chunk, &item) {
+                            //the status is already sent: abort the body
+                            tracing::error!("failed to serialize streamed item: {}", err);
+                            return Some((Err(err), (items, false, true)));
+                        }
+                    }
+                    None => {
+                        if !first {
+                            chunk.clear();
+                        }
+                        chunk.push(b']');
+                        return Some((Ok(Bytes::from(chunk)), (items, false, true)));
+                    }
+                }
+                Some((Ok(Bytes::from(chunk)), (items, false, false)))
+            },
+        );
+        (
+            [(header::CONTENT_TYPE, "application/json")],
+            Body::from_stream(chunks),
+        )
+            .into_response()
+    }
+}
+
+//partial response: keeps only the requested top-level fields (`?fields=a,b`)
+pub fn select_fields<T: Serialize>(data: &T, fields: Option<&str>) -> Result<Value, AppError> {
+    let value = serde_json::to_value(data)?;
+    let Some(fields) = fields else {
+        return Ok(value);
+    };
+    let mut object = match value {
+        Value::Object(object) => object,
+        value => return Ok(value),
+    };
+    let mut selected = Map::new();
+    for field in fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+    {
+        if selected.contains_key(field) {
+            continue;
+        }
+        let Some(value) = object.remove(field) else {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "UNKNOWN_FIELD",
+                format!("unknown field {:?}", field),
+            ));
+        };
+        selected.insert(field.to_string(), value);
+    }
+    Ok(Value::Object(selected))
+}
+
+//request headers without the sensitive ones (repeated headers are joined with `, `)
+pub fn echo_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut echoed: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in headers {
+        if SENSITIVE_HEADERS.contains(name) {
+            continue;
+        }
+        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+        echoed
+            .entry(name.to_string())
+            .and_modify(|joined| {
+                joined.push_str(", ");
+                joined.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    echoed
+}
+
+//RFC 7230 §6.1 hop-by-hop headers (`Trailer` is kept: hyper only sends the trailers it declares)
+const HOP_BY_HOP_HEADERS: [HeaderName; 6] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::TE,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+//Middleware
+//removes hop-by-hop headers (and those listed in `Connection`) set by handlers or copied
+//from a downstream response; hyper adds its own connection headers afterwards
+pub async fn hop_by_hop_middleware(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        return response;
+    }
+    let headers = response.headers_mut();
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect();
+    for name in HOP_BY_HOP_HEADERS.iter().chain(&listed) {
+        if headers.remove(name).is_some() {
+            tracing::debug!("stripped hop-by-hop response header {}", name);
+        }
+    }
+    response
+}
+
+//Middleware
+//no endpoint serves partial content, so `Range` is ignored (full 200) and responses
+//say so with `Accept-Ranges: none`, unless a handler declared its own range support
+pub async fn accept_ranges_middleware(request: Request, next: Next) -> Response {
+    let ranged = request.headers().contains_key(header::RANGE);
+    let mut response = next.run(request).await;
+    if ranged {
+        tracing::debug!("ignored Range header ({})", response.status());
+    }
+    if response.status() != StatusCode::PARTIAL_CONTENT
+        && !response.headers().contains_key(header::ACCEPT_RANGES)
+    {
+        response
+            .headers_mut()
+            .insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+    }
+    response
+}
+
+pub const X_PRETTY: &str = "x-pretty";
+
+//Middleware
+//`?pretty=true` or `X-Pretty: true` indents JSON responses (for humans debugging);
+//the default output stays compact
+pub async fn pretty_json_middleware(request: Request, next: Next) -> Response {
+    let pretty = request
+        .headers()
+        .get(X_PRETTY)
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"true"))
+        || request
+            .uri()
+            .query()
+            .is_some_and(|query| query.split('&').any(|pair| pair == "pretty=true"));
+    let buffered = budget::Buffered::of(request.extensions());
+    let mut response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !is_json
+        || http_body::Body::size_hint(response.body())
+            .exact()
+            .is_none()
+    {
+        return response;
+    }
+    //the query string is part of the cache key already, X-Pretty is not
+    vary::negotiated(&mut response, HeaderName::from_static(X_PRETTY));
+    if !pretty {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => return response_limit::body_error(err).into_response(),
+    };
+    let pretty = json::pretty(&bytes);
+    buffered.add(bytes.len() + pretty.len());
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(pretty.len()));
+    Response::from_parts(parts, Body::from(pretty))
+}
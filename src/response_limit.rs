@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+
+use crate::{error::AppError, state::AppState};
+
+//Middleware
+//caps the response body at MAX_RESPONSE_BYTES
+//known sizes are rejected up front, streamed bodies are counted as they are sent
+pub async fn response_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let cap = state.config.max_response_bytes;
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+
+    if let Some(size) = response.body().size_hint().exact() {
+        if size > cap as u64 {
+            tracing::error!("{}: response body of {} bytes exceeds {}", path, size, cap);
+            return Err(too_large(cap));
+        }
+        return Ok(response);
+    }
+    Ok(response.map(|body| {
+        Body::new(Limited::new(body, cap).map_err(move |err| {
+            tracing::error!("{}: streamed response body exceeds {}: {}", path, cap, err);
+            err
+        }))
+    }))
+}
+
+//body read error (e.g. while buffering a response) => AppError
+pub fn body_error(err: axum::Error) -> AppError {
+    let err = err.into_inner();
+    if err.downcast_ref::<LengthLimitError>().is_some() {
+        //the cap isn't known here; the limit middleware already logged it
+        return AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "RESPONSE_TOO_LARGE",
+            "response body is too large",
+        );
+    }
+    AppError::from(anyhow::anyhow!(err))
+}
+
+fn too_large(cap: usize) -> AppError {
+    AppError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "RESPONSE_TOO_LARGE",
+        format!("response body exceeds {} bytes", cap),
+    )
+}
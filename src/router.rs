@@ -0,0 +1,266 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    Json, Router,
+    extract::{FromRequestParts, MatchedPath, RawPathParams, Request},
+    http::{HeaderValue, Method},
+    middleware::{Next, map_response},
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, Route, get},
+};
+use serde::Serialize;
+use tower::{Layer, Service};
+use utoipa::openapi::{Deprecated, OpenApi};
+
+use crate::{config::Config, deprecation::DeprecatedRoute, ratelimit::RateLimiter};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteInfo {
+    //full path, including the prefixes of nested trees
+    pub path: String,
+    pub methods: Vec<String>,
+    pub handler: &'static str,
+}
+
+pub const X_HANDLER: &str = "x-handler";
+
+//Router wrapper that records each route as it is added
+//(axum doesn't expose the registered routes)
+#[derive(Default)]
+pub struct RouteRecorder {
+    router: Router<()>,
+    routes: Vec<RouteInfo>,
+    tag_handlers: bool,
+}
+
+impl RouteRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //adds `X-Handler: <handler>` to the responses of routes added afterwards (dev only)
+    pub fn tag_handlers(mut self, enabled: bool) -> Self {
+        self.tag_handlers = enabled;
+        self
+    }
+
+    pub fn route(
+        mut self,
+        path: &'static str,
+        methods: &[Method],
+        handler: &'static str,
+        method_router: MethodRouter<()>,
+    ) -> Self {
+        self.routes.push(RouteInfo {
+            path: path.to_string(),
+            methods: methods.iter().map(|method| method.to_string()).collect(),
+            handler,
+        });
+        let method_router = if self.tag_handlers {
+            method_router.layer(map_response(move |mut response: Response| async move {
+                response
+                    .headers_mut()
+                    .insert(X_HANDLER, HeaderValue::from_static(handler));
+                response
+            }))
+        } else {
+            method_router
+        };
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    //wraps the routes added so far (e.g. the middleware of one API version's tree)
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.layer(layer);
+        self
+    }
+
+    //mounts `tree` under `prefix` (Router::nest), recording its routes with the prefix
+    pub fn nest(mut self, prefix: &'static str, tree: RouteRecorder) -> Self {
+        self.routes
+            .extend(tree.routes.into_iter().map(|route| RouteInfo {
+                path: format!("{}{}", prefix, route.path),
+                ..route
+            }));
+        self.router = self.router.nest(prefix, tree.router);
+        self
+    }
+
+    //GET <path> returns the recorded routes as JSON (register it last)
+    pub fn with_routes_endpoint(mut self, path: &'static str) -> Self {
+        self.routes.push(RouteInfo {
+            path: path.to_string(),
+            methods: vec![Method::GET.to_string()],
+            handler: "routes_handler",
+        });
+        let routes: Arc<Vec<RouteInfo>> = Arc::new(self.routes.clone());
+        self.router = self.router.route(
+            path,
+            get(move || async move { Json(routes.as_ref().clone()) }),
+        );
+        self
+    }
+
+    pub fn routes(&self) -> &[RouteInfo] {
+        &self.routes
+    }
+
+    pub fn into_router(self) -> Router<()> {
+        self.router
+    }
+}
+
+//per-route metadata precomputed once in build_router, looked up by MatchedPath
+#[derive(Debug, Clone)]
+pub struct RouteMeta {
+    pub template: String,
+    pub handler: &'static str,
+    pub openapi_path: String,
+    //keyed by lowercase method; empty for routes missing from ApiDoc
+    pub operations: HashMap<String, OperationMeta>,
+    pub deprecated: Option<DeprecatedRoute>,
+    pub replay_protected: bool,
+    //ROUTE_TIMEOUTS override (None: REQUEST_TIMEOUT_SECS)
+    pub timeout: Option<Duration>,
+    //ROUTE_RATE_LIMITS entry, else RATE_LIMIT_DEFAULT (None: unlimited)
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    //CACHE_POLICIES entry (None: whatever the handler sets)
+    pub cache_control: Option<HeaderValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperationMeta {
+    pub operation_id: Option<String>,
+    pub tags: Vec<String>,
+    pub deprecated: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RouteTable(HashMap<String, RouteMeta>);
+
+impl RouteTable {
+    pub fn build(routes: &[RouteInfo], openapi: &OpenApi, config: &Config) -> Self {
+        let table = routes
+            .iter()
+            .map(|route| {
+                let openapi_path = openapi_path(&route.path);
+                let operations = openapi
+                    .paths
+                    .paths
+                    .get(&openapi_path)
+                    .map(|item| {
+                        item.operations
+                            .iter()
+                            .filter_map(|(kind, operation)| {
+                                let method = serde_json::to_value(kind).ok()?.as_str()?.to_string();
+                                Some((
+                                    method,
+                                    OperationMeta {
+                                        operation_id: operation.operation_id.clone(),
+                                        tags: operation.tags.clone().unwrap_or_default(),
+                                        deprecated: matches!(
+                                            operation.deprecated,
+                                            Some(Deprecated::True)
+                                        ),
+                                    },
+                                ))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let meta = RouteMeta {
+                    template: route.path.clone(),
+                    handler: route.handler,
+                    openapi_path,
+                    operations,
+                    deprecated: config
+                        .deprecated_routes
+                        .iter()
+                        .find(|deprecated| deprecated.path == route.path)
+                        .cloned(),
+                    replay_protected: config.replay_protected_routes.contains(&route.path),
+                    timeout: config
+                        .route_timeouts
+                        .iter()
+                        .find(|timeout| timeout.path == route.path)
+                        .map(|timeout| timeout.timeout),
+                    rate_limit: config
+                        .route_rate_limits
+                        .iter()
+                        .find(|limit| limit.path == route.path)
+                        .map(|limit| limit.rate)
+                        .or(config.rate_limit_default)
+                        .filter(|rate| rate.per_minute > 0)
+                        .map(|rate| RateLimiter::new(&route.path, rate.per_minute, rate.burst)),
+                    cache_control: config
+                        .cache_policies
+                        .iter()
+                        .find(|policy| policy.path == route.path)
+                        .map(|policy| policy.cache_control.clone()),
+                };
+                (route.path.clone(), meta)
+            })
+            .collect();
+        Self(table)
+    }
+
+    pub fn get(&self, template: &str) -> Option<&RouteMeta> {
+        self.0.get(template)
+    }
+
+    //metadata of the route a request matched (route layers only)
+    pub fn matched(&self, request: &Request) -> Option<&RouteMeta> {
+        let matched = request.extensions().get::<MatchedPath>()?;
+        self.get(matched.as_str())
+    }
+}
+
+//axum route (`/sample/:path`) => OpenAPI path (`/sample/{path}`)
+pub fn openapi_path(route: &str) -> String {
+    route
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{}}}", name),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+//Middleware (route_layer: runs only after a route matched)
+//dumps the routing decision at trace level
+pub async fn trace_routing_middleware(request: Request, next: Next) -> Response {
+    if !tracing::enabled!(tracing::Level::TRACE) {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+    let route = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string());
+    let params: Vec<(String, String)> =
+        match RawPathParams::from_request_parts(&mut parts, &()).await {
+            Ok(params) => params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+    tracing::trace!(
+        route = ?route,
+        params = ?params,
+        query = parts.uri.query().unwrap_or(""),
+        "matched route: {} {}",
+        parts.method,
+        parts.uri.path()
+    );
+    next.run(Request::from_parts(parts, body)).await
+}
@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use axum::{
+    http::{Method, StatusCode},
+    middleware,
+    response::IntoResponse,
+    routing::{get, post, put},
+};
+use utoipa::openapi::OpenApi;
+
+use crate::{
+    api_version::{self, ApiVersion},
+    apikey::{self, RequiredScopes, Scope},
+    bulkhead::{self, Bulkhead},
+    error::AppError,
+    files, message,
+    middleware::{AUTHENTICATED, HEAVY_LOGGING, IDEMPOTENT, MiddlewareStacks},
+    note,
+    response::CacheControl,
+    router::{self, RouteRecorder},
+    state::AppState,
+    upload, ws,
+};
+
+pub mod sample;
+
+//a versioned route tree
+struct ApiTree {
+    version: &'static str,
+    prefix: &'static str,
+    //URL of the version's OpenAPI document
+    doc: &'static str,
+    build: fn(&Arc<AppState>, &MiddlewareStacks) -> RouteRecorder,
+}
+
+//each tree brings its own middleware (RouteRecorder::layer) on top of the shared stack,
+//and its routes opt into the named stacks of MiddlewareStacks::standard
+const TREES: [ApiTree; 2] = [
+    ApiTree {
+        version: "1",
+        prefix: "/api/v1",
+        doc: "/api-docs/v1/openapi.json",
+        build: v1,
+    },
+    ApiTree {
+        version: "2",
+        prefix: "/api/v2",
+        doc: "/api-docs/v2/openapi.json",
+        build: v2,
+    },
+];
+
+//a mounted tree, for the API documents
+pub struct Mounted {
+    pub version: &'static str,
+    pub prefix: &'static str,
+    pub doc: &'static str,
+    //OpenAPI paths relative to the prefix
+    paths: Vec<String>,
+}
+
+//nests the trees of the versions listed in API_VERSIONS under their prefixes
+pub fn mount(mut routes: RouteRecorder, state: &Arc<AppState>) -> (RouteRecorder, Vec<Mounted>) {
+    let mut mounted = Vec::new();
+    let stacks = MiddlewareStacks::standard(state);
+    for tree in TREES {
+        if !state.config.api_versions.iter().any(|v| v == tree.version) {
+            continue;
+        }
+        let recorder = (tree.build)(state, &stacks).layer(middleware::from_fn_with_state(
+            ApiVersion::mounted(tree.version, tree.prefix),
+            api_version::mounted_version_middleware,
+        ));
+        mounted.push(Mounted {
+            version: tree.version,
+            prefix: tree.prefix,
+            doc: tree.doc,
+            paths: recorder
+                .routes()
+                .iter()
+                .map(|route| router::openapi_path(&route.path))
+                .collect(),
+        });
+        routes = routes.nest(tree.prefix, recorder);
+    }
+    (routes, mounted)
+}
+
+//v1: every resource
+fn v1(state: &Arc<AppState>, stacks: &MiddlewareStacks) -> RouteRecorder {
+    // Bulkheads
+    let sample_bulkhead = middleware::from_fn_with_state(
+        Bulkhead::new("sample", state.config.bulkhead_sample),
+        bulkhead::bulkhead_middleware,
+    );
+    let raw_bulkhead = middleware::from_fn_with_state(
+        Bulkhead::new("raw", state.config.bulkhead_raw),
+        bulkhead::bulkhead_middleware,
+    );
+
+    // API key scopes of the resource methods (the other routes take any valid key)
+    let read_scope = middleware::from_fn_with_state(
+        RequiredScopes(&[Scope::Read]),
+        apikey::require_scopes_middleware,
+    );
+    let write_scope = middleware::from_fn_with_state(
+        RequiredScopes(&[Scope::Write]),
+        apikey::require_scopes_middleware,
+    );
+
+    let routes = RouteRecorder::new()
+        .tag_handlers(state.config.dev_mode)
+        .route(
+            "/sample/:path",
+            &[Method::POST],
+            "sample_handler",
+            stacks.apply(
+                &[IDEMPOTENT, HEAVY_LOGGING],
+                post(sample::sample_handler)
+                    .with_state(state.clone())
+                    .layer(sample_bulkhead),
+            ),
+        )
+        .route(
+            "/sample/:path/raw",
+            &[Method::POST],
+            "raw_sample_handler",
+            stacks.apply(
+                &[HEAVY_LOGGING],
+                post(sample::raw_sample_handler)
+                    .with_state(state.clone())
+                    .layer(raw_bulkhead.clone()),
+            ),
+        )
+        .route(
+            "/sample/:path/list",
+            &[Method::GET],
+            "list_sample_handler",
+            stacks.apply(&[HEAVY_LOGGING], get(sample::list_sample_handler)),
+        )
+        .route(
+            "/sample/:path/page",
+            &[Method::GET],
+            "page_sample_handler",
+            stacks.apply(
+                &[HEAVY_LOGGING],
+                get(sample::page_sample_handler).with_state(state.clone()),
+            ),
+        )
+        .route(
+            "/sample/:path/slow",
+            &[Method::GET],
+            "slow_sample_handler",
+            stacks.apply(&[HEAVY_LOGGING], get(sample::slow_sample_handler)),
+        )
+        .route(
+            "/sample/:path/upload",
+            &[Method::POST],
+            "upload_handler",
+            post(upload::upload_handler)
+                .with_state(state.clone())
+                .layer(raw_bulkhead.clone()),
+        )
+        .route(
+            "/upload",
+            &[Method::POST],
+            "store_upload_handler",
+            post(upload::store_upload_handler)
+                .with_state(state.clone())
+                .layer(raw_bulkhead.clone()),
+        )
+        .route(
+            "/files/:name",
+            &[Method::GET],
+            "download_handler",
+            get(files::download_handler).with_state(state.clone()),
+        )
+        .route(
+            "/sample/:path/stream",
+            &[Method::POST],
+            "stream_sample_handler",
+            stacks.apply(
+                &[HEAVY_LOGGING],
+                post(sample::stream_sample_handler).layer(raw_bulkhead),
+            ),
+        )
+        .route(
+            "/sample/:path/note",
+            &[Method::GET, Method::PUT, Method::DELETE],
+            "note_handler",
+            stacks.apply(
+                &[AUTHENTICATED, HEAVY_LOGGING],
+                get(note::get_note_handler)
+                    .layer(read_scope.clone())
+                    .merge(
+                        put(note::put_note_handler)
+                            .delete(note::delete_note_handler)
+                            .layer(write_scope.clone()),
+                    )
+                    .with_state(state.clone()),
+            ),
+        )
+        .route(
+            "/ws",
+            &[Method::GET],
+            "ws_handler",
+            get(ws::ws_handler).with_state(state.clone()),
+        );
+    messages(routes, state, stacks)
+}
+
+//v2: only the messages so far (the sample endpoints stay v1-only)
+fn v2(state: &Arc<AppState>, stacks: &MiddlewareStacks) -> RouteRecorder {
+    messages(
+        RouteRecorder::new().tag_handlers(state.config.dev_mode),
+        state,
+        stacks,
+    )
+}
+
+fn messages(
+    routes: RouteRecorder,
+    state: &Arc<AppState>,
+    stacks: &MiddlewareStacks,
+) -> RouteRecorder {
+    let read_scope = middleware::from_fn_with_state(
+        RequiredScopes(&[Scope::Read]),
+        apikey::require_scopes_middleware,
+    );
+    let write_scope = middleware::from_fn_with_state(
+        RequiredScopes(&[Scope::Write]),
+        apikey::require_scopes_middleware,
+    );
+    routes
+        .route(
+            "/messages",
+            &[Method::GET, Method::POST],
+            "messages_handler",
+            stacks.apply(
+                &[AUTHENTICATED, IDEMPOTENT, HEAVY_LOGGING],
+                get(message::list_messages_handler)
+                    .layer(read_scope.clone())
+                    .merge(post(message::create_message_handler).layer(write_scope.clone()))
+                    .with_state(state.clone()),
+            ),
+        )
+        .route(
+            "/messages/:id",
+            &[Method::GET, Method::PUT, Method::DELETE],
+            "message_handler",
+            stacks.apply(
+                &[AUTHENTICATED, HEAVY_LOGGING],
+                get(message::get_message_handler)
+                    .layer(read_scope)
+                    .merge(
+                        put(message::update_message_handler)
+                            .delete(message::delete_message_handler)
+                            .layer(write_scope),
+                    )
+                    .with_state(state.clone()),
+            ),
+        )
+}
+
+//ApiDoc lists the tree handlers under their relative paths. the combined document
+//moves them under every prefix they are mounted at (operation ids get a `v<N>_`
+//prefix to stay unique); the unversioned (ops) paths stay as they are
+pub fn combined_doc(doc: &OpenApi, mounted: &[Mounted]) -> OpenApi {
+    let mut combined = doc.clone();
+    let relative: Vec<&String> = mounted.iter().flat_map(|tree| &tree.paths).collect();
+    combined
+        .paths
+        .paths
+        .retain(|path, _| !relative.contains(&path));
+    for tree in mounted {
+        for path in &tree.paths {
+            let Some(mut item) = doc.paths.paths.get(path).cloned() else {
+                continue;
+            };
+            for operation in item.operations.values_mut() {
+                operation.operation_id = operation
+                    .operation_id
+                    .take()
+                    .map(|id| format!("v{}_{}", tree.version, id));
+            }
+            combined
+                .paths
+                .paths
+                .insert(format!("{}{}", tree.prefix, path), item);
+        }
+    }
+    combined
+}
+
+//the combined document without the other versions' trees
+pub fn version_doc(combined: &OpenApi, mounted: &[Mounted], version: &str) -> OpenApi {
+    let mut doc = combined.clone();
+    doc.paths.paths.retain(|path, _| {
+        mounted
+            .iter()
+            .find(|tree| path.starts_with(&format!("{}/", tree.prefix)))
+            .is_none_or(|tree| tree.version == version)
+    });
+    doc.info.version = format!("{} (API v{})", doc.info.version, version);
+    doc
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "Sample",
+    responses(
+        (status = 200, description = "OK"),
+        (status = 500, description = "Internal Server Error", body = ResponseError),
+    ),
+)]
+pub async fn ping_handler() -> Result<impl IntoResponse, AppError> {
+    Ok((StatusCode::OK, CacheControl::MaxAge(60), "pong".to_string()).into_response())
+}
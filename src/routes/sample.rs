@@ -0,0 +1,280 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::{
+    api_version::ApiVersion,
+    auth::CurrentUser,
+    body,
+    checksum::{self, ChecksumBody},
+    context::RequestContext,
+    cursor::{Cursor, CursorPage},
+    error::AppError,
+    extract::{StrictQuery, Timed, ValidatedJson, ValidatedPath},
+    model::{
+        ListQuery, MAX_LIST_COUNT, MAX_PAGE_LIMIT, MAX_SLOW_DELAY_MS, PageQuery, RequestData,
+        ResponseData, SAMPLE_PAGE_ITEMS, SamplePath, SampleQuery, SlowQuery,
+    },
+    response::{self, CacheControl, JsonArray, Outcome},
+    state::AppState,
+};
+
+//Handler
+#[utoipa::path(
+    post,
+    path = "/sample/{path}",
+    tag = "Sample",
+    params(
+        SamplePath,
+        SampleQuery,
+        ("Idempotency-Key" = Option<String>, Header, description = "retries with the same key (and body) get the first response, marked Idempotent-Replayed: true"),
+    ),
+    request_body(
+        description = "RequestData",
+        content = RequestData,
+    ),
+    responses(
+        (status = 201, description = "Created: first POST to this path (Location header set)", body = ResponseData),
+        (status = 200, description = "OK: echo of an existing path (only the requested fields with ?fields=)", body = ResponseData),
+        (status = 400, description = "malformed JSON body, unknown or invalid query parameters", body = ResponseError),
+        (status = 409, description = "a request with the Idempotency-Key is still running", body = ResponseError),
+        (status = 422, description = "Invalid fields (listed in `fields`), or the Idempotency-Key was used with another body", body = ResponseError),
+        (status = 500, description = "Internal Server Error", body = ResponseError),
+    ),
+)]
+//one extractor per input, axum handlers take them as arguments
+#[allow(clippy::too_many_arguments)]
+pub async fn sample_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    StrictQuery(SampleQuery {
+        query,
+        fields,
+        echo_headers,
+    }): StrictQuery<SampleQuery>,
+    context: RequestContext,
+    user: Option<CurrentUser>,
+    api_version: Option<Extension<ApiVersion>>,
+    headers: HeaderMap,
+    Timed(ValidatedJson(body)): Timed<ValidatedJson<RequestData>>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let (api_version, root) = match api_version {
+        Some(Extension(ApiVersion { version, root })) => (Some(version), root),
+        None => (None, String::new()),
+    };
+    let query = query.unwrap_or_else(|| state.config.sample_query_default.clone());
+    tracing::info!(
+        request_id = %context.request_id,
+        api_version = ?api_version,
+        client_ip = ?context.client_ip,
+        user = ?user.as_ref().map(|CurrentUser(claims)| claims.sub.as_str()),
+        locale = ?context.locale,
+        since_start = ?context.started_at.elapsed(),
+        "path: {}, query: {}, body: {{ name: {}, message: {} }}",
+        path,
+        query,
+        body.name,
+        body.message
+    );
+    let result: ResponseData = ResponseData {
+        message: format!(
+            "path: {}, query: {}, body: {{ name: {}, message: {} }}",
+            path, query, body.name, body.message
+        ),
+        headers: echo_headers.then(|| response::echo_headers(&headers)),
+        ..Default::default()
+    };
+    let result = response::select_fields(&result, fields.as_deref())?;
+    let outcome = if state.samples.lock().unwrap().insert(path) {
+        Outcome::Created(format!("{}/sample/{}", root, path))
+    } else {
+        Outcome::Updated
+    };
+    Ok((
+        outcome.status(),
+        outcome,
+        CacheControl::NoStore,
+        Json(result),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/sample/{path}/raw",
+    tag = "Sample",
+    params(SamplePath),
+    request_body(
+        description = "arbitrary bytes",
+        content = String,
+        content_type = "application/octet-stream",
+    ),
+    responses(
+        (status = 200, description = "OK", body = ResponseData),
+        (status = 413, description = "body over BODY_LIMIT", body = ResponseError),
+        (status = 500, description = "Internal Server Error", body = ResponseError),
+    ),
+)]
+pub async fn raw_sample_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    body: Body,
+) -> Result<impl IntoResponse + Send, AppError> {
+    //read here rather than with the Bytes extractor, whose rejection isn't our JSON error
+    let body = body::read_request_body(body, state.config.body_limit).await?;
+    let sha256: String = checksum::sha256_hex(&body);
+    tracing::info!(
+        "path: {}, body: {} bytes, sha256: {}",
+        path,
+        body.len(),
+        sha256
+    );
+    let result: ResponseData = ResponseData {
+        message: format!("path: {}, body: {} bytes", path, body.len()),
+        length: Some(body.len()),
+        sha256: Some(sha256),
+        ..Default::default()
+    };
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(result)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/sample/{path}/list",
+    tag = "Sample",
+    params(SamplePath, ListQuery),
+    responses(
+        (status = 200, description = "JSON array streamed item by item", body = [ResponseData]),
+        (status = 400, description = "Bad Request", body = ResponseError),
+    ),
+)]
+pub async fn list_sample_handler(
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    StrictQuery(ListQuery { count }): StrictQuery<ListQuery>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    if count > MAX_LIST_COUNT {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "COUNT_TOO_LARGE",
+            format!("count must be at most {}, got {}", MAX_LIST_COUNT, count),
+        ));
+    }
+    tracing::info!("path: {}, streaming {} items", path, count);
+    let items = (0..count).map(move |index| ResponseData {
+        message: format!("path: {}, item: {}", path, index),
+        ..Default::default()
+    });
+    Ok((StatusCode::OK, CacheControl::NoStore, JsonArray(items)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/sample/{path}/page",
+    tag = "Sample",
+    params(SamplePath, PageQuery),
+    responses(
+        (status = 200, description = "Success", body = ResponseDataPage),
+        (status = 400, description = "Bad Request", body = ResponseError),
+    ),
+)]
+pub async fn page_sample_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    StrictQuery(PageQuery { cursor, limit }): StrictQuery<PageQuery>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    if limit == 0 || limit > MAX_PAGE_LIMIT {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_LIMIT",
+            format!("limit must be in 1..={}, got {}", MAX_PAGE_LIMIT, limit),
+        ));
+    }
+    let scope = format!("/sample/{}/page", path);
+    let start = match cursor {
+        Some(cursor) => Cursor::decode(&cursor, &state.cursor_key, &scope)?.offset,
+        None => 0,
+    };
+    let end = start.saturating_add(limit).min(SAMPLE_PAGE_ITEMS);
+    let items = (start..end)
+        .map(|index| ResponseData {
+            message: format!("path: {}, item: {}", path, index),
+            ..Default::default()
+        })
+        .collect();
+    let next_cursor =
+        (end < SAMPLE_PAGE_ITEMS).then(|| Cursor::new(end).encode(&state.cursor_key, &scope));
+    let page = CursorPage { items, next_cursor };
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(page)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/sample/{path}/slow",
+    tag = "Sample",
+    params(SamplePath, SlowQuery),
+    responses(
+        (status = 200, description = "OK, after the delay", body = ResponseData),
+        (status = 400, description = "Bad Request", body = ResponseError),
+        (status = 503, description = "the delay exceeded the route's timeout (HANDLER_TIMEOUT)", body = ResponseError),
+    ),
+)]
+pub async fn slow_sample_handler(
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    StrictQuery(SlowQuery { delay_ms }): StrictQuery<SlowQuery>,
+) -> Result<impl IntoResponse + Send, AppError> {
+    if delay_ms > MAX_SLOW_DELAY_MS {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "DELAY_TOO_LONG",
+            format!(
+                "delay_ms must be at most {}, got {}",
+                MAX_SLOW_DELAY_MS, delay_ms
+            ),
+        ));
+    }
+    tracing::info!("path: {}, sleeping {}ms", path, delay_ms);
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    let result = ResponseData {
+        message: format!("path: {}, slept {}ms", path, delay_ms),
+        ..Default::default()
+    };
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(result)).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/sample/{path}/stream",
+    tag = "Sample",
+    params(SamplePath),
+    request_body(
+        description = "arbitrary bytes",
+        content = String,
+        content_type = "application/octet-stream",
+    ),
+    responses(
+        (status = 200, description = "the request body streamed back, followed by an `X-Checksum: sha256=<hex>` trailer (requires `TE: trailers`)"),
+        (status = 500, description = "Internal Server Error", body = ResponseError),
+    ),
+)]
+pub async fn stream_sample_handler(
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    body: Body,
+) -> Result<impl IntoResponse + Send, AppError> {
+    tracing::info!("path: {}, streaming body back", path);
+    Ok((
+        StatusCode::OK,
+        CacheControl::NoStore,
+        [
+            (header::CONTENT_TYPE, "application/octet-stream"),
+            (header::TRAILER, checksum::X_CHECKSUM.as_str()),
+        ],
+        Body::new(ChecksumBody::new(body)),
+    )
+        .into_response())
+}
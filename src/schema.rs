@@ -0,0 +1,244 @@
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use serde_json::Value;
+use utoipa::openapi::OpenApi;
+
+use crate::{budget, error::AppError, response_limit, router};
+
+//RESPONSE_VALIDATION: off | log | fail (fail answers mismatches with 500)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseValidation {
+    Off,
+    Log,
+    Fail,
+}
+
+impl FromStr for ResponseValidation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "log" => Ok(Self::Log),
+            "fail" => Ok(Self::Fail),
+            _ => Err(format!("expected off, log or fail, got {:?}", value)),
+        }
+    }
+}
+
+//checks JSON responses against the schema ApiDoc declares for the matched route
+#[derive(Debug)]
+pub struct SchemaValidator {
+    mode: ResponseValidation,
+    spec: Value,
+}
+
+impl SchemaValidator {
+    pub fn new(mode: ResponseValidation, openapi: &OpenApi) -> Arc<Self> {
+        Arc::new(Self {
+            mode,
+            spec: serde_json::to_value(openapi).unwrap_or_default(),
+        })
+    }
+
+    fn response_schema(&self, route: &str, method: &str, status: StatusCode) -> Option<&Value> {
+        self.spec
+            .get("paths")?
+            .get(router::openapi_path(route))?
+            .get(method.to_ascii_lowercase())?
+            .get("responses")?
+            .get(status.as_str())?
+            .get("content")?
+            .get("application/json")?
+            .get("schema")
+    }
+
+    //`#/components/schemas/X` => the component schema
+    fn resolve<'a>(&'a self, schema: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.spec.pointer(pointer))
+                .unwrap_or(schema),
+            None => schema,
+        }
+    }
+
+    //`partial`: ?fields= responses may leave out required fields
+    fn check(&self, value: &Value, schema: &Value, at: &str, partial: bool) -> Vec<String> {
+        let schema = self.resolve(schema);
+        let mut errors = Vec::new();
+        if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+            if value.is_null() && is_nullable(schema) {
+                return errors;
+            }
+            for schema in all_of {
+                errors.extend(self.check(value, schema, at, partial));
+            }
+            return errors;
+        }
+        if let Some(one_of) = schema
+            .get("oneOf")
+            .or_else(|| schema.get("anyOf"))
+            .and_then(Value::as_array)
+        {
+            if !one_of
+                .iter()
+                .any(|schema| self.check(value, schema, at, partial).is_empty())
+            {
+                errors.push(format!("{}: matches none of the alternatives", at));
+            }
+            return errors;
+        }
+        if value.is_null() {
+            if !is_nullable(schema) && schema.get("type").is_some() {
+                errors.push(format!("{}: null is not allowed", at));
+            }
+            return errors;
+        }
+
+        match schema.get("type").and_then(Value::as_str) {
+            Some("object") => {
+                let Some(object) = value.as_object() else {
+                    errors.push(format!("{}: expected an object", at));
+                    return errors;
+                };
+                let properties = schema.get("properties").and_then(Value::as_object);
+                let additional = schema.get("additionalProperties");
+                for (key, field) in object {
+                    let at = format!("{}.{}", at, key);
+                    match (
+                        properties.and_then(|properties| properties.get(key)),
+                        additional,
+                    ) {
+                        (Some(schema), _) => errors.extend(self.check(field, schema, &at, partial)),
+                        (None, Some(Value::Bool(true))) => {}
+                        (None, Some(schema)) if schema.is_object() => {
+                            errors.extend(self.check(field, schema, &at, partial))
+                        }
+                        //the documented properties are treated as the complete set
+                        (None, _) if properties.is_some() => {
+                            errors.push(format!("{}: field is not in the schema", at))
+                        }
+                        (None, _) => {}
+                    }
+                }
+                if !partial {
+                    for required in schema
+                        .get("required")
+                        .and_then(Value::as_array)
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_str)
+                    {
+                        if !object.contains_key(required) {
+                            errors.push(format!("{}.{}: required field is missing", at, required));
+                        }
+                    }
+                }
+            }
+            Some("array") => match value.as_array() {
+                Some(items) => {
+                    if let Some(schema) = schema.get("items") {
+                        for (index, item) in items.iter().enumerate() {
+                            errors.extend(self.check(
+                                item,
+                                schema,
+                                &format!("{}[{}]", at, index),
+                                partial,
+                            ));
+                        }
+                    }
+                }
+                None => errors.push(format!("{}: expected an array", at)),
+            },
+            Some(expected @ ("string" | "integer" | "number" | "boolean")) => {
+                let ok = match expected {
+                    "string" => value.is_string(),
+                    "integer" => value.is_i64() || value.is_u64(),
+                    "number" => value.is_number(),
+                    _ => value.is_boolean(),
+                };
+                if !ok {
+                    errors.push(format!("{}: expected {}, got {}", at, expected, value));
+                }
+            }
+            _ => {}
+        }
+        errors
+    }
+}
+
+fn is_nullable(schema: &Value) -> bool {
+    schema.get("nullable").and_then(Value::as_bool) == Some(true)
+}
+
+//Middleware (route layer, needs MatchedPath; dev/test only)
+pub async fn response_schema_middleware(
+    State(validator): State<Arc<SchemaValidator>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+    else {
+        return Ok(next.run(request).await);
+    };
+    let method = request.method().to_string();
+    let partial = request
+        .uri()
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair.starts_with("fields=")));
+    let buffered = budget::Buffered::of(request.extensions());
+    let response = next.run(request).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    let Some(schema) = validator
+        .response_schema(&route, &method, response.status())
+        .filter(|_| is_json && response.body().size_hint().exact().is_some())
+        .cloned()
+    else {
+        return Ok(response);
+    };
+
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(response_limit::body_error)?;
+    buffered.add(bytes.len());
+    let errors = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) => validator.check(&value, &schema, "$", partial),
+        Err(err) => vec![format!("$: invalid JSON: {}", err)],
+    };
+    if !errors.is_empty() {
+        tracing::error!(
+            route,
+            method,
+            status = parts.status.as_u16(),
+            ?errors,
+            "response does not match the documented schema"
+        );
+        if validator.mode == ResponseValidation::Fail {
+            return Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "RESPONSE_SCHEMA_MISMATCH",
+                errors.join("; "),
+            ));
+        }
+    }
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
@@ -0,0 +1,30 @@
+use std::sync::{Arc, Mutex};
+
+use axum::http::Extensions;
+
+//typed per-request storage shared by the middleware and extractors of one request.
+//inserted into the request extensions by context_middleware; clones share the same map
+//(unlike request extensions, values written by inner layers are visible to outer ones)
+#[derive(Debug, Clone, Default)]
+pub struct RequestScope(Arc<Mutex<Extensions>>);
+
+impl RequestScope {
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.0.lock().unwrap().get::<T>().cloned()
+    }
+
+    pub fn set<T: Clone + Send + Sync + 'static>(&self, value: T) {
+        self.0.lock().unwrap().insert(value);
+    }
+
+    //edits the stored value in place, starting from T::default()
+    pub fn update<T: Clone + Default + Send + Sync + 'static>(&self, f: impl FnOnce(&mut T)) {
+        let mut map = self.0.lock().unwrap();
+        if map.get::<T>().is_none() {
+            map.insert(T::default());
+        }
+        if let Some(value) = map.get_mut::<T>() {
+            f(value);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    convert::Infallible, fmt, future::Future, path::Path, pin::Pin, sync::Arc, time::Duration,
+};
+
+use anyhow::Context;
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{StatusCode, header, uri::Authority},
+    response::{IntoResponse, Redirect, Response},
+};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::{OwnedSemaphorePermit, Semaphore, watch},
+    time::Instant,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+use tower::ServiceExt;
+
+use crate::{config::Config, error::AppError, reload::LiveApp};
+
+//request line versions hyper's HTTP/1 parser accepts on plain connections (HTTP/2
+//is only offered over TLS, via ALPN)
+const SUPPORTED_VERSIONS: [&str; 2] = ["HTTP/1.0", "HTTP/1.1"];
+
+//ALPN protocols offered with TLS, preferred first
+const ALPN_H2: &[u8] = b"h2";
+const ALPN_HTTP1: &[u8] = b"http/1.1";
+
+//request extension of requests that arrived over TLS (https::require_https_middleware)
+#[derive(Debug, Clone, Copy)]
+pub struct TlsConnection;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServerOptions {
+    //0 disables HTTP keep-alive; otherwise also the TCP keep-alive idle time
+    pub keepalive: Duration,
+    //time allowed to receive a complete request head. hyper also arms this timer
+    //while an idle keep-alive connection waits for its next request, so it bounds
+    //how long idle connections stay open
+    pub header_read_timeout: Duration,
+    //open connections at once (0 = unlimited); further ones wait in the listen backlog
+    pub max_connections: usize,
+}
+
+impl ServerOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            keepalive: config.keepalive,
+            header_read_timeout: config.header_read_timeout,
+            max_connections: config.max_connections,
+        }
+    }
+}
+
+//the listeners of the config: the app on PORT (plain, or TLS with TLS_CERT_PATH and
+//TLS_KEY_PATH) and the HTTP_REDIRECT_PORT redirect to it. returns once `shutdown`
+//completes (see serve)
+pub async fn run(
+    config: &Config,
+    app: Arc<LiveApp>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    let tls = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert), Some(key)) => Some(tls_acceptor(cert, key)?),
+        _ => None,
+    };
+    let listener = TcpListener::bind(config.addr()).await?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    tracing::info!("listening on {}://{}", scheme, listener.local_addr()?);
+    let redirect = if config.http_redirect_port != 0 {
+        let redirect_listener =
+            TcpListener::bind(format!("{}:{}", config.host, config.http_redirect_port)).await?;
+        tracing::info!(
+            "redirecting http://{} to https",
+            redirect_listener.local_addr()?
+        );
+        Some(tokio::spawn(redirect_to_https(
+            redirect_listener,
+            config.port,
+            ServerOptions::from_config(config),
+        )))
+    } else {
+        None
+    };
+    serve(
+        listener,
+        app,
+        ServerOptions::from_config(config),
+        tls,
+        shutdown,
+    )
+    .await;
+    //plain requests still arriving are told where to go until here
+    if let Some(redirect) = redirect {
+        redirect.abort();
+    }
+    Ok(())
+}
+
+//the certificate chain and private key (PEM), offering HTTP/2 and HTTP/1.1 via ALPN
+fn tls_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, anyhow::Error> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("TLS_CERT_PATH {}", cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("TLS_KEY_PATH {}", key.display()))?;
+    let mut tls = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("TLS_CERT_PATH / TLS_KEY_PATH")?;
+    tls.alpn_protocols = vec![ALPN_H2.to_vec(), ALPN_HTTP1.to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(tls)))
+}
+
+//Server (hyper, replaces axum::serve to expose connection settings; HTTP/1.1, and with
+//`tls` also HTTP/2 when the client negotiates it)
+//stops accepting once `shutdown` completes and asks open connections to close gracefully
+//(idle keep-alive connections close, busy ones after their current response); waiting
+//for those responses is left to the caller (lifecycle::drain)
+pub async fn serve(
+    listener: TcpListener,
+    app: Arc<LiveApp>,
+    options: ServerOptions,
+    tls: Option<TlsAcceptor>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let connections =
+        (options.max_connections > 0).then(|| Arc::new(Semaphore::new(options.max_connections)));
+    let (closing, _) = watch::channel(false);
+    tokio::pin!(shutdown);
+    loop {
+        let permit = tokio::select! {
+            permit = acquire(connections.as_ref(), options.max_connections) => permit,
+            _ = &mut shutdown => break,
+        };
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let (stream, addr) = match accepted {
+            Ok(connection) => connection,
+            Err(err) => {
+                tracing::error!("failed to accept connection: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        if !options.keepalive.is_zero() {
+            let keepalive = TcpKeepalive::new().with_time(options.keepalive);
+            if let Err(err) = SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+                tracing::warn!("failed to set TCP keep-alive for {}: {}", addr, err);
+            }
+        }
+
+        let app = app.clone();
+        let tls = tls.clone();
+        let closing = closing.subscribe();
+        tokio::spawn(async move {
+            //released when the connection closes
+            let _permit = permit;
+            let secure = tls.is_some();
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                //peer address for ConnectInfo<SocketAddr> (trusted proxy checks)
+                request.extensions_mut().insert(ConnectInfo(addr));
+                if secure {
+                    request.extensions_mut().insert(TlsConnection);
+                }
+                //the app current when the request arrived (see LiveApp)
+                let app = app.router();
+                async move { app.oneshot(request).await }
+            });
+            let Some(tls) = tls else {
+                if let Some(version) =
+                    unsupported_version(&stream, options.header_read_timeout).await
+                {
+                    reject_version(stream, &version).await;
+                    return;
+                }
+                let connection = http1::Builder::new()
+                    .timer(TokioTimer::new())
+                    .keep_alive(!options.keepalive.is_zero())
+                    .header_read_timeout(options.header_read_timeout)
+                    .serve_connection(TokioIo::new(stream), service)
+                    .with_upgrades();
+                if let Err(err) = close_gracefully(connection, closing, |connection| {
+                    connection.graceful_shutdown()
+                })
+                .await
+                {
+                    tracing::debug!("connection {} closed: {}", addr, err);
+                }
+                return;
+            };
+            //the handshake counts against the header read timeout
+            let stream =
+                match tokio::time::timeout(options.header_read_timeout, tls.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(err)) => {
+                        tracing::debug!("TLS handshake with {} failed: {}", addr, err);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("TLS handshake with {} timed out", addr);
+                        return;
+                    }
+                };
+            let h2 = stream.get_ref().1.alpn_protocol() == Some(ALPN_H2);
+            let mut builder = auto::Builder::new(TokioExecutor::new());
+            builder
+                .http1()
+                .timer(TokioTimer::new())
+                .keep_alive(!options.keepalive.is_zero())
+                .header_read_timeout(options.header_read_timeout);
+            builder.http2().timer(TokioTimer::new());
+            let builder = if h2 {
+                builder.http2_only()
+            } else {
+                builder.http1_only()
+            };
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(err) = close_gracefully(connection, closing, |connection| {
+                connection.graceful_shutdown()
+            })
+            .await
+            {
+                tracing::debug!("connection {} closed: {}", addr, err);
+            }
+        });
+    }
+    tracing::info!("stopped accepting connections");
+    closing.send_replace(true);
+}
+
+//runs the connection until it ends; once `closing` is set, asks it to close gracefully
+//and waits for that
+async fn close_gracefully<C, E>(
+    connection: C,
+    mut closing: watch::Receiver<bool>,
+    graceful_shutdown: impl FnOnce(Pin<&mut C>),
+) -> Result<(), E>
+where
+    C: Future<Output = Result<(), E>>,
+    E: fmt::Display,
+{
+    tokio::pin!(connection);
+    tokio::select! {
+        result = connection.as_mut() => result,
+        _ = async { closing.wait_for(|closing| *closing).await.is_ok() } => {
+            graceful_shutdown(connection.as_mut());
+            connection.await
+        }
+    }
+}
+
+//Server (HTTP_REDIRECT_PORT)
+//plain HTTP/1.1 answering every request with a 308 to the same URL on the https port
+async fn redirect_to_https(listener: TcpListener, https_port: u16, options: ServerOptions) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                tracing::error!("failed to accept connection: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            let service = service_fn(move |request: Request<Incoming>| async move {
+                Ok::<_, Infallible>(https_redirect(&request, https_port))
+            });
+            let result = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(options.header_read_timeout)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+            if let Err(err) = result {
+                tracing::debug!("redirect connection {} closed: {}", addr, err);
+            }
+        });
+    }
+}
+
+fn https_redirect(request: &Request<Incoming>, https_port: u16) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok());
+    let Some(host) = host else {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            "MISSING_HOST",
+            "a Host header is required to redirect to https",
+        )
+        .into_response();
+    };
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    let location = if https_port == 443 {
+        format!("https://{}{}", host.host(), path)
+    } else {
+        format!("https://{}:{}{}", host.host(), https_port, path)
+    };
+    Redirect::permanent(&location).into_response()
+}
+
+//a permit is taken before accepting, so connections past the cap aren't accepted
+//until one closes (protects the runtime from connection floods)
+async fn acquire(
+    connections: Option<&Arc<Semaphore>>,
+    max_connections: usize,
+) -> Option<OwnedSemaphorePermit> {
+    let connections = connections?;
+    match connections.clone().try_acquire_owned() {
+        Ok(permit) => Some(permit),
+        Err(_) => {
+            tracing::warn!(
+                "connection cap of {} reached, delaying new connections",
+                max_connections
+            );
+            connections.clone().acquire_owned().await.ok()
+        }
+    }
+}
+
+//hyper answers request lines it can't parse (HTTP/0.9, HTTP/2.0, HTTP/1.2, ...) with an
+//empty 400, so the first request line of a connection is peeked at and other versions
+//are answered with a JSON 505 instead (later requests on a keep-alive connection still
+//get hyper's 400)
+async fn unsupported_version(stream: &TcpStream, timeout: Duration) -> Option<String> {
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 1024];
+    let mut previous = 0;
+    loop {
+        let read = tokio::time::timeout_at(deadline, stream.peek(&mut buffer))
+            .await
+            .ok()?
+            .ok()?;
+        if read == 0 {
+            return None;
+        }
+        if let Some(end) = buffer[..read].iter().position(|byte| *byte == b'\n') {
+            let line = String::from_utf8_lossy(&buffer[..end]);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            return match parts.as_slice() {
+                //`GET /` without a version is HTTP/0.9
+                [_, _] => Some("HTTP/0.9".to_string()),
+                [_, _, version]
+                    if version.starts_with("HTTP/") && !SUPPORTED_VERSIONS.contains(version) =>
+                {
+                    Some(version.to_string())
+                }
+                //anything else is left to hyper
+                _ => None,
+            };
+        }
+        if read == buffer.len() {
+            return None;
+        }
+        //peek returns immediately while no new bytes arrived
+        if read == previous {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        previous = read;
+    }
+}
+
+async fn reject_version(mut stream: TcpStream, version: &str) {
+    tracing::debug!("rejecting unsupported version {}", version);
+    let response = AppError::new(
+        StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+        "HTTP_VERSION_NOT_SUPPORTED",
+        format!(
+            "{} is not supported, use {}",
+            version,
+            SUPPORTED_VERSIONS.join(" or ")
+        ),
+    )
+    .into_response();
+    let status = response.status();
+    let Ok(body) = axum::body::to_bytes(response.into_body(), usize::MAX).await else {
+        return;
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&body).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+use tracing::{Instrument, field::Empty};
+
+use crate::state::AppState;
+
+//Middleware (route_layer: runs only after a route matched)
+//one span per handler invocation, with OpenTelemetry HTTP semantic-convention attributes.
+//the span closes once the response is produced (streamed bodies have no size yet)
+pub async fn handler_span_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    let meta = state.route(&request);
+    let route = meta
+        .map_or(request.uri().path(), |meta| meta.template.as_str())
+        .to_string();
+    let handler = meta.map_or("", |meta| meta.handler);
+    let operation_id = meta
+        .and_then(|meta| meta.operations.get(&method.as_str().to_lowercase()))
+        .and_then(|operation| operation.operation_id.clone())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "handler",
+        otel.name = %format!("{} {}", method, route),
+        otel.kind = "server",
+        http.method = %method,
+        http.route = %route,
+        code.function = handler,
+        operation.id = %operation_id,
+        http.status_code = Empty,
+        http.response.body.size = Empty,
+    );
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    if let Some(size) = response.body().size_hint().exact() {
+        span.record("http.response.body.size", size);
+    }
+    response
+}
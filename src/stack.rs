@@ -0,0 +1,89 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    Json, Router,
+    extract::Request,
+    response::IntoResponse,
+    routing::{Route, get},
+};
+use serde::Serialize;
+use tower::{Layer, Service};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerInfo {
+    pub name: &'static str,
+    //route_layer: only runs for matched routes
+    pub kind: &'static str,
+}
+
+//Router wrapper that records each layer as it is added, innermost first
+//(a request passes them from the last to the first, the response the other way)
+pub struct LayerStack {
+    router: Router<()>,
+    layers: Vec<LayerInfo>,
+}
+
+impl LayerStack {
+    pub fn new(router: Router<()>) -> Self {
+        Self {
+            router,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn route_layer<L>(self, name: &'static str, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.push(name, "route_layer", |router| router.route_layer(layer))
+    }
+
+    pub fn layer<L>(self, name: &'static str, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.push(name, "layer", |router| router.layer(layer))
+    }
+
+    //routes added here are only wrapped by the layers added afterwards
+    pub fn map(mut self, f: impl FnOnce(Router<()>) -> Router<()>) -> Self {
+        self.router = f(self.router);
+        self
+    }
+
+    pub fn router(&self) -> &Router<()> {
+        &self.router
+    }
+
+    //GET <path> returns the recorded layers as JSON (register it last)
+    pub fn with_middleware_endpoint(self, path: &'static str) -> Router<()> {
+        let layers: Arc<Vec<LayerInfo>> = Arc::new(self.layers);
+        self.router.route(
+            path,
+            get(move || async move { Json(layers.as_ref().clone()) }),
+        )
+    }
+
+    pub fn into_router(self) -> Router<()> {
+        self.router
+    }
+
+    fn push(
+        mut self,
+        name: &'static str,
+        kind: &'static str,
+        f: impl FnOnce(Router<()>) -> Router<()>,
+    ) -> Self {
+        self.layers.push(LayerInfo { name, kind });
+        self.router = f(self.router);
+        self
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use axum::extract::Request;
+use tokio::sync::broadcast;
+
+use crate::{
+    apikey::ApiKeyStore,
+    cache::ResponseCache,
+    config::Config,
+    cursor,
+    dedupe::DedupeStore,
+    degraded::DegradedMode,
+    events::{self, ServerEvent},
+    health::HealthChecks,
+    idempotency::IdempotencyStore,
+    lifecycle::Lifecycle,
+    maintenance::Maintenance,
+    message::{self, Repository},
+    metrics::Metrics,
+    note::NoteStore,
+    proxy::Proxy,
+    replay::NonceStore,
+    report::{self, ErrorReporter},
+    router::{RouteMeta, RouteTable},
+    tap::{self, TapEvent},
+    ws,
+};
+
+//shared resources (handed to handlers and middleware as State<Arc<AppState>>).
+//immutable per config: a reload builds a new AppState, and the Arc'd runtime state
+//(counters, flags, stores) is carried over to it
+#[derive(Debug)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub cache: ResponseCache,
+    pub lifecycle: Arc<Lifecycle>,
+    pub degraded: Arc<DegradedMode>,
+    pub nonces: Arc<NonceStore>,
+    pub dedupe: DedupeStore,
+    pub idempotency: Arc<IdempotencyStore>,
+    //dev request tap (GET /_tap)
+    pub tap: broadcast::Sender<TapEvent>,
+    //GET /events feed
+    pub events: broadcast::Sender<ServerEvent>,
+    pub reporter: Arc<dyn ErrorReporter>,
+    pub notes: Arc<NoteStore>,
+    //MESSAGE_STORE backend of /messages
+    pub messages: Arc<dyn Repository>,
+    pub maintenance: Arc<Maintenance>,
+    //GET /ws?broadcast=true clients
+    pub ws: broadcast::Sender<ws::Message>,
+    //set once by build_router
+    pub routes: OnceLock<RouteTable>,
+    //HMAC key of pagination cursors
+    pub cursor_key: Vec<u8>,
+    //sample paths POSTed so far (201 the first time, 200 afterwards)
+    pub samples: Arc<Mutex<HashSet<i32>>>,
+    //request counters and histograms (GET /metrics)
+    pub metrics: Arc<Metrics>,
+    //GET /readyz
+    pub health: Arc<HealthChecks>,
+    //x-api-key lookup (API_KEY_STORE)
+    pub api_keys: ApiKeyStore,
+    //client and circuit breaker of /proxy
+    pub proxy: Arc<Proxy>,
+}
+
+//dependencies of an AppState; whatever is not handed in is built from the config (main
+//and tests construct the app the same way: `build_router(Arc::new(builder.build()))`)
+#[derive(Debug, Default)]
+pub struct AppStateBuilder {
+    config: Option<Config>,
+    messages: Option<Arc<dyn Repository>>,
+    metrics: Option<Arc<Metrics>>,
+    ws: Option<broadcast::Sender<ws::Message>>,
+    tap: Option<broadcast::Sender<TapEvent>>,
+    events: Option<broadcast::Sender<ServerEvent>>,
+}
+
+impl AppStateBuilder {
+    //default: Config::from_env()
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    //default: MESSAGE_STORE
+    pub fn messages(mut self, messages: Arc<dyn Repository>) -> Self {
+        self.messages = Some(messages);
+        self
+    }
+
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    //GET /ws?broadcast=true channel
+    pub fn ws(mut self, ws: broadcast::Sender<ws::Message>) -> Self {
+        self.ws = Some(ws);
+        self
+    }
+
+    //dev request tap channel
+    pub fn tap(mut self, tap: broadcast::Sender<TapEvent>) -> Self {
+        self.tap = Some(tap);
+        self
+    }
+
+    //GET /events channel
+    pub fn events(mut self, events: broadcast::Sender<ServerEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn build(self) -> AppState {
+        let config = self.config.unwrap_or_else(Config::from_env);
+        let cache = ResponseCache::new(config.cache_ttl, config.cache_max_entries);
+        let degraded = DegradedMode::new(config.degraded_mode);
+        let nonces = NonceStore::new(config.replay_window);
+        let dedupe = DedupeStore::new(config.dedupe_window);
+        let idempotency = IdempotencyStore::new(config.idempotency_ttl);
+        let reporter = report::from_config(&config.error_reporter);
+        let cursor_key = cursor::key(config.cursor_secret.as_deref());
+        let messages = self
+            .messages
+            .unwrap_or_else(|| message::from_config(&config.message_store));
+        let maintenance =
+            Maintenance::new(config.maintenance_mode, config.maintenance_message.clone());
+        let lifecycle = Arc::new(Lifecycle::new());
+        let degraded = Arc::new(degraded);
+        let api_keys = ApiKeyStore::from_config(&config);
+        let health = HealthChecks::standard(
+            &config,
+            lifecycle.clone(),
+            messages.clone(),
+            degraded.clone(),
+        );
+        AppState {
+            config: Arc::new(config),
+            cache,
+            lifecycle,
+            degraded,
+            nonces: Arc::new(nonces),
+            dedupe,
+            idempotency,
+            tap: self.tap.unwrap_or_else(tap::channel),
+            events: self.events.unwrap_or_else(events::channel),
+            reporter,
+            notes: Arc::default(),
+            ws: self.ws.unwrap_or_else(ws::channel),
+            messages,
+            maintenance: Arc::new(maintenance),
+            routes: OnceLock::new(),
+            cursor_key,
+            samples: Arc::default(),
+            metrics: self.metrics.unwrap_or_default(),
+            health: Arc::new(health),
+            api_keys,
+            proxy: Arc::default(),
+        }
+    }
+}
+
+impl AppState {
+    pub fn builder() -> AppStateBuilder {
+        AppStateBuilder::default()
+    }
+
+    //state for a reloaded config: cache, dedupe, reporter, health checks, cursor key and API
+    //keys (with fresh rate limits) follow the new config; lifecycle, the runtime-toggled flags, notes, messages, nonces,
+    //idempotency keys and the proxy's client and breaker are kept (so the REPLAY_WINDOW, IDEMPOTENCY_TTL, DEGRADED_MODE,
+    //MAINTENANCE_MODE and MESSAGE_STORE values need a restart)
+    pub fn reload(&self, config: Config) -> Self {
+        Self {
+            cache: ResponseCache::new(config.cache_ttl, config.cache_max_entries),
+            lifecycle: self.lifecycle.clone(),
+            degraded: self.degraded.clone(),
+            nonces: self.nonces.clone(),
+            dedupe: DedupeStore::new(config.dedupe_window),
+            idempotency: self.idempotency.clone(),
+            tap: self.tap.clone(),
+            events: self.events.clone(),
+            reporter: report::from_config(&config.error_reporter),
+            notes: self.notes.clone(),
+            ws: self.ws.clone(),
+            messages: self.messages.clone(),
+            maintenance: self.maintenance.clone(),
+            routes: OnceLock::new(),
+            cursor_key: cursor::key(config.cursor_secret.as_deref()),
+            samples: self.samples.clone(),
+            metrics: self.metrics.clone(),
+            health: Arc::new(HealthChecks::standard(
+                &config,
+                self.lifecycle.clone(),
+                self.messages.clone(),
+                self.degraded.clone(),
+            )),
+            api_keys: ApiKeyStore::from_config(&config),
+            proxy: self.proxy.clone(),
+            config: Arc::new(config),
+        }
+    }
+
+    //precomputed metadata of the matched route (route layers only)
+    pub fn route(&self, request: &Request) -> Option<&RouteMeta> {
+        self.routes.get()?.matched(request)
+    }
+}
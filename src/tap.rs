@@ -0,0 +1,88 @@
+use std::{convert::Infallible, sync::Arc, time::Instant};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    context::RequestContext, response::CacheControl, scope::RequestScope, state::AppState,
+};
+
+//summary of one handled request, published to the dev tap
+#[derive(Debug, Clone, Serialize)]
+pub struct TapEvent {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub request_id: Option<String>,
+}
+
+//slow tap readers lag (and skip events) instead of holding requests back
+const TAP_CAPACITY: usize = 256;
+
+pub fn channel() -> broadcast::Sender<TapEvent> {
+    broadcast::channel(TAP_CAPACITY).0
+}
+
+//Middleware (dev only)
+pub async fn tap_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.tap.receiver_count() == 0 {
+        return next.run(request).await;
+    }
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let request_id = request
+        .extensions()
+        .get::<RequestScope>()
+        .and_then(|scope| scope.get::<RequestContext>())
+        .map(|context| context.request_id);
+    let start = Instant::now();
+    let response = next.run(request).await;
+    //no receivers left is fine
+    let _ = state.tap.send(TapEvent {
+        method,
+        path,
+        status: response.status().as_u16(),
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        request_id,
+    });
+    response
+}
+
+//Handler (dev only)
+//GET /_tap => newline-delimited JSON TapEvents, streamed until the client disconnects
+//(a plain streamed response: axum's `ws` feature isn't available in this build)
+pub async fn tap_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let receiver = state.tap.subscribe();
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let mut line = serde_json::to_vec(&event).unwrap_or_default();
+                    line.push(b'\n');
+                    return Some((Ok::<Bytes, Infallible>(Bytes::from(line)), receiver));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("tap reader lagged, {} events skipped", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    (
+        CacheControl::NoStore,
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(stream),
+    )
+}
@@ -0,0 +1,95 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+
+use crate::{body::DeadlineBody, error::AppError, state::AppState};
+
+//one ROUTE_TIMEOUTS entry: `<route>=<seconds>` (e.g. `/api/v1/upload=300`, 0 = none)
+#[derive(Debug, Clone)]
+pub struct RouteTimeout {
+    pub path: String,
+    pub timeout: Duration,
+}
+
+impl RouteTimeout {
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (path, secs) = entry.split_once('=')?;
+        let path = path.trim();
+        if !path.starts_with('/') {
+            return None;
+        }
+        Some(Self {
+            path: path.to_string(),
+            timeout: Duration::from_secs(secs.trim().parse().ok()?),
+        })
+    }
+}
+
+//Middleware (route layer)
+//aborts the handler after the route's timeout (ROUTE_TIMEOUTS, else REQUEST_TIMEOUT_SECS)
+//with a JSON error instead of tower's empty timeout response: 408 BODY_READ_TIMEOUT when
+//the request body was still arriving (the client is slow), 503 HANDLER_TIMEOUT otherwise.
+//only the handler's future is bounded, a streamed response body is not
+pub async fn timeout_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let timeout = state
+        .route(&request)
+        .and_then(|route| route.timeout)
+        .unwrap_or(state.config.request_timeout);
+    if timeout.is_zero() {
+        return Ok(next.run(request).await);
+    }
+    let done = Arc::new(AtomicBool::new(request.body().is_end_stream()));
+    let request =
+        request.map(|body| Body::new(DeadlineBody::new(body, Duration::ZERO, done.clone())));
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => Ok(response),
+        Err(_) if !done.load(Ordering::Relaxed) => Err(AppError::new(
+            StatusCode::REQUEST_TIMEOUT,
+            "BODY_READ_TIMEOUT",
+            format!("request body not received within {}s", timeout.as_secs()),
+        )),
+        Err(_) => {
+            tracing::warn!("handler timed out after {:?}", timeout);
+            Err(AppError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "HANDLER_TIMEOUT",
+                format!("the request was not handled within {}s", timeout.as_secs()),
+            ))
+        }
+    }
+}
+
+//Middleware
+//BODY_READ_TIMEOUT_SECS: 408 BODY_READ_TIMEOUT when the whole request body hasn't arrived
+//in time. an outer layer, so it also covers the layers that buffer bodies before routing
+//(dedupe_middleware, ...), which the handler timeout doesn't see
+pub async fn body_timeout_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let timeout = state.config.body_read_timeout;
+    if timeout.is_zero() || request.body().is_end_stream() {
+        return next.run(request).await;
+    }
+    let done = Arc::new(AtomicBool::new(false));
+    next.run(request.map(|body| Body::new(DeadlineBody::new(body, timeout, done))))
+        .await
+}
@@ -0,0 +1,61 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{scope::RequestScope, state::AppState};
+
+pub const SERVER_TIMING: &str = "server-timing";
+
+//per-request timing phases (kept in the RequestScope)
+#[derive(Debug, Clone, Default)]
+pub struct ServerTiming(Vec<(&'static str, Duration)>);
+
+impl ServerTiming {
+    //e.g. `timing::record(&scope, "parse", start.elapsed())`
+    pub fn record(scope: &RequestScope, phase: &'static str, duration: Duration) {
+        scope.update(|timing: &mut ServerTiming| timing.0.push((phase, duration)));
+    }
+
+    //Server-Timing header value (e.g. `parse;dur=0.042`)
+    fn header_value(&self) -> Option<HeaderValue> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let value = self
+            .0
+            .iter()
+            .map(|(phase, duration)| {
+                format!("{};dur={:.3}", phase, duration.as_secs_f64() * 1000.0)
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+//Middleware
+//reports the phases the inner layers recorded in the RequestScope
+//(skipped in degraded mode)
+pub async fn server_timing_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let scope = request.extensions().get::<RequestScope>().cloned();
+    let mut response = next.run(request).await;
+    if state.degraded.is_enabled() {
+        return response;
+    }
+    if let Some(value) = scope
+        .and_then(|scope| scope.get::<ServerTiming>())
+        .and_then(|timing| timing.header_value())
+    {
+        response.headers_mut().append(SERVER_TIMING, value);
+    }
+    response
+}
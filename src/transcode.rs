@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{body, budget, error::AppError, state::AppState};
+
+//charsets decoded to UTF-8 when ENABLE_TRANSCODING is on (lowercase labels)
+pub const TRANSCODE_CHARSETS: [&str; 8] = [
+    "us-ascii",
+    "ascii",
+    "iso-8859-1",
+    "latin1",
+    "windows-1252",
+    "utf-16",
+    "utf-16le",
+    "utf-16be",
+];
+
+//windows-1252 0x80..=0x9f (None = undefined byte)
+const WINDOWS_1252_HIGH: [Option<char>; 32] = [
+    Some('\u{20ac}'),
+    None,
+    Some('\u{201a}'),
+    Some('\u{0192}'),
+    Some('\u{201e}'),
+    Some('\u{2026}'),
+    Some('\u{2020}'),
+    Some('\u{2021}'),
+    Some('\u{02c6}'),
+    Some('\u{2030}'),
+    Some('\u{0160}'),
+    Some('\u{2039}'),
+    Some('\u{0152}'),
+    None,
+    Some('\u{017d}'),
+    None,
+    None,
+    Some('\u{2018}'),
+    Some('\u{2019}'),
+    Some('\u{201c}'),
+    Some('\u{201d}'),
+    Some('\u{2022}'),
+    Some('\u{2013}'),
+    Some('\u{2014}'),
+    Some('\u{02dc}'),
+    Some('\u{2122}'),
+    Some('\u{0161}'),
+    Some('\u{203a}'),
+    Some('\u{0153}'),
+    None,
+    Some('\u{017e}'),
+    Some('\u{0178}'),
+];
+
+//Err(offset) of the first byte that can't be decoded
+fn decode(charset: &str, bytes: &[u8]) -> Result<String, usize> {
+    match charset {
+        "us-ascii" | "ascii" => match bytes.iter().position(|byte| !byte.is_ascii()) {
+            Some(offset) => Err(offset),
+            None => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        },
+        "iso-8859-1" | "latin1" => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        "windows-1252" => bytes
+            .iter()
+            .enumerate()
+            .map(|(offset, byte)| match byte {
+                0x80..=0x9f => WINDOWS_1252_HIGH[(byte - 0x80) as usize].ok_or(offset),
+                _ => Ok(*byte as char),
+            })
+            .collect(),
+        _ => {
+            //utf-16 without a BOM is big-endian (RFC 2781)
+            let (little_endian, start) = match (charset, bytes) {
+                ("utf-16le", _) => (true, 0),
+                ("utf-16be", _) => (false, 0),
+                (_, [0xff, 0xfe, ..]) => (true, 2),
+                (_, [0xfe, 0xff, ..]) => (false, 2),
+                _ => (false, 0),
+            };
+            if !(bytes.len() - start).is_multiple_of(2) {
+                return Err(bytes.len() - 1);
+            }
+            let units = bytes[start..].chunks_exact(2).map(|pair| {
+                if little_endian {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                }
+            });
+            let mut decoded = String::new();
+            let mut offset = start;
+            for unit in char::decode_utf16(units) {
+                let unit = unit.map_err(|_| offset)?;
+                offset += unit.len_utf16() * 2;
+                decoded.push(unit);
+            }
+            Ok(decoded)
+        }
+    }
+}
+
+//Middleware
+//ENABLE_TRANSCODING: a body declared in a legacy charset is decoded and passed on as
+//UTF-8 (Content-Type rewritten to charset=utf-8), so charset_middleware and the JSON
+//extractors only ever see UTF-8. UTF-8 bodies and bodies without a charset pass untouched
+pub async fn transcode_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !state.config.enable_transcoding {
+        return Ok(next.run(request).await);
+    }
+    let Some(content_type) = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<mime::Mime>().ok())
+    else {
+        return Ok(next.run(request).await);
+    };
+    let Some(charset) = content_type
+        .get_param(mime::CHARSET)
+        .map(|charset| charset.as_str().to_ascii_lowercase())
+        .filter(|charset| charset != "utf-8" && charset != "utf8")
+    else {
+        return Ok(next.run(request).await);
+    };
+    if !TRANSCODE_CHARSETS.contains(&charset.as_str()) {
+        return Err(AppError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "UNSUPPORTED_CHARSET",
+            format!(
+                "unsupported charset {:?}, use utf-8 or one of {}",
+                charset,
+                TRANSCODE_CHARSETS.join(", ")
+            ),
+        ));
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let bytes = body::read_request_body(body, state.config.body_limit).await?;
+    let decoded = decode(&charset, &bytes).map_err(|offset| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            "TRANSCODE_FAILED",
+            format!("body is not valid {} (byte offset {})", charset, offset),
+        )
+    })?;
+    budget::Buffered::of(&parts.extensions).add(bytes.len() + decoded.len());
+    tracing::debug!(
+        "transcoded {} byte {} body to {} byte utf-8",
+        bytes.len(),
+        charset,
+        decoded.len()
+    );
+    let params: String = content_type
+        .params()
+        .filter(|(name, _)| *name != mime::CHARSET)
+        .map(|(name, value)| format!("; {}={}", name, value))
+        .collect();
+    let essence = format!("{}{}; charset=utf-8", content_type.essence_str(), params);
+    if let Ok(essence) = HeaderValue::from_str(&essence) {
+        parts.headers.insert(header::CONTENT_TYPE, essence);
+    }
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, decoded.len().into());
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(decoded)))
+        .await)
+}
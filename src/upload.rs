@@ -0,0 +1,548 @@
+use std::{path::PathBuf, sync::Arc};
+
+use axum::{
+    Json,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use http_body_util::BodyExt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+
+use crate::{
+    body, checksum,
+    config::Config,
+    context,
+    error::{AppError, ErrorKind},
+    extract::ValidatedPath,
+    model::SamplePath,
+    response::CacheControl,
+    state::AppState,
+};
+
+//magic bytes => MIME type, for the types worth sniffing
+const SIGNATURES: [(&[u8], &str); 6] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+];
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadedFile {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub length: usize,
+    pub sha256: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/sample/{path}/upload",
+    tag = "Sample",
+    params(SamplePath),
+    request_body(
+        description = "files (the type of each part must be in UPLOAD_ALLOWED_TYPES)",
+        content = String,
+        content_type = "multipart/form-data",
+    ),
+    responses(
+        (status = 200, description = "OK", body = [UploadedFile]),
+        (status = 400, description = "malformed multipart body or more parts than MAX_UPLOAD_FIELDS", body = ResponseError),
+        (status = 413, description = "a part larger than MAX_FIELD_BYTES", body = ResponseError),
+        (status = 415, description = "disallowed file type", body = ResponseError),
+    ),
+)]
+pub async fn upload_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedPath(SamplePath { path }): ValidatedPath<SamplePath>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let boundary = boundary(&headers)?;
+    let mut reader = MultipartReader::new(body, &boundary, &state.config);
+    let mut files = Vec::new();
+    while let Some(headers) = reader.next_part().await? {
+        //plain form fields
+        if headers.filename.is_none() && headers.content_type.is_none() {
+            reader.skip_part().await?;
+            continue;
+        }
+        let part = read_file_part(&state, &mut reader, &headers, None).await?;
+        files.push(UploadedFile {
+            name: headers.name,
+            filename: headers.filename,
+            content_type: part.content_type,
+            length: part.length as usize,
+            sha256: part.sha256,
+        });
+    }
+    tracing::info!("path: {}, uploaded {} files", path, files.len());
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(files)).into_response())
+}
+
+//longest signature: bytes sniffed from the start of a streamed part
+const SNIFF_BYTES: usize = 8;
+const MAX_PART_HEADER_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StoredFile {
+    pub name: String,
+    pub filename: Option<String>,
+    //file name in UPLOAD_DIR (the client's filename is never used on disk)
+    pub id: String,
+    pub content_type: String,
+    pub length: u64,
+    pub sha256: String,
+}
+
+//multipart/form-data body of POST /upload (ApiDoc only)
+#[derive(ToSchema)]
+pub struct UploadForm {
+    //any number of file parts; plain fields are ignored (never constructed, hence the `_`)
+    #[schema(value_type = String, format = Binary, rename = "file")]
+    _file: Vec<u8>,
+}
+
+//Handler
+#[utoipa::path(
+    post,
+    path = "/upload",
+    tag = "Sample",
+    request_body(
+        description = "files (the type of each part must be in UPLOAD_ALLOWED_TYPES)",
+        content = UploadForm,
+        content_type = "multipart/form-data",
+    ),
+    responses(
+        (status = 201, description = "Stored in UPLOAD_DIR", body = [StoredFile]),
+        (status = 400, description = "malformed multipart body or more parts than MAX_UPLOAD_FIELDS", body = ResponseError),
+        (status = 413, description = "body larger than BODY_LIMIT or a part larger than MAX_FIELD_BYTES", body = ResponseError),
+        (status = 415, description = "disallowed file type", body = ResponseError),
+    ),
+)]
+//parts are written to disk as they arrive, so memory stays at about one body chunk.
+//BODY_LIMIT still caps the whole body. a part is written under a hidden `.<id>.part` name
+//(which GET /files/:name refuses) and renamed once complete; a failed or cancelled upload
+//(a timeout, a client that disconnects) leaves no files behind
+pub async fn store_upload_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse + Send, AppError> {
+    let boundary = boundary(&headers)?;
+    tokio::fs::create_dir_all(&state.config.upload_dir).await?;
+    let mut reader = MultipartReader::new(body, &boundary, &state.config);
+    let mut written = PendingFiles::default();
+    let files = store_parts(&state, &mut reader, &mut written).await?;
+    written.keep();
+    tracing::info!(
+        "stored {} files in {}",
+        files.len(),
+        state.config.upload_dir.display()
+    );
+    Ok((StatusCode::CREATED, CacheControl::NoStore, Json(files)).into_response())
+}
+
+//the files of an upload in progress, removed when dropped before keep(): on an error,
+//and also when the handler's future is dropped mid-upload
+#[derive(Debug, Default)]
+struct PendingFiles {
+    paths: Vec<PathBuf>,
+    kept: bool,
+}
+
+impl PendingFiles {
+    fn keep(&mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for PendingFiles {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        for path in &self.paths {
+            match std::fs::remove_file(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => tracing::warn!("failed to remove {}: {}", path.display(), err),
+            }
+        }
+    }
+}
+
+async fn store_parts(
+    state: &AppState,
+    reader: &mut MultipartReader,
+    written: &mut PendingFiles,
+) -> Result<Vec<StoredFile>, AppError> {
+    let mut files = Vec::new();
+    while let Some(headers) = reader.next_part().await? {
+        //plain form fields
+        if headers.filename.is_none() && headers.content_type.is_none() {
+            reader.skip_part().await?;
+            continue;
+        }
+        let id = context::generate_request_id();
+        let partial = state.config.upload_dir.join(format!(".{}.part", id));
+        let path = state.config.upload_dir.join(&id);
+        //tracked before it exists: a drop between create and push would miss it
+        written.paths.push(partial.clone());
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let part = read_file_part(state, reader, &headers, Some(&mut file)).await?;
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&partial, &path).await?;
+        written.paths.push(path);
+        files.push(StoredFile {
+            name: headers.name,
+            filename: headers.filename,
+            id,
+            content_type: part.content_type,
+            length: part.length,
+            sha256: part.sha256,
+        });
+    }
+    Ok(files)
+}
+
+//a file part read to its end
+struct FilePart {
+    //declared type, checked against UPLOAD_ALLOWED_TYPES and the sniffed content
+    content_type: String,
+    length: u64,
+    sha256: String,
+}
+
+//reads the current part chunk by chunk, hashing it and copying it into `file` if given
+async fn read_file_part(
+    state: &AppState,
+    reader: &mut MultipartReader,
+    headers: &PartHeaders,
+    mut file: Option<&mut tokio::fs::File>,
+) -> Result<FilePart, AppError> {
+    let allowed = &state.config.upload_allowed_types;
+    let content_type = headers.content_type.as_deref();
+    let declared = check_type(allowed, &headers.name, content_type, &[])?;
+    let mut hasher = Sha256::new();
+    let mut length = 0u64;
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    loop {
+        let (chunk, end) = reader.part_chunk().await?;
+        if head.len() < SNIFF_BYTES {
+            let take = (SNIFF_BYTES - head.len()).min(chunk.len());
+            head.extend_from_slice(&chunk[..take]);
+            if head.len() == SNIFF_BYTES || end {
+                check_type(allowed, &headers.name, content_type, &head)?;
+            }
+        }
+        hasher.update(&chunk);
+        length += chunk.len() as u64;
+        if let Some(file) = file.as_mut() {
+            file.write_all(&chunk).await?;
+        }
+        if end {
+            break;
+        }
+    }
+    Ok(FilePart {
+        content_type: declared,
+        length,
+        sha256: checksum::hex(&hasher.finalize()),
+    })
+}
+
+//multipart/form-data body (RFC 7578) read chunk by chunk (at most about one chunk plus a
+//delimiter is buffered), for both upload handlers
+struct MultipartReader {
+    body: Body,
+    buffer: Vec<u8>,
+    //`\r\n--<boundary>`
+    delimiter: Vec<u8>,
+    received: usize,
+    limit: usize,
+    //the preamble before the first boundary was skipped
+    started: bool,
+    //parts started so far and bytes of the current one
+    fields: usize,
+    field_bytes: usize,
+    max_fields: usize,
+    max_field_bytes: usize,
+}
+
+impl MultipartReader {
+    fn new(body: Body, boundary: &str, config: &Config) -> Self {
+        Self {
+            body,
+            //so that a body starting with the first boundary needs no special case
+            buffer: b"\r\n".to_vec(),
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            received: 0,
+            limit: config.body_limit,
+            started: false,
+            fields: 0,
+            field_bytes: 0,
+            max_fields: config.max_upload_fields,
+            max_field_bytes: config.max_field_bytes,
+        }
+    }
+
+    //the headers of the next part, None after the closing boundary. the previous part
+    //must have been read to its end (part_chunk / skip_part)
+    async fn next_part(&mut self) -> Result<Option<PartHeaders>, AppError> {
+        if !self.started {
+            //the preamble isn't a field, it only counts against BODY_LIMIT
+            while !self.read_until_delimiter().await?.1 {}
+            self.started = true;
+        }
+        if !self.ensure(2).await? {
+            return Err(invalid_multipart("missing closing boundary"));
+        }
+        if self.buffer.starts_with(b"--") {
+            return Ok(None);
+        }
+        if !self.buffer.starts_with(b"\r\n") {
+            return Err(invalid_multipart("malformed boundary line"));
+        }
+        self.buffer.drain(..2);
+        self.fields += 1;
+        if self.max_fields != 0 && self.fields > self.max_fields {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "TOO_MANY_FIELDS",
+                format!("multipart body has more than {} parts", self.max_fields),
+            ));
+        }
+        self.field_bytes = 0;
+        parse_part_headers(&self.part_headers().await?).map(Some)
+    }
+
+    //reads the next data frame into the buffer (false at the end of the body)
+    async fn fill(&mut self) -> Result<bool, AppError> {
+        while let Some(frame) = self.body.frame().await {
+            let frame = frame.map_err(|err| body::read_error(err, self.limit))?;
+            if let Ok(data) = frame.into_data() {
+                self.received += data.len();
+                if self.received > self.limit {
+                    return Err(body::too_large(self.limit));
+                }
+                self.buffer.extend_from_slice(&data);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    //false if the body ends before `len` bytes are buffered
+    async fn ensure(&mut self, len: usize) -> Result<bool, AppError> {
+        while self.buffer.len() < len {
+            if !self.fill().await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    //the header block of a part (its blank line is consumed)
+    async fn part_headers(&mut self) -> Result<Vec<u8>, AppError> {
+        loop {
+            if let Some(end) = find(&self.buffer, b"\r\n\r\n") {
+                let headers = self.buffer[..end].to_vec();
+                self.buffer.drain(..end + 4);
+                return Ok(headers);
+            }
+            if self.buffer.len() > MAX_PART_HEADER_BYTES {
+                return Err(invalid_multipart("part headers too large"));
+            }
+            if !self.fill().await? {
+                return Err(invalid_multipart("missing part headers"));
+            }
+        }
+    }
+
+    //the next piece of the current part, and whether it was the last one
+    //(the delimiter after it is consumed). a part over MAX_FIELD_BYTES is a 413
+    async fn part_chunk(&mut self) -> Result<(Vec<u8>, bool), AppError> {
+        let (chunk, last) = self.read_until_delimiter().await?;
+        self.field_bytes += chunk.len();
+        if self.max_field_bytes != 0 && self.field_bytes > self.max_field_bytes {
+            return Err(AppError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "FIELD_TOO_LARGE",
+                format!("multipart part exceeds {} bytes", self.max_field_bytes),
+            ));
+        }
+        Ok((chunk, last))
+    }
+
+    async fn read_until_delimiter(&mut self) -> Result<(Vec<u8>, bool), AppError> {
+        loop {
+            if let Some(end) = find(&self.buffer, &self.delimiter) {
+                let chunk = self.buffer[..end].to_vec();
+                self.buffer.drain(..end + self.delimiter.len());
+                return Ok((chunk, true));
+            }
+            //the tail may be the start of a delimiter split across chunks
+            let keep = self.delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                let chunk = self.buffer.drain(..self.buffer.len() - keep).collect();
+                return Ok((chunk, false));
+            }
+            if !self.fill().await? {
+                return Err(invalid_multipart("missing closing boundary"));
+            }
+        }
+    }
+
+    async fn skip_part(&mut self) -> Result<(), AppError> {
+        while !self.part_chunk().await?.1 {}
+        Ok(())
+    }
+}
+
+//the declared type (lowercase, without parameters) if UPLOAD_ALLOWED_TYPES allows it and
+//the content doesn't contradict it (e.g. a zip sent as image/png)
+fn check_type(
+    allowed: &[String],
+    name: &str,
+    content_type: Option<&str>,
+    data: &[u8],
+) -> Result<String, AppError> {
+    let declared = content_type
+        .unwrap_or("application/octet-stream")
+        .to_ascii_lowercase();
+    let declared = declared.split(';').next().unwrap_or("").trim().to_string();
+    if !is_allowed(allowed, &declared) {
+        return Err(disallowed(name, &format!("{} is not allowed", declared)));
+    }
+    if let Some(sniffed) = sniff(data)
+        && sniffed != declared
+    {
+        return Err(disallowed(
+            name,
+            &format!("declared {}, content looks like {}", declared, sniffed),
+        ));
+    }
+    Ok(declared)
+}
+
+//UPLOAD_ALLOWED_TYPES entries are exact types or `type/*`
+fn is_allowed(allowed: &[String], content_type: &str) -> bool {
+    allowed.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        match allowed.strip_suffix("/*") {
+            Some(prefix) => content_type
+                .split_once('/')
+                .is_some_and(|(kind, _)| kind == prefix),
+            None => allowed == content_type,
+        }
+    })
+}
+
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|(_, content_type)| *content_type)
+}
+
+//multipart bodies may be streamed uploads: layers that buffer bodies let these through
+pub fn is_multipart(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<mime::Mime>().ok())
+        .is_some_and(|content_type| content_type.type_() == mime::MULTIPART)
+}
+
+fn boundary(headers: &HeaderMap) -> Result<String, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<mime::Mime>().ok())
+        .filter(|content_type| {
+            content_type.essence_str() == mime::MULTIPART_FORM_DATA.essence_str()
+        });
+    let Some(content_type) = content_type else {
+        return Err(AppError::of(
+            ErrorKind::UnsupportedMediaType,
+            "expected multipart/form-data",
+        ));
+    };
+    content_type
+        .get_param(mime::BOUNDARY)
+        .map(|boundary| boundary.as_str().to_string())
+        .ok_or_else(|| invalid_multipart("missing boundary"))
+}
+
+struct PartHeaders {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+}
+
+fn parse_part_headers(headers: &[u8]) -> Result<PartHeaders, AppError> {
+    let headers = std::str::from_utf8(headers)
+        .map_err(|_| invalid_multipart("part headers are not UTF-8"))?;
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+    for line in headers.split("\r\n") {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "content-disposition" => {
+                for param in value.split(';').skip(1) {
+                    let Some((key, value)) = param.split_once('=') else {
+                        continue;
+                    };
+                    let value = value.trim().trim_matches('"').to_string();
+                    match key.trim() {
+                        "name" => name = Some(value),
+                        "filename" => filename = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            "content-type" => content_type = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    Ok(PartHeaders {
+        name: name.ok_or_else(|| invalid_multipart("part without a name"))?,
+        filename,
+        content_type,
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn invalid_multipart(reason: &str) -> AppError {
+    AppError::new(
+        StatusCode::BAD_REQUEST,
+        "INVALID_MULTIPART",
+        format!("invalid multipart body: {}", reason),
+    )
+}
+
+fn disallowed(name: &str, reason: &str) -> AppError {
+    AppError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "DISALLOWED_FILE_TYPE",
+        format!("part {:?}: {}", name, reason),
+    )
+}
@@ -0,0 +1,53 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+
+//request headers that selected this response's representation, recorded by the
+//negotiating middleware (see `negotiated`) and written out once by vary_middleware
+#[derive(Debug, Clone, Default)]
+pub struct Negotiated(Vec<HeaderName>);
+
+//call only when `name` could have changed the response (not merely when it was read)
+pub fn negotiated(response: &mut Response, name: HeaderName) {
+    let negotiated = response
+        .extensions_mut()
+        .get_or_insert_default::<Negotiated>();
+    if !negotiated.0.contains(&name) {
+        negotiated.0.push(name);
+    }
+}
+
+//Middleware
+//sets Vary to exactly the recorded dimensions, merged with any Vary already present.
+//responses nothing was negotiated for get no Vary from here
+pub async fn vary_middleware(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let Some(Negotiated(names)) = response.extensions_mut().remove::<Negotiated>() else {
+        return response;
+    };
+    let mut vary: Vec<String> = response
+        .headers()
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    //`*` already covers everything
+    if vary.iter().any(|name| name == "*") {
+        return response;
+    }
+    for name in names {
+        if !vary.iter().any(|existing| existing == name.as_str()) {
+            vary.push(name.as_str().to_string());
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&vary.join(", ")) {
+        response.headers_mut().insert(header::VARY, value);
+    }
+    response
+}
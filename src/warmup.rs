@@ -0,0 +1,63 @@
+use std::{sync::Arc, sync::atomic::Ordering, time::Instant};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::Request,
+    http::{StatusCode, header},
+};
+use serde::Serialize;
+use tower::ServiceExt;
+
+use crate::state::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct WarmupReport {
+    pub warmed: bool,
+    pub steps: Vec<WarmupStep>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmupStep {
+    pub name: &'static str,
+    pub status: u16,
+    pub duration_ms: f64,
+}
+
+//requests sent through the middleware stack (GET / also fills the response cache)
+const WARMUP_REQUESTS: [(&str, &str); 2] = [("ping", "/"), ("shutdown_status", "/shutdown-status")];
+
+//Handler (dev only)
+//POST /_warmup => runs no-op requests through `app` before readiness is flipped
+pub async fn warmup_handler(state: Arc<AppState>, app: Router) -> (StatusCode, Json<WarmupReport>) {
+    let mut steps = Vec::new();
+    for (name, uri) in WARMUP_REQUESTS {
+        let request = Request::get(uri)
+            .header(header::HOST, "warmup")
+            //part of the cache key; matches what typical clients send
+            .header(header::ACCEPT, "*/*")
+            .body(Body::empty())
+            .expect("static warmup request");
+        let start = Instant::now();
+        let status = match app.clone().oneshot(request).await {
+            Ok(response) => response.status(),
+            Err(err) => match err {},
+        };
+        steps.push(WarmupStep {
+            name,
+            status: status.as_u16(),
+            duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+        });
+    }
+    let warmed = steps
+        .iter()
+        .all(|step| StatusCode::from_u16(step.status).is_ok_and(|status| status.is_success()));
+    state.lifecycle.warmed.store(warmed, Ordering::SeqCst);
+    tracing::info!(warmed, ?steps, "warmup finished");
+    let status = if warmed {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(WarmupReport { warmed, steps }))
+}
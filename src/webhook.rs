@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    audit::Subject, body, budget, checksum, error::AppError, model::ResponseData,
+    response::CacheControl, scope::RequestScope, state::AppState,
+};
+
+pub const X_SIGNATURE: &str = "x-signature";
+
+//Middleware
+//X-Signature: `sha256=<hex>` (or bare hex) HMAC-SHA256 of the raw body with WEBHOOK_SECRET.
+//the verified bytes are handed on unchanged as the request body
+pub async fn verify_signature_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(secret) = state.config.webhook_secret.as_deref() else {
+        return Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "WEBHOOK_DISABLED",
+            "WEBHOOK_SECRET is not configured",
+        ));
+    };
+    let (parts, body) = request.into_parts();
+    let signature = parts
+        .headers
+        .get(X_SIGNATURE)
+        .and_then(|signature| signature.to_str().ok())
+        .map(|signature| {
+            let signature = signature.trim();
+            signature
+                .strip_prefix("sha256=")
+                .unwrap_or(signature)
+                .to_ascii_lowercase()
+        })
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::UNAUTHORIZED,
+                "MISSING_SIGNATURE",
+                "X-Signature header is required",
+            )
+        })?;
+    let bytes = body::read_request_body(body, state.config.body_limit).await?;
+    budget::Buffered::of(&parts.extensions).add(bytes.len());
+    let expected = checksum::hmac_sha256_hex(secret.as_bytes(), &bytes);
+    if !checksum::constant_time_eq(signature.as_bytes(), expected.as_bytes()) {
+        return Err(AppError::new(
+            StatusCode::UNAUTHORIZED,
+            "INVALID_SIGNATURE",
+            "X-Signature does not match the request body",
+        ));
+    }
+    //the signature is the caller's credential (audit log subject)
+    if let Some(scope) = parts.extensions.get::<RequestScope>() {
+        scope.set(Subject("webhook".to_string()));
+    }
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await)
+}
+
+//Handler (runs only after verify_signature_middleware)
+#[utoipa::path(
+    post,
+    path = "/webhook",
+    tag = "Sample",
+    params(
+        ("X-Signature" = String, Header, description = "sha256=<hex HMAC-SHA256 of the body with WEBHOOK_SECRET>"),),
+    request_body(
+        description = "webhook payload (any content type)",
+        content = String,
+        content_type = "application/octet-stream",
+    ),
+    responses(
+        (status = 200, description = "OK", body = ResponseData),
+        (status = 401, description = "missing or invalid signature", body = ResponseError),
+        (status = 503, description = "WEBHOOK_SECRET is not configured", body = ResponseError),
+    ),
+)]
+pub async fn webhook_handler(body: Bytes) -> Result<impl IntoResponse + Send, AppError> {
+    tracing::info!("webhook: {} bytes", body.len());
+    let result = ResponseData {
+        message: format!("webhook received, body: {} bytes", body.len()),
+        length: Some(body.len()),
+        sha256: Some(checksum::sha256_hex(&body)),
+        ..Default::default()
+    };
+    Ok((StatusCode::OK, CacheControl::NoStore, Json(result)).into_response())
+}
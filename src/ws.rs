@@ -0,0 +1,353 @@
+use std::{
+    io,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{broadcast, mpsc},
+};
+use utoipa::IntoParams;
+
+use crate::{
+    checksum, error::AppError, extract::StrictQuery, lifecycle::InFlightGuard, state::AppState,
+};
+
+//RFC 6455 §1.3
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+//largest message (after reassembling fragments); bigger ones close with 1009
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+//slow broadcast clients lag (and skip messages) instead of holding the others back
+const BROADCAST_CAPACITY: usize = 256;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+pub fn channel() -> broadcast::Sender<Message> {
+    broadcast::channel(BROADCAST_CAPACITY).0
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsQuery {
+    /// true: join the broadcast channel instead of echoing (messages go to every
+    /// broadcast client, the sender included)
+    #[serde(default)]
+    #[param(required = false, default = false)]
+    pub broadcast: bool,
+}
+
+//Handler
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "Sample",
+    params(WsQuery),
+    responses(
+        (status = 101, description = "Switching Protocols (WebSocket)"),
+        (status = 400, description = "not a WebSocket handshake", body = ResponseError),
+        (status = 426, description = "unsupported Sec-WebSocket-Version", body = ResponseError),
+    ),
+)]
+//WebSocket echo, or broadcast with ?broadcast=true.
+//
+//the upgrade is an ordinary GET, so every layer runs for the handshake: auth (a token is
+//needed unless /ws is in AUTH_PUBLIC_PATHS; browsers can't set Authorization on a
+//WebSocket), the access log and metrics (status 101), maintenance, rate limits, CORS.
+//once the 101 is sent the connection is handed to the task below and no layer sees the
+//frames: DEADLINE/timeouts, Server-Timing and panic catching only cover the handshake.
+//the socket task counts itself as in flight and closes with 1001 when shutdown starts
+pub async fn ws_handler(
+    State(state): State<Arc<AppState>>,
+    StrictQuery(WsQuery { broadcast }): StrictQuery<WsQuery>,
+    mut request: Request,
+) -> Result<Response, AppError> {
+    let accept = handshake(request.headers())?;
+    let upgrade = hyper::upgrade::on(&mut request);
+    tokio::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => serve_socket(state, TokioIo::new(upgraded), broadcast).await,
+            Err(err) => tracing::warn!("WebSocket upgrade failed: {}", err),
+        }
+    });
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = response.headers_mut();
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    headers.insert(
+        header::SEC_WEBSOCKET_ACCEPT,
+        HeaderValue::from_str(&accept)?,
+    );
+    Ok(response)
+}
+
+//Sec-WebSocket-Accept for a valid RFC 6455 handshake
+fn handshake(headers: &HeaderMap) -> Result<String, AppError> {
+    let has_token = |name: header::HeaderName, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    if !has_token(header::UPGRADE, "websocket") || !has_token(header::CONNECTION, "upgrade") {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "NOT_A_WEBSOCKET_HANDSHAKE",
+            "expected `Upgrade: websocket` and `Connection: Upgrade`",
+        ));
+    }
+    if headers
+        .get(header::SEC_WEBSOCKET_VERSION)
+        .is_none_or(|version| version != "13")
+    {
+        return Err(AppError::new(
+            StatusCode::UPGRADE_REQUIRED,
+            "UNSUPPORTED_WEBSOCKET_VERSION",
+            "only Sec-WebSocket-Version 13 is supported",
+        ));
+    }
+    let key = headers
+        .get(header::SEC_WEBSOCKET_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "NOT_A_WEBSOCKET_HANDSHAKE",
+                "missing Sec-WebSocket-Key",
+            )
+        })?;
+    Ok(checksum::base64_encode(&checksum::sha1(
+        format!("{}{}", key, ACCEPT_GUID).as_bytes(),
+    )))
+}
+
+//what the reader task saw
+enum Event {
+    Message(Message),
+    Ping(Vec<u8>),
+    Close,
+    //protocol violation or read error: close with this code
+    Failed(u16, String),
+}
+
+async fn serve_socket<S>(state: Arc<AppState>, io: S, broadcast: bool)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    //open sockets hold off drain until they have sent their close frame
+    let _guard = InFlightGuard::enter(&state.lifecycle.in_flight);
+    let (reader, mut writer) = tokio::io::split(io);
+    //a cancelled read would lose a partial frame, so reads run in their own task
+    let (events_tx, mut events) = mpsc::channel(16);
+    let reader = tokio::spawn(read_events(reader, events_tx));
+    let mut receiver = broadcast.then(|| state.ws.subscribe());
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let (mut received, mut sent) = (0u64, 0u64);
+    tracing::info!(broadcast, "WebSocket opened");
+    let close = loop {
+        let from_channel = async {
+            match receiver.as_mut() {
+                Some(receiver) => receiver.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            event = events.recv() => match event {
+                Some(Event::Message(message)) => {
+                    received += 1;
+                    if broadcast {
+                        //no receivers left is fine
+                        let _ = state.ws.send(message);
+                        Ok(())
+                    } else {
+                        sent += 1;
+                        write_message(&mut writer, &message).await
+                    }
+                }
+                Some(Event::Ping(payload)) => write_frame(&mut writer, OP_PONG, &payload).await,
+                Some(Event::Close) | None => break (CLOSE_NORMAL, String::new()),
+                Some(Event::Failed(code, reason)) => break (code, reason),
+            },
+            message = from_channel => match message {
+                Ok(message) => {
+                    sent += 1;
+                    write_message(&mut writer, &message).await
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("WebSocket client lagged, skipped {} messages", skipped);
+                    Ok(())
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    break (CLOSE_GOING_AWAY, String::new());
+                }
+            },
+            _ = tick.tick() => {
+                if state.lifecycle.shutting_down.load(Ordering::SeqCst) {
+                    break (CLOSE_GOING_AWAY, "server shutting down".to_string());
+                }
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            tracing::debug!("WebSocket write failed: {}", err);
+            break (CLOSE_GOING_AWAY, String::new());
+        }
+    };
+    let (code, reason) = close;
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    if let Err(err) = write_frame(&mut writer, OP_CLOSE, &payload).await {
+        tracing::debug!("failed to send WebSocket close: {}", err);
+    }
+    let _ = writer.shutdown().await;
+    reader.abort();
+    tracing::info!(code, reason, received, sent, "WebSocket closed");
+}
+
+async fn read_events<R: AsyncRead + Unpin>(mut reader: R, events: mpsc::Sender<Event>) {
+    //opcode and payload of a fragmented message so far
+    let mut partial: Option<(u8, Vec<u8>)> = None;
+    loop {
+        let event = match read_frame(&mut reader).await {
+            Err(err) => Event::Failed(CLOSE_GOING_AWAY, err.to_string()),
+            Ok(Err(failed)) => failed,
+            Ok(Ok((fin, opcode, payload))) => match opcode {
+                OP_PING => Event::Ping(payload),
+                OP_PONG => continue,
+                OP_CLOSE => Event::Close,
+                OP_TEXT | OP_BINARY if partial.is_none() => {
+                    if !fin {
+                        partial = Some((opcode, payload));
+                        continue;
+                    }
+                    message(opcode, payload)
+                }
+                OP_CONTINUATION if partial.is_some() => {
+                    let (opcode, mut data) = partial.take().unwrap_or_default();
+                    data.extend_from_slice(&payload);
+                    if data.len() > MAX_MESSAGE_BYTES {
+                        Event::Failed(CLOSE_TOO_BIG, "message too big".to_string())
+                    } else if !fin {
+                        partial = Some((opcode, data));
+                        continue;
+                    } else {
+                        message(opcode, data)
+                    }
+                }
+                _ => Event::Failed(CLOSE_PROTOCOL_ERROR, "unexpected frame".to_string()),
+            },
+        };
+        let last = matches!(event, Event::Close | Event::Failed(..));
+        if events.send(event).await.is_err() || last {
+            return;
+        }
+    }
+}
+
+fn message(opcode: u8, payload: Vec<u8>) -> Event {
+    if opcode == OP_BINARY {
+        return Event::Message(Message::Binary(payload));
+    }
+    match String::from_utf8(payload) {
+        Ok(text) => Event::Message(Message::Text(text)),
+        Err(_) => Event::Failed(CLOSE_INVALID_DATA, "text is not UTF-8".to_string()),
+    }
+}
+
+//one client frame: (fin, opcode, unmasked payload), or the close to send for a bad frame
+async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> io::Result<Result<(bool, u8, Vec<u8>), Event>> {
+    let failed = |code, reason: &str| Ok(Err(Event::Failed(code, reason.to_string())));
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0f;
+    if head[0] & 0x70 != 0 {
+        return failed(CLOSE_PROTOCOL_ERROR, "reserved bits set");
+    }
+    //clients must mask every frame (RFC 6455 §5.1)
+    if head[1] & 0x80 == 0 {
+        return failed(CLOSE_PROTOCOL_ERROR, "unmasked frame");
+    }
+    let length = match head[1] & 0x7f {
+        126 => u64::from(reader.read_u16().await?),
+        127 => reader.read_u64().await?,
+        length => u64::from(length),
+    };
+    if opcode >= OP_CLOSE && (!fin || length > 125) {
+        return failed(CLOSE_PROTOCOL_ERROR, "invalid control frame");
+    }
+    if length > MAX_MESSAGE_BYTES as u64 {
+        return failed(CLOSE_TOO_BIG, "message too big");
+    }
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload).await?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+    Ok(Ok((fin, opcode, payload)))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<()> {
+    match message {
+        Message::Text(text) => write_frame(writer, OP_TEXT, text.as_bytes()).await,
+        Message::Binary(data) => write_frame(writer, OP_BINARY, data).await,
+    }
+}
+
+//a single unmasked, final frame (servers never mask)
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
@@ -0,0 +1,238 @@
+//shared helpers of the integration tests: requests go through the whole app with
+//tower's oneshot, no socket is bound
+#![allow(dead_code)]
+
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::ConnectInfo,
+    http::{Extensions, HeaderMap, Method, Request, StatusCode, header},
+};
+use axum_middleware_mytutorial::{build_router, config::Config, state::AppState};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    //what the layers left for the server (e.g. the MiddlewareTrace)
+    pub extensions: Extensions,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).expect("body is not UTF-8")
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|err| panic!("body is not JSON ({}): {}", err, self.text()))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    //asserts an AppError response (`{"code", "message", ...}`) and returns its body
+    pub fn assert_error(&self, status: StatusCode, code: &str) -> serde_json::Value {
+        assert_eq!(self.status, status, "body: {}", self.text());
+        let body = self.json();
+        assert_eq!(body["code"], code, "body: {}", body);
+        assert!(body["message"].is_string(), "no message: {}", body);
+        body
+    }
+}
+
+//the app for the environment's config changed by `configure`
+pub fn app_with(configure: impl FnOnce(&mut Config)) -> Router {
+    let mut config = Config::from_env();
+    configure(&mut config);
+    build_router(Arc::new(AppState::builder().config(config).build()))
+}
+
+pub async fn send(app: &Router, request: Request<Body>) -> TestResponse {
+    let response = app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("the router is infallible");
+    let (parts, body) = response.into_parts();
+    TestResponse {
+        status: parts.status,
+        headers: parts.headers,
+        extensions: parts.extensions,
+        body: body.collect().await.expect("body error").to_bytes(),
+    }
+}
+
+//a request builder with what the server provides for a real client: the Host header
+//(HTTP/1.1 requires it) and the peer address (ConnectInfo, here a local client)
+pub fn request(method: Method, uri: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::HOST, "localhost")
+        .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+}
+
+//everything logged in this test binary so far (the subscriber is installed on the first
+//call; tests run in parallel, so look for values unique to the test)
+pub fn logs() -> String {
+    static LOGS: OnceLock<Arc<Mutex<Vec<u8>>>> = OnceLock::new();
+    let logs = LOGS.get_or_init(|| {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || LogWriter(writer.clone()))
+            .finish();
+        tracing::subscriber::set_global_default(subscriber).expect("a subscriber is already set");
+        logs
+    });
+    String::from_utf8_lossy(&logs.lock().unwrap()).into_owned()
+}
+
+//what is logged at `level` on this thread until the capture is dropped (instead of going
+//to logs()); the test runtime is single threaded, so that covers the whole request
+pub struct CapturedLogs {
+    logs: Arc<Mutex<Vec<u8>>>,
+    _guard: tracing::subscriber::DefaultGuard,
+}
+
+impl CapturedLogs {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.logs.lock().unwrap()).into_owned()
+    }
+}
+
+pub fn capture_logs(level: tracing::Level) -> CapturedLogs {
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_max_level(level)
+        .with_writer(move || LogWriter(writer.clone()))
+        .finish();
+    CapturedLogs {
+        logs,
+        _guard: tracing::subscriber::set_default(subscriber),
+    }
+}
+
+struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//the app on an ephemeral local port (over hyper, like server::serve), for raw-socket tests
+pub async fn serve(app: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    });
+    addr
+}
+
+//writes `raw` (a complete HTTP/1.1 exchange ending with `Connection: close`) and
+//returns everything the server answered
+pub async fn send_raw(addr: SocketAddr, raw: &str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(raw.as_bytes()).await.unwrap();
+    let mut answer = Vec::new();
+    stream.read_to_end(&mut answer).await.unwrap();
+    String::from_utf8_lossy(&answer).into_owned()
+}
+
+pub fn get(uri: &str) -> Request<Body> {
+    request(Method::GET, uri).body(Body::empty()).unwrap()
+}
+
+pub fn post_json(uri: &str, json: &str) -> Request<Body> {
+    request(Method::POST, uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+//lowercase hex SHA-256, as in the server's `sha256` fields and X-Checksum trailers
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+//an HS256 token over `claims` (auth::verify's format), `exp` defaults to an hour from now
+pub fn jwt(secret: &str, mut claims: serde_json::Value) -> String {
+    if claims.get("exp").is_none() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        claims["exp"] = serde_json::json!(now + 3600);
+    }
+    let encode = |bytes: &[u8]| {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let mut encoded = String::new();
+        for chunk in bytes.chunks(3) {
+            let block = chunk.iter().enumerate().fold(0u32, |block, (index, byte)| {
+                block | (u32::from(*byte) << (16 - 8 * index))
+            });
+            for index in 0..=chunk.len() {
+                encoded.push(ALPHABET[(block >> (18 - 6 * index)) as usize & 0x3f] as char);
+            }
+        }
+        encoded
+    };
+    let signed = format!(
+        "{}.{}",
+        encode(br#"{"alg":"HS256","typ":"JWT"}"#),
+        encode(claims.to_string().as_bytes())
+    );
+    let signature = hmac_sha256(secret, signed.as_bytes());
+    format!("{}.{}", signed, encode(&signature))
+}
+
+//HMAC-SHA256 with a key shorter than the block
+pub fn hmac_sha256(secret: &str, message: &[u8]) -> Vec<u8> {
+    use sha2::{Digest, Sha256};
+
+    let mut key = [0u8; 64];
+    key[..secret.len()].copy_from_slice(secret.as_bytes());
+    let pad = |byte: u8| key.map(|key| key ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
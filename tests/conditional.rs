@@ -0,0 +1,72 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Method, StatusCode, header},
+};
+use axum_middleware_mytutorial::app;
+use common::{app_with, get, request, send};
+
+fn get_if_none_match(uri: &str, etag: &str) -> axum::http::Request<Body> {
+    request(Method::GET, uri)
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn get_responses_carry_a_strong_etag() {
+    let app = app();
+    let response = send(&app, get("/")).await;
+    let etag = response.header("etag").expect("no ETag").to_string();
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "{}", etag);
+    //the same body gets the same tag
+    assert_eq!(
+        send(&app, get("/")).await.header("etag"),
+        Some(etag.as_str())
+    );
+}
+
+#[tokio::test]
+async fn a_matching_if_none_match_gets_a_304() {
+    let app = app();
+    let etag = send(&app, get("/"))
+        .await
+        .header("etag")
+        .unwrap()
+        .to_string();
+    for tag in [
+        etag.clone(),
+        format!("W/{}", etag),
+        format!("\"other\", {}", etag),
+    ] {
+        let response = send(&app, get_if_none_match("/", &tag)).await;
+        assert_eq!(response.status, StatusCode::NOT_MODIFIED, "{}", tag);
+        assert!(response.body.is_empty());
+        assert_eq!(response.header("etag"), Some(etag.as_str()));
+        assert_eq!(response.header("content-type"), None);
+    }
+    let response = send(&app, get_if_none_match("/", "\"other\"")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.text(), "pong");
+}
+
+#[tokio::test]
+async fn large_bodies_are_not_tagged() {
+    let app = app_with(|config| config.etag_max_bytes = 2);
+    let response = send(&app, get("/")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("etag"), None);
+}
+
+#[tokio::test]
+async fn cache_policies_replace_the_handler_cache_control() {
+    let app = app_with(|config| {
+        config.cache_policies = vec!["/=public+max-age=5".parse().unwrap()];
+    });
+    let response = send(&app, get("/")).await;
+    assert_eq!(response.header("cache-control"), Some("public, max-age=5"));
+    //other routes keep their own
+    let response = send(&app, get("/healthz")).await;
+    assert_eq!(response.header("cache-control"), Some("no-store"));
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use std::{
+    env,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+
+use axum_middleware_mytutorial::{build_router, config::Config, state::AppState};
+use common::{get, send};
+
+//the environment is process-wide: the tests of this binary set it one at a time
+fn env_lock() -> MutexGuard<'static, ()> {
+    static ENV: Mutex<()> = Mutex::new(());
+    ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn set_env(vars: &[(&str, &str)]) {
+    for (key, value) in vars {
+        //safe: env_lock is held and nothing else in this binary touches the environment
+        unsafe { env::set_var(key, value) };
+    }
+}
+
+fn remove_env(vars: &[(&str, &str)]) {
+    for (key, _) in vars {
+        unsafe { env::remove_var(key) };
+    }
+}
+
+#[test]
+fn settings_file_values_are_read_per_load() {
+    let _lock = env_lock();
+    let path = env::temp_dir().join(format!("config-test-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        "port = 5055\nbody_limit = 2048\ntypo_setting = 1\n\n[cors]\nmax_age_secs = 77\n",
+    )
+    .unwrap();
+    let vars = [
+        ("CONFIG_FILE", path.to_str().unwrap()),
+        ("APP_BODY_LIMIT", "4096"),
+    ];
+    set_env(&vars);
+
+    //concurrent loads each see the whole file and report its unknown key once
+    let loads: Vec<_> = (0..4).map(|_| thread::spawn(Config::from_env)).collect();
+    for load in loads {
+        let config = load.join().unwrap();
+        assert_eq!(config.port, 5055);
+        assert_eq!(config.cors_max_age.as_secs(), 77);
+        //APP_<KEY> wins over the file
+        assert_eq!(config.body_limit, 4096);
+        let unknown: Vec<_> = config
+            .problems()
+            .into_iter()
+            .filter(|problem| problem.contains("typo_setting"))
+            .collect();
+        assert_eq!(unknown.len(), 1, "{:?}", unknown);
+        //the overridden key is known, not reported
+        let problems = config.problems();
+        assert!(
+            !problems
+                .iter()
+                .any(|problem| problem.contains("body_limit")),
+            "{:?}",
+            problems
+        );
+    }
+    remove_env(&vars);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn unparsable_values_fail_validation() {
+    let _lock = env_lock();
+    let vars = [
+        ("APP_PORT", "fivethousand"),
+        ("APP_TRUSTED_PROXIES", "127.0.0.1,not-an-ip"),
+        ("APP_ROUTE_TIMEOUTS", "/slow"),
+    ];
+    set_env(&vars);
+    let config = Config::from_env();
+    remove_env(&vars);
+
+    let err = config.validate().unwrap_err().to_string();
+    for problem in [
+        r#"invalid PORT: "fivethousand""#,
+        r#"invalid TRUSTED_PROXIES entry "not-an-ip""#,
+        r#"invalid ROUTE_TIMEOUTS entry "/slow""#,
+    ] {
+        assert!(err.contains(problem), "{:?} missing in {}", problem, err);
+    }
+}
+
+#[test]
+fn cors_credentials_need_listed_origins() {
+    let _lock = env_lock();
+    let vars = [("APP_CORS_ALLOW_CREDENTIALS", "true")];
+    set_env(&vars);
+    let any_origin = Config::from_env();
+    let listed = [("APP_CORS_ALLOW_ORIGINS", "https://app.example")];
+    set_env(&listed);
+    let listed_origins = Config::from_env();
+    remove_env(&vars);
+    remove_env(&listed);
+
+    let err = any_origin.validate().unwrap_err().to_string();
+    assert!(
+        err.contains("CORS_ALLOW_CREDENTIALS=true cannot be combined"),
+        "{}",
+        err
+    );
+    assert!(
+        !listed_origins
+            .problems()
+            .iter()
+            .any(|problem| problem.contains("CORS_ALLOW_CREDENTIALS")),
+        "{:?}",
+        listed_origins.problems()
+    );
+}
+
+#[tokio::test]
+async fn deprecated_routes_announce_their_sunset() {
+    let mut config = {
+        let _lock = env_lock();
+        let vars = [(
+            "APP_DEPRECATED_ROUTES",
+            "/api/v1/sample/:path/list=2027-01-01T00:00:00Z,/api/v1/sample/:path/page",
+        )];
+        set_env(&vars);
+        let config = Config::from_env();
+        remove_env(&vars);
+        config
+    };
+    config.swagger_enabled = true;
+    let app = build_router(Arc::new(AppState::builder().config(config).build()));
+
+    let list = send(&app, get("/api/v1/sample/142/list")).await;
+    assert_eq!(list.header("deprecation"), Some("true"));
+    assert_eq!(list.header("sunset"), Some("Fri, 01 Jan 2027 00:00:00 GMT"));
+    let page = send(&app, get("/api/v1/sample/142/page")).await;
+    assert_eq!(page.header("deprecation"), Some("true"));
+    assert_eq!(page.header("sunset"), None);
+    let slow = send(&app, get("/api/v1/sample/142/slow?delay_ms=0")).await;
+    assert_eq!(slow.header("deprecation"), None);
+
+    let document = send(&app, get("/api-docs/openapi.json")).await.json();
+    let paths = &document["paths"];
+    assert_eq!(
+        paths["/api/v1/sample/{path}/list"]["get"]["deprecated"],
+        true
+    );
+    assert!(paths["/api/v1/sample/{path}/slow"]["get"]["deprecated"].is_null());
+}
+
+#[tokio::test]
+async fn a_route_timeout_wins_over_the_request_timeout() {
+    let config = {
+        let _lock = env_lock();
+        let vars = [
+            ("APP_REQUEST_TIMEOUT_SECS", "30"),
+            ("APP_ROUTE_TIMEOUTS", "/api/v1/sample/:path/slow=1"),
+        ];
+        set_env(&vars);
+        let config = Config::from_env();
+        remove_env(&vars);
+        config
+    };
+    let app = build_router(Arc::new(AppState::builder().config(config).build()));
+    let slow = send(&app, get("/api/v1/sample/266/slow?delay_ms=1500")).await;
+    slow.assert_error(
+        axum::http::StatusCode::SERVICE_UNAVAILABLE,
+        "HANDLER_TIMEOUT",
+    );
+    //the other routes keep REQUEST_TIMEOUT_SECS
+    let list = send(&app, get("/api/v1/sample/266/list?count=1")).await;
+    assert_eq!(list.status, axum::http::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn api_keys_carry_scopes_and_rate_limits() {
+    use axum::{
+        body::Body,
+        http::{Method, StatusCode},
+    };
+    use common::request;
+
+    let config = {
+        let _lock = env_lock();
+        let vars = [
+            ("APP_API_KEY_STORE", "memory"),
+            (
+                "APP_API_KEYS",
+                "reader:r-key:read:2,writer:w-key:read+write",
+            ),
+        ];
+        set_env(&vars);
+        let config = Config::from_env();
+        remove_env(&vars);
+        config
+    };
+    let app = build_router(Arc::new(AppState::builder().config(config).build()));
+    let call = |method: Method, key: Option<&str>| {
+        let mut builder = request(method.clone(), "/api/v1/messages");
+        if let Some(key) = key {
+            builder = builder.header("x-api-key", key);
+        }
+        let body = if method == Method::POST {
+            builder = builder.header("content-type", "application/json");
+            Body::from(r#"{"name":"a","message":"b"}"#)
+        } else {
+            Body::empty()
+        };
+        builder.body(body).unwrap()
+    };
+
+    send(&app, call(Method::GET, None))
+        .await
+        .assert_error(StatusCode::UNAUTHORIZED, "API_KEY_REQUIRED");
+    send(&app, call(Method::GET, Some("nope")))
+        .await
+        .assert_error(StatusCode::UNAUTHORIZED, "INVALID_API_KEY");
+
+    let listed = send(&app, call(Method::GET, Some("r-key"))).await;
+    assert_eq!(listed.status, StatusCode::OK, "{}", listed.text());
+    send(&app, call(Method::POST, Some("r-key")))
+        .await
+        .assert_error(StatusCode::FORBIDDEN, "INSUFFICIENT_SCOPE");
+    let created = send(&app, call(Method::POST, Some("w-key"))).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.text());
+
+    //the reader's 2 per minute are used up, the writer has no limit
+    send(&app, call(Method::GET, Some("r-key")))
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    let listed = send(&app, call(Method::GET, Some("w-key"))).await;
+    assert_eq!(listed.status, StatusCode::OK);
+}
+
+#[test]
+fn invalid_rate_limits_are_reported() {
+    let _lock = env_lock();
+    let vars = [
+        (
+            "APP_ROUTE_RATE_LIMITS",
+            "/api/v1/sample/:path=10/20,/api/v1/upload=5/0,no-slash=1",
+        ),
+        ("APP_RATE_LIMIT_DEFAULT", "fast"),
+    ];
+    set_env(&vars);
+    let config = Config::from_env();
+    remove_env(&vars);
+    assert_eq!(config.route_rate_limits.len(), 1);
+    assert_eq!(config.route_rate_limits[0].path, "/api/v1/sample/:path");
+    assert_eq!(
+        (
+            config.route_rate_limits[0].rate.per_minute,
+            config.route_rate_limits[0].rate.burst
+        ),
+        (10, 20)
+    );
+    let problems = config.problems();
+    for expected in [
+        r#"invalid ROUTE_RATE_LIMITS entry "/api/v1/upload=5/0""#,
+        r#"invalid ROUTE_RATE_LIMITS entry "no-slash=1""#,
+        r#"invalid RATE_LIMIT_DEFAULT: "fast""#,
+    ] {
+        assert!(
+            problems.iter().any(|problem| problem == expected),
+            "{} missing in {:?}",
+            expected,
+            problems
+        );
+    }
+    assert!(config.validate().is_err());
+}
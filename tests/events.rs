@@ -0,0 +1,45 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::{app_with, get, send};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn events_stream_the_handled_requests() {
+    let app = app_with(|config| config.events_enabled = true);
+    let response = app.clone().oneshot(get("/events")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/event-stream",
+        "{:?}",
+        response.headers()
+    );
+    let mut body = response.into_body();
+
+    send(&app, get("/")).await;
+    //the subscription's own request comes first
+    let mut received = String::new();
+    while !received.contains(r#""path":"/","#) {
+        let frame = tokio::time::timeout(Duration::from_secs(5), body.frame())
+            .await
+            .expect("no event within 5s")
+            .expect("the stream ended")
+            .unwrap();
+        if let Ok(data) = frame.into_data() {
+            received.push_str(std::str::from_utf8(&data).unwrap());
+        }
+    }
+    assert!(received.contains("event: request\n"), "{}", received);
+    assert!(received.contains(r#""status":200"#), "{}", received);
+}
+
+#[tokio::test]
+async fn events_are_off_unless_enabled() {
+    let app = app_with(|config| config.events_enabled = false);
+    let response = send(&app, get("/events")).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
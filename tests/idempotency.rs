@@ -0,0 +1,80 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Method, StatusCode, header},
+};
+use axum_middleware_mytutorial::app;
+use common::{request, send};
+
+fn create(key: Option<&str>, text: &str) -> axum::http::Request<Body> {
+    let mut builder =
+        request(Method::POST, "/api/v1/messages").header(header::CONTENT_TYPE, "application/json");
+    if let Some(key) = key {
+        builder = builder.header("idempotency-key", key);
+    }
+    builder
+        .body(Body::from(format!(
+            r#"{{"name":"dave","message":"{}"}}"#,
+            text
+        )))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn retries_with_a_key_get_the_first_response() {
+    let app = app();
+    let first = send(&app, create(Some("key-1"), "hi")).await;
+    assert_eq!(first.status, StatusCode::CREATED, "{}", first.text());
+    assert_eq!(first.header("idempotent-replayed"), None);
+
+    let retry = send(&app, create(Some("key-1"), "hi")).await;
+    assert_eq!(retry.status, StatusCode::CREATED);
+    assert_eq!(retry.header("idempotent-replayed"), Some("true"));
+    assert_eq!(retry.json()["id"], first.json()["id"]);
+    assert_eq!(retry.header("location"), first.header("location"));
+
+    //another key is another request
+    let other = send(&app, create(Some("key-2"), "hi")).await;
+    assert_ne!(other.json()["id"], first.json()["id"]);
+}
+
+#[tokio::test]
+async fn requests_without_a_key_run_every_time() {
+    let app = app();
+    let first = send(&app, create(None, "hi")).await;
+    let second = send(&app, create(None, "hi")).await;
+    assert_eq!(second.status, StatusCode::CREATED);
+    assert_ne!(second.json()["id"], first.json()["id"]);
+}
+
+#[tokio::test]
+async fn a_key_cannot_be_reused_with_another_body() {
+    let app = app();
+    send(&app, create(Some("key-1"), "hi")).await;
+    let response = send(&app, create(Some("key-1"), "bye")).await;
+    response.assert_error(StatusCode::UNPROCESSABLE_ENTITY, "IDEMPOTENCY_KEY_REUSED");
+}
+
+#[tokio::test]
+async fn failed_requests_are_not_stored() {
+    let app = app();
+    let invalid = request(Method::POST, "/api/v1/messages")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("idempotency-key", "key-1")
+        .body(Body::from("{"))
+        .unwrap();
+    send(&app, invalid)
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "INVALID_BODY");
+    //a 4xx is a response like any other: the fixed retry needs a new key
+    let retry = send(&app, create(Some("key-1"), "hi")).await;
+    retry.assert_error(StatusCode::UNPROCESSABLE_ENTITY, "IDEMPOTENCY_KEY_REUSED");
+}
+
+#[tokio::test]
+async fn oversized_keys_are_rejected() {
+    let key = "k".repeat(256);
+    let response = send(&app(), create(Some(&key), "hi")).await;
+    response.assert_error(StatusCode::BAD_REQUEST, "INVALID_IDEMPOTENCY_KEY");
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use std::{
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use axum::http::StatusCode;
+use axum_middleware_mytutorial::{build_router, config::Config, lifecycle, state::AppState};
+use common::{get, send};
+use tokio::sync::oneshot;
+
+#[tokio::test]
+async fn shutdown_is_reported_before_the_listener_closes() {
+    let state = Arc::new(AppState::builder().config(Config::from_env()).build());
+    let app = build_router(state.clone());
+    assert_eq!(send(&app, get("/readyz")).await.status, StatusCode::OK);
+
+    let (signal, received) = oneshot::channel::<()>();
+    let shutdown = tokio::spawn(lifecycle::announce_shutdown(
+        state.lifecycle.clone(),
+        Duration::from_millis(300),
+        async move {
+            let _ = received.await;
+        },
+    ));
+    signal.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    //still accepting (the future hasn't completed), but no longer ready
+    assert!(!shutdown.is_finished());
+    assert_eq!(
+        send(&app, get("/readyz")).await.status,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(
+        send(&app, get("/shutdown-status")).await.json()["shutting_down"],
+        true
+    );
+    assert!(
+        send(&app, get("/metrics"))
+            .await
+            .text()
+            .contains("\nshutting_down 1\n")
+    );
+    tokio::time::timeout(Duration::from_secs(2), shutdown)
+        .await
+        .expect("the delay elapses")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_not_ready_response_says_when_to_retry() {
+    let mut config = Config::from_env();
+    config.readiness_retry_after = Duration::from_secs(7);
+    let state = Arc::new(AppState::builder().config(config).build());
+    let app = build_router(state.clone());
+    let ready = send(&app, get("/readyz")).await;
+    assert_eq!(ready.status, StatusCode::OK);
+    assert_eq!(ready.header("retry-after"), None);
+
+    state.lifecycle.shutting_down.store(true, Ordering::SeqCst);
+    let unready = send(&app, get("/readyz")).await;
+    assert_eq!(unready.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(unready.header("retry-after"), Some("7"));
+}
+
+#[tokio::test]
+async fn the_shutdown_status_counts_requests_in_flight() {
+    let app = build_router(Arc::new(
+        AppState::builder().config(Config::from_env()).build(),
+    ));
+    let in_flight = |app| async move {
+        send(&app, get("/shutdown-status")).await.json()["in_flight"]
+            .as_u64()
+            .unwrap()
+    };
+    //the status request counts itself
+    assert_eq!(in_flight(app.clone()).await, 1);
+    let slow = tokio::spawn({
+        let app = app.clone();
+        async move { send(&app, get("/api/v1/sample/131/slow?delay_ms=300")).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(in_flight(app.clone()).await, 2);
+    assert_eq!(slow.await.unwrap().status, StatusCode::OK);
+    assert_eq!(in_flight(app).await, 1);
+}
+
+#[tokio::test]
+async fn warmup_marks_the_app_warmed() {
+    let mut config = Config::from_env();
+    config.dev_mode = true;
+    let app = build_router(Arc::new(AppState::builder().config(config).build()));
+    let warmed =
+        |app| async move { send(&app, get("/shutdown-status")).await.json()["warmed"].clone() };
+    assert_eq!(warmed(app.clone()).await, false);
+
+    let response = send(
+        &app,
+        common::request(axum::http::Method::POST, "/_warmup")
+            .body(axum::body::Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let report = response.json();
+    assert_eq!(report["warmed"], true);
+    let steps: Vec<&str> = report["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|step| {
+            assert_eq!(step["status"], 200, "{}", step);
+            assert!(step["duration_ms"].is_number(), "{}", step);
+            step["name"].as_str().unwrap()
+        })
+        .collect();
+    assert_eq!(steps, ["ping", "shutdown_status"]);
+    assert_eq!(warmed(app).await, true);
+}
+
+#[tokio::test]
+async fn the_shutdown_report_counts_the_requests_served() {
+    let mut config = Config::from_env();
+    config.dev_mode = true;
+    let state = Arc::new(AppState::builder().config(config).build());
+    let app = build_router(state.clone());
+    for uri in ["/", "/healthz", "/no-such-route", "/_error/503"] {
+        send(&app, get(uri)).await;
+    }
+    let captured = common::capture_logs(tracing::Level::INFO);
+    state.lifecycle.log_report();
+    let logs = captured.text();
+    let report = logs
+        .lines()
+        .find(|line| line.contains("shutdown report"))
+        .unwrap_or_else(|| panic!("no report in {}", logs));
+    for field in [
+        "requests=4",
+        "client_errors=1",
+        "server_errors=1",
+        "uptime_secs=",
+        "unfinished=0",
+    ] {
+        assert!(report.contains(field), "{} missing in {}", field, report);
+    }
+}
+
+#[tokio::test]
+async fn readiness_reports_every_check() {
+    let checks = |body: &serde_json::Value| -> Vec<(String, bool)> {
+        body["checks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|check| {
+                (
+                    check["name"].as_str().unwrap().to_string(),
+                    check["ok"].as_bool().unwrap(),
+                )
+            })
+            .collect()
+    };
+    let app = build_router(Arc::new(
+        AppState::builder().config(Config::from_env()).build(),
+    ));
+    let ready = send(&app, get("/readyz")).await;
+    assert_eq!(ready.status, StatusCode::OK);
+    let body = ready.json();
+    assert_eq!(body["status"], "ready");
+    assert_eq!(
+        checks(&body),
+        [
+            ("accepting".to_string(), true),
+            ("message_store".to_string(), true),
+            ("upload_dir".to_string(), true),
+            ("degraded_mode".to_string(), true),
+        ]
+    );
+
+    //a failing non-critical check only degrades the report
+    let mut config = Config::from_env();
+    config.degraded_mode = true;
+    let app = build_router(Arc::new(AppState::builder().config(config).build()));
+    let degraded = send(&app, get("/readyz")).await;
+    assert_eq!(degraded.status, StatusCode::OK);
+    assert_eq!(degraded.json()["status"], "degraded");
+
+    //a critical one makes the instance unready
+    let blocker = std::env::temp_dir().join(format!("readyz-file-{}", std::process::id()));
+    std::fs::write(&blocker, b"").unwrap();
+    let mut config = Config::from_env();
+    config.upload_dir = blocker.join("uploads");
+    let app = build_router(Arc::new(AppState::builder().config(config).build()));
+    let unready = send(&app, get("/readyz")).await;
+    assert_eq!(unready.status, StatusCode::SERVICE_UNAVAILABLE);
+    let body = unready.json();
+    assert_eq!(body["status"], "unavailable");
+    assert!(checks(&body).contains(&("upload_dir".to_string(), false)));
+    let _ = std::fs::remove_file(&blocker);
+}
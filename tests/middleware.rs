@@ -0,0 +1,1582 @@
+mod common;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::Request,
+    http::{Method, StatusCode, header},
+    middleware::{Next, from_fn},
+    response::{IntoResponse, Response},
+    routing,
+};
+use axum_middleware_mytutorial::ratelimit::{Rate, RouteRateLimit};
+use axum_middleware_mytutorial::{
+    app,
+    middleware::{MiddlewareStack, MiddlewareStacks, MiddlewareTrace},
+};
+use common::{app_with, get, post_json, request, send};
+
+//ROUTE_RATE_LIMITS entries
+fn rate_limits(entries: &str) -> Vec<RouteRateLimit> {
+    entries
+        .split(',')
+        .map(|entry| RouteRateLimit::parse(entry).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn cors_allows_any_origin_by_default() {
+    let response = send(
+        &app(),
+        request(Method::GET, "/")
+            .header(header::ORIGIN, "https://example.com")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("access-control-allow-origin"), Some("*"));
+    let exposed = response
+        .header("access-control-expose-headers")
+        .unwrap_or_default();
+    assert!(exposed.contains("x-request-id"), "exposed: {}", exposed);
+}
+
+#[tokio::test]
+async fn cors_answers_preflights() {
+    let response = send(
+        &app(),
+        request(Method::OPTIONS, "/api/v1/sample/1")
+            .header(header::ORIGIN, "https://example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let methods = response
+        .header("access-control-allow-methods")
+        .unwrap_or_default();
+    assert!(methods.contains("POST"), "methods: {}", methods);
+    assert!(response.header("access-control-max-age").is_some());
+}
+
+#[tokio::test]
+async fn cors_omits_headers_for_unlisted_origins() {
+    let app =
+        app_with(|config| config.cors_allow_origins = vec!["https://allowed.example".to_string()]);
+    let response = send(
+        &app,
+        request(Method::GET, "/")
+            .header(header::ORIGIN, "https://other.example")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("access-control-allow-origin"), None);
+}
+
+//sample_middleware buffers and rebuilds the bodies (LOG_BODIES); the handler's
+//status, headers and body must come through unchanged either way
+#[tokio::test]
+async fn body_logging_passes_the_response_through() {
+    for log_bodies in [true, false] {
+        let app = app_with(|config| config.log_bodies = log_bodies);
+        let valid = r#"{"name":"carol","message":"hey"}"#;
+        let created = send(&app, post_json("/api/v1/sample/20?query=q", valid)).await;
+        assert_eq!(
+            created.status,
+            StatusCode::CREATED,
+            "log_bodies={}",
+            log_bodies
+        );
+        assert_eq!(created.header("location"), Some("/api/v1/sample/20"));
+        assert_eq!(
+            created.json()["message"],
+            "path: 20, query: q, body: { name: carol, message: hey }"
+        );
+
+        let invalid = send(&app, post_json("/api/v1/sample/20", r#"{"name":""}"#)).await;
+        assert!(
+            invalid.status.is_client_error(),
+            "log_bodies={}: {}",
+            log_bodies,
+            invalid.text()
+        );
+        assert!(invalid.json()["code"].is_string());
+
+        let missing = send(&app, get("/api/v1/nothing")).await;
+        assert_eq!(
+            missing.status,
+            StatusCode::NOT_FOUND,
+            "log_bodies={}",
+            log_bodies
+        );
+    }
+}
+
+#[tokio::test]
+async fn responses_carry_a_request_id() {
+    let response = send(&app(), get("/")).await;
+    assert!(
+        response
+            .header("x-request-id")
+            .is_some_and(|id| !id.is_empty())
+    );
+
+    let response = send(
+        &app(),
+        request(Method::GET, "/")
+            .header("x-request-id", "test-id-1")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.header("x-request-id"), Some("test-id-1"));
+}
+
+fn trace(response: &common::TestResponse) -> Vec<String> {
+    response
+        .extensions
+        .get::<MiddlewareTrace>()
+        .map(|trace| trace.0.clone())
+        .unwrap_or_default()
+}
+
+async fn pass(request: Request, next: Next) -> Response {
+    next.run(request).await
+}
+
+async fn reject(_request: Request, _next: Next) -> Response {
+    StatusCode::FORBIDDEN.into_response()
+}
+
+//stack layers run in the order they were added, stacks in the order they are listed
+#[tokio::test]
+async fn middleware_stacks_run_in_order() {
+    let stacks = MiddlewareStacks::default()
+        .register(
+            MiddlewareStack::new("outer")
+                .layer("first", from_fn(pass))
+                .layer("second", from_fn(pass)),
+        )
+        .register(MiddlewareStack::new("inner").layer("third", from_fn(pass)))
+        .register(
+            MiddlewareStack::new("closed")
+                .layer("reject", from_fn(reject))
+                .layer("unreached", from_fn(pass)),
+        );
+    let app = Router::new()
+        .route(
+            "/open",
+            stacks.apply(&["outer", "inner"], routing::get(|| async { "ok" })),
+        )
+        .route(
+            "/closed",
+            stacks.apply(&["outer", "closed"], routing::get(|| async { "ok" })),
+        );
+
+    let response = send(&app, get("/open")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        trace(&response),
+        ["outer/first", "outer/second", "inner/third"]
+    );
+
+    //a layer that answers itself ends the trace
+    let response = send(&app, get("/closed")).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        trace(&response),
+        ["outer/first", "outer/second", "closed/reject"]
+    );
+}
+
+#[tokio::test]
+async fn routes_opt_into_the_standard_stacks() {
+    let app = app();
+    let sample = send(
+        &app,
+        post_json("/api/v1/sample/21?query=q", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    assert_eq!(sample.status, StatusCode::CREATED, "{}", sample.text());
+    assert_eq!(
+        trace(&sample),
+        [
+            "idempotent/idempotency_middleware",
+            "heavy-logging/sample_middleware"
+        ]
+    );
+
+    let messages = send(&app, get("/api/v2/messages")).await;
+    assert_eq!(
+        trace(&messages),
+        [
+            "authenticated/require_identity_middleware",
+            "idempotent/idempotency_middleware",
+            "heavy-logging/sample_middleware"
+        ]
+    );
+
+    //ops routes stay outside every stack
+    assert!(trace(&send(&app, get("/healthz")).await).is_empty());
+}
+
+//AUTH_PUBLIC_PATHS doesn't open a route of the authenticated stack
+#[tokio::test]
+async fn authenticated_stack_requires_a_caller_on_public_paths() {
+    let app = app_with(|config| {
+        config.jwt_secret = Some("secret".to_string());
+        config.auth_public_paths = vec!["/".to_string(), "/api/v1/messages".to_string()];
+    });
+    let response = send(&app, get("/api/v1/messages")).await;
+    response.assert_error(StatusCode::UNAUTHORIZED, "AUTHENTICATION_REQUIRED");
+    assert_eq!(send(&app, get("/")).await.status, StatusCode::OK);
+}
+
+//`METHOD /path` entries open only that method (GET also HEAD)
+#[tokio::test]
+async fn public_paths_can_be_limited_to_reads() {
+    let app = app_with(|config| {
+        config.jwt_secret = Some("secret".to_string());
+        config.auth_public_paths = vec!["GET /api/v1/sample".to_string()];
+    });
+    let read = send(&app, get("/api/v1/sample/113/list")).await;
+    assert_eq!(read.status, StatusCode::OK, "{}", read.text());
+    let head = request(Method::HEAD, "/api/v1/sample/113/list")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, head).await.status, StatusCode::OK);
+    send(
+        &app,
+        post_json("/api/v1/sample/113", r#"{"name":"a","message":"b"}"#),
+    )
+    .await
+    .assert_error(StatusCode::UNAUTHORIZED, "UNAUTHORIZED");
+}
+
+#[tokio::test]
+async fn logged_bodies_are_redacted() {
+    common::logs();
+    let app = app_with(|config| config.log_bodies = true);
+    let form = send(
+        &app,
+        request(Method::POST, "/api/v1/sample/21")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("name=dave&message=form-21&password=hunter21"))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(form.status, StatusCode::CREATED, "{}", form.text());
+    let json = r#"{"name":"erin","message":"json-22","token":"secret22"}"#;
+    send(&app, post_json("/api/v1/sample/22", json)).await;
+    send(
+        &app,
+        request(Method::POST, "/api/v1/sample/23")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from("password: text-secret-23"))
+            .unwrap(),
+    )
+    .await;
+
+    let logs = common::logs();
+    assert!(logs.contains("message=form-21&password=***"), "{}", logs);
+    assert!(logs.contains(r#""token":"***""#), "{}", logs);
+    //text that is neither JSON nor a form is logged as it is
+    assert!(
+        logs.contains("request: password: text-secret-23"),
+        "{}",
+        logs
+    );
+    for secret in ["hunter21", "secret22"] {
+        assert!(!logs.contains(secret), "{} logged: {}", secret, logs);
+    }
+}
+
+#[tokio::test]
+async fn long_bodies_are_logged_up_to_the_limit_and_passed_on_whole() {
+    common::logs();
+    let app = app_with(|config| {
+        config.log_bodies = true;
+        config.log_body_max_bytes = 48;
+    });
+    let message = "m".repeat(300);
+    let json = format!(
+        r#"{{"token":"secret-2571","name":"a","message":"{}"}}"#,
+        message
+    );
+    let response = send(&app, post_json("/api/v1/sample/2571", &json)).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    //the handler still got all of it
+    assert!(response.text().contains(&message), "{}", response.text());
+
+    let logs = common::logs();
+    let logged = format!(
+        r#"request: {{"token":"***","name":"a","message":"{}... (truncated, {} bytes)"#,
+        "m".repeat(3),
+        json.len()
+    );
+    assert!(logs.contains(&logged), "{}", logs);
+    assert!(!logs.contains("secret-2571"), "{}", logs);
+
+    //a secret cut off at the limit is masked all the same
+    let json = format!(
+        r#"{{"name":"a","message":"b","token":"{}"}}"#,
+        "s".repeat(100)
+    );
+    send(&app, post_json("/api/v1/sample/2572", &json)).await;
+    let logs = common::logs();
+    assert!(
+        logs.contains(r#"request: {"name":"a","message":"b","token":"***"... (truncated"#),
+        "{}",
+        logs
+    );
+    assert!(!logs.contains(&"s".repeat(20)), "{}", logs);
+}
+
+#[tokio::test]
+async fn binary_bodies_are_logged_as_base64() {
+    common::logs();
+    let app = app_with(|config| config.log_bodies = true);
+    send(
+        &app,
+        request(Method::POST, "/api/v1/sample/2573")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(vec![0xff, 0x00, 0x10, 0x80]))
+            .unwrap(),
+    )
+    .await;
+    let logs = common::logs();
+    assert!(logs.contains("request: base64:/wAQgA=="), "{}", logs);
+}
+
+//POST /api/v1/sample with a chunked body and a Content-Length that disagrees with it
+fn ambiguous_request(content_length_first: bool) -> String {
+    let body = r#"{"name":"frank","message":"hi"}"#;
+    let (cl, te) = ("Content-Length: 4\r\n", "Transfer-Encoding: chunked\r\n");
+    let (first, second) = if content_length_first {
+        (cl, te)
+    } else {
+        (te, cl)
+    };
+    format!(
+        "POST /api/v1/sample/24 HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nConnection: close\r\n{}{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+        first,
+        second,
+        body.len(),
+        body
+    )
+}
+
+#[tokio::test]
+async fn content_length_with_transfer_encoding_is_rejected() {
+    let addr = common::serve(app()).await;
+    let answer = common::send_raw(addr, &ambiguous_request(true)).await;
+    assert!(answer.starts_with("HTTP/1.1 400"), "{}", answer);
+    assert!(answer.contains("AMBIGUOUS_LENGTH"), "{}", answer);
+}
+
+//the documented limitation: hyper drops the later Content-Length, the body is framed by
+//Transfer-Encoding only (no bytes are left over for a second request)
+#[tokio::test]
+async fn transfer_encoding_before_content_length_is_framed_as_chunked() {
+    let addr = common::serve(app()).await;
+    let answer = common::send_raw(addr, &ambiguous_request(false)).await;
+    assert!(answer.starts_with("HTTP/1.1 201"), "{}", answer);
+    assert_eq!(answer.matches("HTTP/1.1 ").count(), 1, "{}", answer);
+}
+
+#[tokio::test]
+async fn duplicate_posts_are_deduplicated_per_caller() {
+    let app = app_with(|config| {
+        config.dedupe_window = std::time::Duration::from_secs(60);
+        config.jwt_secret = Some("secret".to_string());
+    });
+    let post = |sub: &str| {
+        let token = common::jwt("secret", serde_json::json!({ "sub": sub }));
+        request(Method::POST, "/api/v1/sample/173")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name":"a","message":"double click"}"#))
+            .unwrap()
+    };
+    let first = send(&app, post("alice")).await;
+    assert_eq!(first.status, StatusCode::CREATED, "{}", first.text());
+    let again = send(&app, post("alice")).await;
+    assert_eq!(again.status, StatusCode::CREATED);
+    assert_eq!(again.header("x-deduplicated"), Some("true"));
+    //same client IP and body, another subject: runs on its own
+    let other = send(&app, post("bob")).await;
+    assert_eq!(other.status, StatusCode::OK, "{}", other.text());
+    assert_eq!(other.header("x-deduplicated"), None);
+}
+
+fn slow_with_deadline(delay_ms: u64, deadline: Option<&str>) -> Request<Body> {
+    let mut builder = request(
+        Method::GET,
+        &format!("/api/v1/sample/1/slow?delay_ms={}", delay_ms),
+    );
+    if let Some(deadline) = deadline {
+        builder = builder.header("x-request-deadline", deadline);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn a_tight_request_deadline_is_a_504() {
+    let app = app();
+    send(&app, slow_with_deadline(2000, Some("50")))
+        .await
+        .assert_error(StatusCode::GATEWAY_TIMEOUT, "DEADLINE_EXCEEDED");
+    //an RFC3339 deadline already in the past
+    send(&app, slow_with_deadline(0, Some("2000-01-01T00:00:00Z")))
+        .await
+        .assert_error(StatusCode::GATEWAY_TIMEOUT, "DEADLINE_EXCEEDED");
+}
+
+#[tokio::test]
+async fn absent_or_malformed_deadlines_are_ignored() {
+    let app = app();
+    for deadline in [None, Some("soon"), Some("2024-13-45T99:00:00Z")] {
+        let response = send(&app, slow_with_deadline(10, deadline)).await;
+        assert_eq!(
+            response.status,
+            StatusCode::OK,
+            "{:?}: {}",
+            deadline,
+            response.text()
+        );
+    }
+}
+
+#[tokio::test]
+async fn handlers_over_their_timeout_get_a_json_error() {
+    use futures_util::{StreamExt, stream};
+
+    let app = app_with(|config| config.request_timeout = std::time::Duration::from_secs(1));
+    send(&app, slow_with_deadline(1500, None))
+        .await
+        .assert_error(StatusCode::SERVICE_UNAVAILABLE, "HANDLER_TIMEOUT");
+    //a body that stops arriving is the client's fault
+    let stalled =
+        stream::once(async { Ok::<_, std::io::Error>("{\"name\":") }).chain(stream::pending());
+    let response = send(
+        &app,
+        request(Method::POST, "/api/v1/sample/266")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(stalled))
+            .unwrap(),
+    )
+    .await;
+    response.assert_error(StatusCode::REQUEST_TIMEOUT, "BODY_READ_TIMEOUT");
+}
+
+fn gzip_upload(inflated_len: usize) -> Request<Body> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(&vec![0u8; inflated_len]).unwrap();
+    request(Method::POST, "/api/v1/sample/1/raw")
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(encoder.finish().unwrap()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn compressed_bodies_are_capped_by_their_inflated_size() {
+    let app = app_with(|config| config.max_decompressed_bytes = 64 * 1024);
+    //a few hundred bytes on the wire, 1MB inflated
+    let bomb = gzip_upload(1024 * 1024);
+    send(&app, bomb)
+        .await
+        .assert_error(StatusCode::PAYLOAD_TOO_LARGE, "DECOMPRESSED_TOO_LARGE");
+
+    let response = send(&app, gzip_upload(1000)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(
+        response.json()["message"]
+            .as_str()
+            .unwrap()
+            .contains("body: 1000 bytes"),
+        "{}",
+        response.text()
+    );
+}
+
+#[tokio::test]
+async fn deflate_bodies_are_inflated_and_other_codings_rejected() {
+    use std::io::Write;
+
+    let app = app();
+    let json = r#"{"name":"a","message":"deflated"}"#;
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(json.as_bytes()).unwrap();
+    let encoded = |coding: &str, body: Vec<u8>| {
+        request(Method::POST, "/api/v1/sample/267")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_ENCODING, coding)
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let response = send(&app, encoded("deflate", encoder.finish().unwrap())).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    assert!(
+        response.json()["message"]
+            .as_str()
+            .unwrap()
+            .contains("message: deflated"),
+        "{}",
+        response.text()
+    );
+
+    let response = send(&app, encoded("br", json.as_bytes().to_vec())).await;
+    response.assert_error(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "UNSUPPORTED_CONTENT_ENCODING",
+    );
+    assert_eq!(response.header("accept-encoding"), Some("gzip, deflate"));
+    send(&app, encoded("gzip", b"not gzip".to_vec()))
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "INVALID_CONTENT_ENCODING");
+}
+
+#[tokio::test]
+async fn duplicated_single_value_headers_are_rejected() {
+    let app = app();
+    let duplicated = |name: header::HeaderName, first: &str, second: &str| {
+        request(Method::POST, "/api/v1/sample/107")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(name.clone(), first)
+            .header(name, second)
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap()
+    };
+    //the second Content-Type differs from the one the body was written for
+    let body = send(
+        &app,
+        duplicated(header::CONTENT_TYPE, "application/json", "text/plain"),
+    )
+    .await
+    .assert_error(StatusCode::BAD_REQUEST, "DUPLICATE_HEADER");
+    assert!(
+        body["message"].as_str().unwrap().contains("content-type"),
+        "{}",
+        body
+    );
+    send(
+        &app,
+        duplicated(header::AUTHORIZATION, "Bearer one", "Bearer two"),
+    )
+    .await
+    .assert_error(StatusCode::BAD_REQUEST, "DUPLICATE_HEADER");
+}
+
+#[tokio::test]
+async fn sample_writes_are_limited_at_their_own_rate() {
+    let app = app_with(|config| config.route_rate_limits = rate_limits("/api/v1/sample/:path=1/2"));
+    let write = |n: usize| {
+        post_json(
+            "/api/v1/sample/111",
+            &format!(r#"{{"name":"a","message":"{}"}}"#, n),
+        )
+    };
+    for n in 0..2 {
+        let response = send(&app, write(n)).await;
+        assert!(response.status.is_success(), "{}", response.text());
+    }
+    let limited = send(&app, write(2)).await;
+    limited.assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    assert!(limited.header("retry-after").is_some());
+    //routes without an entry keep the default (unlimited without RATE_LIMIT_DEFAULT)
+    for _ in 0..5 {
+        assert_eq!(send(&app, get("/healthz")).await.status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn the_routing_decision_is_traced_only_at_trace_level() {
+    let app = app();
+    let line = "matched route: GET /api/v1/sample/112/page";
+    let captured = common::capture_logs(tracing::Level::DEBUG);
+    send(&app, get("/api/v1/sample/112/page?limit=2")).await;
+    assert!(!captured.text().contains(line), "{}", captured.text());
+    drop(captured);
+
+    let captured = common::capture_logs(tracing::Level::TRACE);
+    send(&app, get("/api/v1/sample/112/page?limit=2")).await;
+    let logs = captured.text();
+    let traced = logs
+        .lines()
+        .find(|traced| traced.contains(line))
+        .unwrap_or_else(|| panic!("no routing trace in {}", logs));
+    assert!(
+        traced.contains(r#"route=Some("/api/v1/sample/:path/page")"#),
+        "{}",
+        traced
+    );
+    assert!(traced.contains(r#"("path", "112")"#), "{}", traced);
+    assert!(traced.contains("query=\"limit=2\""), "{}", traced);
+}
+
+#[tokio::test]
+async fn expect_continue_uploads_over_the_limit_are_rejected_before_the_body() {
+    let addr = common::serve(app_with(|config| config.body_limit = 1024)).await;
+    //the headers only: a client waiting for `100 Continue` sends nothing more
+    let raw = "POST /api/v1/sample/115 HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 1048576\r\nExpect: 100-continue\r\nConnection: close\r\n\r\n";
+    let answer = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        common::send_raw(addr, raw),
+    )
+    .await
+    .expect("the server waited for the body");
+    assert!(answer.starts_with("HTTP/1.1 413"), "{}", answer);
+    assert!(!answer.contains("100 Continue"), "{}", answer);
+}
+
+#[tokio::test]
+async fn oversized_responses_are_capped() {
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    let app = app_with(|config| config.max_response_bytes = 64);
+    //a buffered response is replaced before its head is sent
+    let body = send(
+        &app,
+        post_json(
+            "/api/v1/sample/117",
+            &format!(r#"{{"name":"a","message":"{}"}}"#, "x".repeat(100)),
+        ),
+    )
+    .await
+    .assert_error(StatusCode::INTERNAL_SERVER_ERROR, "RESPONSE_TOO_LARGE");
+    assert!(body["message"].as_str().unwrap().contains("64"), "{}", body);
+
+    //a streamed one is cut off once it crosses the cap
+    let response = app
+        .oneshot(
+            request(Method::POST, "/api/v1/sample/117/stream")
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from_stream(futures_util::stream::iter(
+                    (0..4).map(|_| Ok::<_, std::io::Error>(vec![b'x'; 32])),
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.into_body().collect().await.is_err());
+}
+
+#[tokio::test]
+async fn http11_requests_need_exactly_one_host() {
+    use axum::{extract::ConnectInfo, http::Version};
+    use std::net::SocketAddr;
+
+    let app = app();
+    let without_host = Request::builder()
+        .version(Version::HTTP_11)
+        .uri("/healthz")
+        .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+        .body(Body::empty())
+        .unwrap();
+    let body = send(&app, without_host)
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "MISSING_HOST");
+    assert!(
+        body["message"].as_str().unwrap().contains("without a Host"),
+        "{}",
+        body
+    );
+    let twice = request(Method::GET, "/healthz")
+        .header(header::HOST, "other.example")
+        .body(Body::empty())
+        .unwrap();
+    let body = send(&app, twice)
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "MISSING_HOST");
+    assert_eq!(body["message"], "multiple Host headers");
+}
+
+#[tokio::test]
+async fn a_full_upload_bulkhead_leaves_sample_requests_alone() {
+    let app = app_with(|config| config.bulkhead_raw = 1);
+    let raw = |body: Body| {
+        request(Method::POST, "/api/v1/sample/135/raw")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(body)
+            .unwrap()
+    };
+    //an upload whose body never ends holds the only raw permit
+    let stalled = tokio::spawn({
+        let app = app.clone();
+        let body = Body::from_stream(futures_util::stream::pending::<
+            Result<Vec<u8>, std::io::Error>,
+        >());
+        async move { send(&app, raw(body)).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    send(&app, raw(Body::from("x")))
+        .await
+        .assert_error(StatusCode::SERVICE_UNAVAILABLE, "BULKHEAD_FULL");
+    let response = send(
+        &app,
+        post_json("/api/v1/sample/1", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    assert!(response.status.is_success(), "{}", response.text());
+    stalled.abort();
+}
+
+#[tokio::test]
+async fn chunked_bodies_over_the_limit_are_cut_off() {
+    let app = app_with(|config| config.body_limit = 1024);
+    //no Content-Length: the limit can only apply while the chunks arrive
+    let chunked = |uri: &str, content_type: &str| {
+        request(Method::POST, uri)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from_stream(futures_util::stream::iter(
+                (0..4).map(|_| Ok::<_, std::io::Error>(vec![b' '; 512])),
+            )))
+            .unwrap()
+    };
+    for (uri, content_type) in [
+        ("/api/v1/sample/137", "application/json"),
+        ("/api/v1/sample/137", "application/x-www-form-urlencoded"),
+        ("/api/v1/sample/137/raw", "application/octet-stream"),
+    ] {
+        let body = send(&app, chunked(uri, content_type))
+            .await
+            .assert_error(StatusCode::PAYLOAD_TOO_LARGE, "PAYLOAD_TOO_LARGE");
+        assert!(
+            body["message"].as_str().unwrap().contains("1024"),
+            "{}: {}",
+            uri,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn degraded_mode_skips_the_optional_middleware() {
+    common::logs();
+    let app = app_with(|config| {
+        config.dev_mode = true;
+        config.log_bodies = true;
+    });
+    let post = |message: &str| {
+        post_json(
+            "/api/v1/sample/140",
+            &format!(r#"{{"name":"a","message":"{}"}}"#, message),
+        )
+    };
+    let normal = send(&app, post("logged-140")).await;
+    assert!(normal.header("server-timing").is_some());
+
+    let toggled = send(
+        &app,
+        request(Method::POST, "/_degraded")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(toggled.json()["degraded"], true);
+    let degraded = send(&app, post("unlogged-140")).await;
+    //routing and the handler are untouched
+    assert!(degraded.status.is_success(), "{}", degraded.text());
+    assert_eq!(degraded.header("server-timing"), None);
+
+    //sample_middleware's body line (the handler logs the message itself either way)
+    let logs = common::logs();
+    assert!(
+        logs.contains(r#"request: {"message":"logged-140""#),
+        "{}",
+        logs
+    );
+    assert!(
+        !logs.contains(r#"request: {"message":"unlogged-140""#),
+        "{}",
+        logs
+    );
+}
+
+#[tokio::test]
+async fn buffering_past_the_memory_budget_is_reported() {
+    common::logs();
+    let big = format!(r#"{{"name":"a","message":"{}"}}"#, "x".repeat(200));
+    let app = app_with(|config| {
+        config.log_bodies = true;
+        config.memory_budget_bytes = 256;
+    });
+    let response = send(&app, post_json("/api/v1/sample/141", &big)).await;
+    assert!(response.status.is_success(), "{}", response.text());
+    let over_budget = |path: &str| {
+        common::logs().lines().any(|line| {
+            line.contains("more bytes than the memory budget")
+                && line.contains(&format!("{}\"", path))
+        })
+    };
+    assert!(over_budget("/api/v1/sample/141"), "{}", common::logs());
+    //small requests stay under it
+    let response = send(
+        &app,
+        post_json("/api/v1/sample/1410", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    assert!(response.status.is_success());
+    assert!(!over_budget("/api/v1/sample/1410"));
+
+    let app = app_with(|config| {
+        config.log_bodies = true;
+        config.memory_budget_bytes = 256;
+        config.memory_budget_reject = true;
+    });
+    send(&app, post_json("/api/v1/sample/1411", &big))
+        .await
+        .assert_error(StatusCode::INTERNAL_SERVER_ERROR, "MEMORY_BUDGET_EXCEEDED");
+}
+
+#[tokio::test]
+async fn the_memory_budget_counts_every_buffering_layer_without_body_logging() {
+    common::logs();
+    let big = format!(r#"{{"name":"a","message":"{}"}}"#, "x".repeat(200));
+    let app = app_with(|config| {
+        config.log_bodies = false;
+        config.memory_budget_bytes = 256;
+    });
+    let over_budget = |path: &str| {
+        common::logs().lines().any(|line| {
+            line.contains("more bytes than the memory budget")
+                && line.contains(&format!("{}\"", path))
+        })
+    };
+    //nothing buffers the plain response
+    let response = send(&app, post_json("/api/v1/sample/1412", &big)).await;
+    assert!(response.status.is_success(), "{}", response.text());
+    assert!(!over_budget("/api/v1/sample/1412"), "{}", common::logs());
+    //pretty-printing holds the compact and the pretty copy
+    let response = send(&app, post_json("/api/v1/sample/1413?pretty=true", &big)).await;
+    assert!(response.status.is_success(), "{}", response.text());
+    assert!(over_budget("/api/v1/sample/1413"), "{}", common::logs());
+
+    let app = app_with(|config| {
+        config.log_bodies = false;
+        config.memory_budget_bytes = 256;
+        config.memory_budget_reject = true;
+    });
+    send(&app, post_json("/api/v1/sample/1414?pretty=true", &big))
+        .await
+        .assert_error(StatusCode::INTERNAL_SERVER_ERROR, "MEMORY_BUDGET_EXCEEDED");
+}
+
+#[tokio::test]
+async fn protected_routes_reject_stale_and_replayed_requests() {
+    let app = app_with(|config| {
+        config.replay_protected_routes = vec!["/api/v1/sample/:path".to_string()];
+        config.replay_window = std::time::Duration::from_secs(300);
+    });
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let post = |nonce: &str, timestamp: u64| {
+        request(Method::POST, "/api/v1/sample/143")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-nonce", nonce)
+            .header("x-timestamp", timestamp.to_string())
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap()
+    };
+    let fresh = send(&app, post("nonce-1", now)).await;
+    assert_eq!(fresh.status, StatusCode::CREATED, "{}", fresh.text());
+    send(&app, post("nonce-1", now))
+        .await
+        .assert_error(StatusCode::UNAUTHORIZED, "REPLAYED_NONCE");
+    send(&app, post("nonce-2", now - 301))
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "STALE_REQUEST");
+    send(
+        &app,
+        post_json("/api/v1/sample/143", r#"{"name":"a","message":"b"}"#),
+    )
+    .await
+    .assert_error(StatusCode::BAD_REQUEST, "MISSING_REPLAY_HEADERS");
+    //routes that didn't opt in don't need the headers
+    assert_eq!(
+        send(&app, get("/api/v1/sample/143/list")).await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn oversized_header_values_are_named_in_a_431() {
+    let app = app_with(|config| config.max_header_value_bytes = 1024);
+    let with_cookie = |len: usize| {
+        request(Method::GET, "/healthz")
+            .header(header::COOKIE, format!("session={}", "x".repeat(len)))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(send(&app, with_cookie(512)).await.status, StatusCode::OK);
+    let body = send(&app, with_cookie(2048)).await.assert_error(
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+        "HEADER_VALUE_TOO_LARGE",
+    );
+    assert_eq!(
+        body["message"],
+        "cookie header value is 2056 bytes, limit is 1024"
+    );
+}
+
+#[tokio::test]
+async fn plain_http_is_redirected_or_rejected_when_https_is_required() {
+    let app = app_with(|config| {
+        config.require_https = true;
+        config.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    });
+    let via_proxy = |method: Method, uri: &str, proto: &str| {
+        request(method, uri)
+            .header("x-forwarded-proto", proto)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap()
+    };
+    let redirect = send(
+        &app,
+        via_proxy(Method::GET, "/api/v1/sample/145/list?count=1", "http"),
+    )
+    .await;
+    assert_eq!(redirect.status, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        redirect.header("location"),
+        Some("https://localhost/api/v1/sample/145/list?count=1")
+    );
+    send(&app, via_proxy(Method::POST, "/api/v1/sample/145", "http"))
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "HTTPS_REQUIRED");
+    let secure = send(&app, via_proxy(Method::POST, "/api/v1/sample/145", "https")).await;
+    assert_eq!(secure.status, StatusCode::CREATED, "{}", secure.text());
+
+    //X-Forwarded-Proto from a peer that isn't a trusted proxy is ignored
+    let app = app_with(|config| {
+        config.require_https = true;
+        config.trusted_proxies = vec!["10.0.0.1".parse().unwrap()];
+    });
+    send(&app, via_proxy(Method::POST, "/api/v1/sample/145", "https"))
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "HTTPS_REQUIRED");
+}
+
+//(name, value) in the order they were recorded
+type Fields = Vec<(String, String)>;
+
+//the fields of every `handler` span, as recorded by the time it closes
+#[derive(Clone, Default)]
+struct HandlerSpans(
+    std::sync::Arc<std::sync::Mutex<std::collections::HashMap<tracing::span::Id, Fields>>>,
+);
+
+struct SpanFields<'a>(&'a mut Fields);
+
+impl tracing::field::Visit for SpanFields<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for HandlerSpans {
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        _: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        if attrs.metadata().name() == "handler" {
+            let mut fields = Vec::new();
+            attrs.record(&mut SpanFields(&mut fields));
+            self.0.lock().unwrap().insert(id.clone(), fields);
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        _: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        if let Some(fields) = self.0.lock().unwrap().get_mut(id) {
+            values.record(&mut SpanFields(fields));
+        }
+    }
+}
+
+#[tokio::test]
+async fn handlers_run_in_a_span_with_otel_attributes() {
+    use tracing_subscriber::layer::SubscriberExt;
+    let spans = HandlerSpans::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+    let response = send(
+        &app(),
+        post_json("/api/v1/sample/150", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+
+    let spans = spans.0.lock().unwrap();
+    assert_eq!(spans.len(), 1, "{:?}", spans);
+    let fields = spans.values().next().unwrap();
+    let field = |name: &str| {
+        fields
+            .iter()
+            .rev()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(field("otel.name"), Some("POST /api/v1/sample/:path"));
+    assert_eq!(field("otel.kind"), Some("\"server\""));
+    assert_eq!(field("http.method"), Some("POST"));
+    assert_eq!(field("http.route"), Some("/api/v1/sample/:path"));
+    assert_eq!(field("http.status_code"), Some("201"));
+    let size: usize = field("http.response.body.size").unwrap().parse().unwrap();
+    assert_eq!(size, response.body.len());
+}
+
+#[tokio::test]
+async fn excluded_paths_skip_the_detailed_logging() {
+    let app = app_with(|config| {
+        config.log_bodies = true;
+        config
+            .log_exclude_paths
+            .push("/api/v1/sample/1510".to_string());
+    });
+    let captured = common::capture_logs(tracing::Level::INFO);
+    assert_eq!(send(&app, get("/healthz")).await.status, StatusCode::OK);
+    let response = send(
+        &app,
+        post_json("/api/v1/sample/1510", r#"{"name":"a","message":"quiet"}"#),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    assert!(
+        !captured.text().contains("Preprocess"),
+        "{}",
+        captured.text()
+    );
+    drop(captured);
+
+    let captured = common::capture_logs(tracing::Level::INFO);
+    let response = send(
+        &app,
+        post_json("/api/v1/sample/151", r#"{"name":"a","message":"logged"}"#),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    let logs = captured.text();
+    assert!(logs.contains("Preprocess"), "{}", logs);
+    assert!(logs.contains(r#""message":"logged""#), "{}", logs);
+}
+
+//the request scope carries values from inner layers out to outer ones: the subject
+//authenticated inside the audit layer and the parse phase timed by the extractor
+#[tokio::test]
+async fn inner_layers_share_values_through_the_request_scope() {
+    let app = app_with(|config| config.jwt_secret = Some("secret".to_string()));
+    let token = common::jwt("secret", serde_json::json!({ "sub": "scoped-161" }));
+    let captured = common::capture_logs(tracing::Level::INFO);
+    let response = send(
+        &app,
+        request(Method::POST, "/api/v1/sample/161")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    let timing = response.header("server-timing").unwrap_or_default();
+    assert!(timing.contains("parse;dur="), "{}", timing);
+    let logs = captured.text();
+    let audit = logs
+        .lines()
+        .find(|line| line.contains("audit") && line.contains("/api/v1/sample/:path"))
+        .unwrap_or_else(|| panic!("no audit line in {}", logs));
+    assert!(audit.contains("subject=scoped-161"), "{}", audit);
+}
+
+#[tokio::test]
+async fn null_bytes_in_the_url_are_rejected() {
+    let app = app();
+    for uri in ["/api/v1/sample/1%00", "/api/v1/sample/1/list?count=1%00"] {
+        let body = send(&app, get(uri))
+            .await
+            .assert_error(StatusCode::BAD_REQUEST, "INVALID_URL");
+        assert_eq!(body["message"], "the URL must not contain null bytes");
+    }
+    //an encoded zero digit is fine
+    let response = send(&app, get("/api/v1/sample/1/list?count=%30")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.text(), "[]");
+}
+
+#[tokio::test]
+async fn trickled_bodies_are_aborted_below_the_minimum_rate() {
+    use futures_util::StreamExt;
+
+    let app = app_with(|config| {
+        config.min_body_rate = 100;
+        config.min_body_rate_grace = std::time::Duration::from_secs(1);
+    });
+    //eleven bytes, then nothing more
+    let trickle = futures_util::stream::iter([Ok::<_, std::io::Error>(r#"{"name":"a""#)])
+        .chain(futures_util::stream::pending());
+    let started_at = std::time::Instant::now();
+    let response = send(
+        &app,
+        request(Method::POST, "/api/v1/sample/167")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(trickle))
+            .unwrap(),
+    )
+    .await;
+    let body = response.assert_error(StatusCode::REQUEST_TIMEOUT, "SLOW_BODY");
+    let message = body["message"].as_str().unwrap();
+    assert!(
+        message.starts_with("request body too slow: 11 bytes in 1."),
+        "{}",
+        message
+    );
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(3));
+
+    let response = send(
+        &app,
+        post_json("/api/v1/sample/167", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+}
+
+#[tokio::test]
+async fn only_responses_over_the_minimum_size_are_compressed() {
+    use std::io::Read;
+
+    let small = send(
+        &app(),
+        request(Method::GET, "/")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(small.text(), "pong");
+    assert_eq!(small.header("content-encoding"), None);
+
+    //echoed back in a response of about 2.6KB
+    let message = "compressible ".repeat(200);
+    let post = || {
+        request(Method::POST, "/api/v1/sample/170")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::from(
+                serde_json::json!({ "name": "a", "message": message }).to_string(),
+            ))
+            .unwrap()
+    };
+    let large = send(&app(), post()).await;
+    assert_eq!(large.status, StatusCode::CREATED);
+    assert_eq!(large.header("content-encoding"), Some("gzip"));
+    let mut inflated = String::new();
+    flate2::read::GzDecoder::new(&large.body[..])
+        .read_to_string(&mut inflated)
+        .unwrap();
+    let echoed = serde_json::from_str::<serde_json::Value>(&inflated).unwrap()["message"].clone();
+    assert!(echoed.as_str().unwrap().contains(&message), "{}", echoed);
+    assert!(large.body.len() < inflated.len() / 10);
+
+    //level 0 only frames the bytes: the body grows
+    let stored = send(&app_with(|config| config.compression_level = 0), post()).await;
+    assert_eq!(stored.header("content-encoding"), Some("gzip"));
+    assert!(stored.body.len() > inflated.len());
+    //under a raised threshold it is sent as is
+    let raised = app_with(|config| config.compression_min_size = 64 * 1024);
+    let identity = send(&raised, post()).await;
+    assert_eq!(identity.header("content-encoding"), None);
+    assert_eq!(identity.text(), inflated);
+}
+
+#[tokio::test]
+async fn the_dev_middleware_endpoint_lists_the_layers_innermost_first() {
+    assert_eq!(
+        send(&app(), get("/_middleware")).await.status,
+        StatusCode::NOT_FOUND
+    );
+    let response = send(
+        &app_with(|config| config.dev_mode = true),
+        get("/_middleware"),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let layers = response.json();
+    let names: Vec<&str> = layers
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| layer["name"].as_str().unwrap())
+        .collect();
+    let position = |name: &str| {
+        names
+            .iter()
+            .position(|layer| *layer == name)
+            .unwrap_or_else(|| panic!("{} missing in {:?}", name, names))
+    };
+    //the outermost two, in this order
+    assert_eq!(&names[names.len() - 2..], ["cors", "body_limit"]);
+    assert!(position("context_middleware") < position("cors"));
+    assert!(position("handler_span_middleware") < position("context_middleware"));
+    assert_eq!(
+        layers[position("handler_span_middleware")]["kind"],
+        "route_layer"
+    );
+    assert_eq!(layers[position("cors")]["kind"], "layer");
+}
+
+#[tokio::test]
+async fn rate_limits_are_kept_per_resolved_client_ip() {
+    let configure = |trusted: bool| {
+        move |config: &mut axum_middleware_mytutorial::config::Config| {
+            config.route_rate_limits = rate_limits("/api/v1/sample/:path=1");
+            if !trusted {
+                config.trusted_proxies.clear();
+            }
+        }
+    };
+    let write = |forwarded_for: Option<&str>| {
+        let mut builder = request(Method::POST, "/api/v1/sample/182")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(forwarded_for) = forwarded_for {
+            builder = builder.header("x-forwarded-for", forwarded_for);
+        }
+        builder
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap()
+    };
+    //the test peer 127.0.0.1 is a trusted proxy: every forwarded client has its own bucket
+    let app = app_with(configure(true));
+    for client in [Some("203.0.113.1"), Some("203.0.113.2"), None] {
+        assert!(send(&app, write(client)).await.status.is_success());
+    }
+    send(&app, write(Some("198.51.100.9, 203.0.113.1")))
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+
+    //untrusted: X-Forwarded-For is ignored, all of them are the peer
+    let app = app_with(configure(false));
+    assert!(
+        send(&app, write(Some("203.0.113.1")))
+            .await
+            .status
+            .is_success()
+    );
+    send(&app, write(Some("203.0.113.2")))
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+
+    //not served through server::serve: no peer address to limit by
+    let unserved = axum::http::Request::builder()
+        .method(Method::POST)
+        .uri("/api/v1/sample/182")
+        .header(header::HOST, "localhost")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"name":"a","message":"b"}"#))
+        .unwrap();
+    send(&app, unserved)
+        .await
+        .assert_error(StatusCode::INTERNAL_SERVER_ERROR, "CLIENT_IP_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn vary_lists_only_the_negotiated_headers() {
+    let app = app();
+    let vary = |response: &common::TestResponse| -> Vec<String> {
+        response
+            .headers
+            .get_all(header::VARY)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap().split(','))
+            .map(|name| name.trim().to_string())
+            .collect()
+    };
+    //big enough to compress, JSON so X-Pretty applies
+    let large = send(
+        &app,
+        post_json(
+            "/api/v1/sample/185",
+            &format!(r#"{{"name":"a","message":"{}"}}"#, "x".repeat(2048)),
+        ),
+    )
+    .await;
+    let names = vary(&large);
+    assert!(
+        names.contains(&"accept-encoding".to_string()),
+        "{:?}",
+        names
+    );
+    assert!(names.contains(&"x-pretty".to_string()), "{:?}", names);
+
+    let small = send(
+        &app,
+        post_json("/api/v1/sample/185", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    let names = vary(&small);
+    assert!(
+        !names.contains(&"accept-encoding".to_string()),
+        "{:?}",
+        names
+    );
+    assert!(names.contains(&"x-pretty".to_string()), "{:?}", names);
+
+    //plain text under the compression threshold: nothing negotiated
+    let names = vary(&send(&app, get("/")).await);
+    assert!(
+        !names.contains(&"accept-encoding".to_string()),
+        "{:?}",
+        names
+    );
+    assert!(!names.contains(&"x-pretty".to_string()), "{:?}", names);
+}
+
+#[tokio::test]
+async fn mutating_requests_are_audited() {
+    let app = app_with(|config| config.jwt_secret = Some("secret".to_string()));
+    let token = common::jwt("secret", serde_json::json!({ "sub": "auditor-187" }));
+    let captured = common::capture_logs(tracing::Level::INFO);
+    let response = send(
+        &app,
+        request(Method::POST, "/api/v1/sample/187")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-request-id", "audit-187")
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    send(&app, get("/api/v1/sample/187/list?count=1")).await;
+
+    let logs = captured.text();
+    let audit: Vec<&str> = logs
+        .lines()
+        .filter(|line| line.contains(" audit:"))
+        .collect();
+    assert_eq!(audit.len(), 1, "reads are not audited: {:?}", audit);
+    for field in [
+        "subject=auditor-187",
+        "method=POST",
+        "route=/api/v1/sample/:path",
+        "resource=path=187",
+        "status=201",
+        "request_id=\"audit-187\"",
+        "timestamp_ms=",
+    ] {
+        assert!(
+            audit[0].contains(field),
+            "{} missing in {}",
+            field,
+            audit[0]
+        );
+    }
+}
+
+#[tokio::test]
+async fn log_lines_carry_the_request_id_of_their_request() {
+    let app = app_with(|config| config.log_bodies = true);
+    let captured = common::capture_logs(tracing::Level::INFO);
+    let response = send(
+        &app,
+        request(Method::POST, "/api/v1/sample/251")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-request-id", "correlated-251")
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    let logs = captured.text();
+    for message in ["Preprocess", "path: 251, query", "Postprocess"] {
+        let line = logs
+            .lines()
+            .find(|line| line.contains(message))
+            .unwrap_or_else(|| panic!("no {:?} line in {}", message, logs));
+        assert!(
+            line.contains("request{request_id=correlated-251}"),
+            "{}",
+            line
+        );
+    }
+
+    //generated ids are version 4 UUIDs
+    let generated = send(&app, get("/")).await;
+    let id = generated.header("x-request-id").unwrap();
+    let groups: Vec<usize> = id.split('-').map(str::len).collect();
+    assert_eq!(groups, [8, 4, 4, 4, 12], "{}", id);
+    assert_eq!(&id[14..15], "4", "{}", id);
+}
+
+#[tokio::test]
+async fn bearer_tokens_are_verified_and_their_claims_handed_on() {
+    let app = app_with(|config| config.jwt_secret = Some("secret".to_string()));
+    let write = |token: Option<String>| {
+        let mut builder = request(Method::POST, "/api/v1/sample/252")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder
+            .body(Body::from(r#"{"name":"a","message":"b"}"#))
+            .unwrap()
+    };
+    //public by default
+    assert_eq!(send(&app, get("/")).await.status, StatusCode::OK);
+
+    let captured = common::capture_logs(tracing::Level::INFO);
+    let token = common::jwt("secret", serde_json::json!({ "sub": "claims-252" }));
+    let response = send(&app, write(Some(token))).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    //the handler reads the claims from the request extensions
+    let logs = captured.text();
+    assert!(
+        logs.lines()
+            .any(|line| line.contains("path: 252") && line.contains(r#"user=Some("claims-252")"#)),
+        "{}",
+        logs
+    );
+
+    let missing = send(&app, write(None)).await;
+    missing.assert_error(StatusCode::UNAUTHORIZED, "UNAUTHORIZED");
+    assert_eq!(missing.header("www-authenticate"), Some("Bearer"));
+    let forged = common::jwt("another secret", serde_json::json!({ "sub": "claims-252" }));
+    send(&app, write(Some(forged)))
+        .await
+        .assert_error(StatusCode::UNAUTHORIZED, "INVALID_TOKEN");
+    let expired = common::jwt(
+        "secret",
+        serde_json::json!({ "sub": "claims-252", "exp": 1 }),
+    );
+    send(&app, write(Some(expired)))
+        .await
+        .assert_error(StatusCode::UNAUTHORIZED, "TOKEN_EXPIRED");
+}
+
+#[tokio::test]
+async fn every_route_template_has_its_own_buckets() {
+    let app = app_with(|config| {
+        config.route_rate_limits = rate_limits("/api/v1/sample/:path=1,/api/v1/sample/:path/raw=1");
+    });
+    let raw = || {
+        request(Method::POST, "/api/v1/sample/253/raw")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from("raw"))
+            .unwrap()
+    };
+    let write = || post_json("/api/v1/sample/253", r#"{"name":"a","message":"b"}"#);
+    assert!(send(&app, write()).await.status.is_success());
+    //another path of the same template shares the bucket
+    let limited = send(
+        &app,
+        post_json("/api/v1/sample/1253", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+    let body = limited.assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    //one token a minute
+    assert_eq!(limited.header("retry-after"), Some("60"));
+    assert_eq!(
+        body["message"],
+        "too many /api/v1/sample/:path requests, retry after 60 seconds"
+    );
+    //the raw route still has its token, then runs out on its own
+    assert_eq!(send(&app, raw()).await.status, StatusCode::OK);
+    let body = send(&app, raw())
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    assert_eq!(
+        body["message"],
+        "too many /api/v1/sample/:path/raw requests, retry after 60 seconds"
+    );
+}
+
+#[tokio::test]
+async fn unlisted_routes_fall_back_to_the_default_rate_limit() {
+    let app = app_with(|config| {
+        config.rate_limit_default = Rate::parse("1");
+        config.route_rate_limits = rate_limits("/api/v1/sample/:path=2,/healthz=0");
+    });
+    let write = || post_json("/api/v1/sample/111", r#"{"name":"a","message":"b"}"#);
+    //the listed route has its own limit
+    for _ in 0..2 {
+        assert!(send(&app, write()).await.status.is_success());
+    }
+    send(&app, write())
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    //an unlisted one gets the default
+    let list = || get("/api/v1/sample/111/list?count=1");
+    assert_eq!(send(&app, list()).await.status, StatusCode::OK);
+    send(&app, list())
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    //`=0` exempts a route
+    for _ in 0..3 {
+        assert_eq!(send(&app, get("/healthz")).await.status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn each_request_gets_one_access_log_line() {
+    let app = app_with(|config| config.log_redact_headers.push("x-internal".to_string()));
+    let captured = common::capture_logs(tracing::Level::INFO);
+    let response = send(
+        &app,
+        request(Method::GET, "/api/v1/sample/254/list?count=1")
+            .header(header::USER_AGENT, "agent/254")
+            .header(header::AUTHORIZATION, "Bearer not-for-the-log")
+            .header("x-internal", "also-hidden")
+            .header("x-request-id", "access-254")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let buffered = send(
+        &app,
+        post_json("/api/v1/sample/254", r#"{"name":"a","message":"b"}"#),
+    )
+    .await;
+
+    let logs = captured.text();
+    let entries: Vec<serde_json::Value> = logs
+        .lines()
+        .filter_map(|line| line.split_once(" access: "))
+        .map(|(_, json)| serde_json::from_str(json).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2, "{}", logs);
+    let entry = &entries[0];
+    assert_eq!(entry["method"], "GET");
+    assert_eq!(entry["path"], "/api/v1/sample/254/list");
+    assert_eq!(entry["status"], 200);
+    assert!(entry["latency_ms"].is_number(), "{}", entry);
+    //streamed
+    assert!(entry["response_bytes"].is_null(), "{}", entry);
+    assert_eq!(entry["request_id"], "access-254");
+    assert_eq!(entry["user_agent"], "agent/254");
+    assert_eq!(entry["headers"]["authorization"], "***");
+    assert_eq!(entry["headers"]["x-internal"], "***");
+    assert_eq!(entry["headers"]["user-agent"], "agent/254");
+    assert!(!logs.contains("not-for-the-log"), "{}", logs);
+    assert_eq!(entries[1]["status"], 201);
+    assert_eq!(entries[1]["response_bytes"], buffered.body.len());
+}
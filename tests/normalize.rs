@@ -0,0 +1,67 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use axum_middleware_mytutorial::app;
+use common::{app_with, get, post_json, request, send};
+
+#[tokio::test]
+async fn unknown_paths_get_a_structured_404() {
+    let response = send(&app(), get("/api/v1/nothing")).await;
+    let body = response.assert_error(StatusCode::NOT_FOUND, "NOT_FOUND");
+    assert_eq!(body["message"], "no route for GET /api/v1/nothing");
+    //outside the nested API trees too
+    let response = send(&app(), get("/nothing")).await;
+    response.assert_error(StatusCode::NOT_FOUND, "NOT_FOUND");
+}
+
+#[tokio::test]
+async fn unsupported_methods_get_a_structured_405() {
+    let response = send(
+        &app(),
+        request(Method::DELETE, "/api/v1/sample/1")
+            .body(Default::default())
+            .unwrap(),
+    )
+    .await;
+    let body = response.assert_error(StatusCode::METHOD_NOT_ALLOWED, "METHOD_NOT_ALLOWED");
+    assert_eq!(response.header("allow"), Some("POST"));
+    assert!(
+        body["message"].as_str().unwrap().contains("DELETE"),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn trailing_slashes_are_strict_by_default() {
+    let response = send(&app(), get("/api/v1/messages/")).await;
+    response.assert_error(StatusCode::NOT_FOUND, "NOT_FOUND");
+}
+
+#[tokio::test]
+async fn trailing_slashes_can_redirect() {
+    let app = app_with(|config| config.trailing_slash = "redirect".parse().unwrap());
+    let response = send(&app, get("/api/v1/sample/1/list/?count=2")).await;
+    assert_eq!(response.status, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.header("location"),
+        Some("/api/v1/sample/1/list?count=2")
+    );
+    //the root stays as it is
+    assert_eq!(send(&app, get("/")).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn trailing_slashes_can_be_rewritten() {
+    let app = app_with(|config| config.trailing_slash = "rewrite".parse().unwrap());
+    let response = send(
+        &app,
+        post_json(
+            "/api/v1/sample/30/?query=q",
+            r#"{"name":"a","message":"b"}"#,
+        ),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    assert_eq!(response.header("location"), Some("/api/v1/sample/30"));
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use std::{collections::HashSet, fs, path::Path};
+
+use axum::http::StatusCode;
+use common::{app_with, get, send};
+use serde_json::Value;
+
+const GOLDEN: &str = "tests/golden/openapi.json";
+
+//the combined document as served, for the default config
+async fn document() -> Value {
+    let app = app_with(|config| config.swagger_enabled = true);
+    let response = send(&app, get("/api-docs/openapi.json")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    response.json()
+}
+
+//a changed document is reviewed through the golden file: rerun with UPDATE_GOLDEN=1 and
+//commit the diff
+#[tokio::test]
+async fn document_matches_the_golden_file() {
+    let document = document().await;
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(GOLDEN);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let json = serde_json::to_string_pretty(&document).unwrap();
+        fs::write(&path, json + "\n").unwrap();
+        return;
+    }
+    let golden: Value = serde_json::from_str(
+        &fs::read_to_string(&path).expect("golden file missing, run with UPDATE_GOLDEN=1"),
+    )
+    .unwrap();
+    assert!(
+        document == golden,
+        "the API document differs from {}, rerun with UPDATE_GOLDEN=1 and review the diff",
+        GOLDEN
+    );
+}
+
+//the OpenAPI 3.0 rules a validator would trip over first
+#[tokio::test]
+async fn document_is_valid() {
+    let document = document().await;
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    let schemas = document["components"]["schemas"].as_object().unwrap();
+    let mut refs = Vec::new();
+    collect_refs(&document, &mut refs);
+    for reference in refs {
+        let name = reference
+            .strip_prefix("#/components/schemas/")
+            .unwrap_or_else(|| panic!("unexpected $ref {}", reference));
+        assert!(schemas.contains_key(name), "dangling $ref {}", reference);
+    }
+
+    let mut operation_ids = HashSet::new();
+    for (path, item) in document["paths"].as_object().unwrap() {
+        let templated: HashSet<&str> = path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            .collect();
+        for (method, operation) in item.as_object().unwrap() {
+            let at = format!("{} {}", method, path);
+            let id = operation["operationId"].as_str().unwrap_or_default();
+            assert!(!id.is_empty(), "{}: no operationId", at);
+            assert!(
+                operation_ids.insert(id),
+                "{}: duplicate operationId {}",
+                at,
+                id
+            );
+            assert!(
+                !operation["responses"].as_object().unwrap().is_empty(),
+                "{}: no responses",
+                at
+            );
+            for (status, response) in operation["responses"].as_object().unwrap() {
+                assert!(
+                    response["description"].is_string(),
+                    "{}: response {} has no description",
+                    at,
+                    status
+                );
+            }
+            let parameters = operation["parameters"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            for parameter in &parameters {
+                let name = parameter["name"].as_str().unwrap();
+                assert!(
+                    parameter["schema"].is_object() || parameter["content"].is_object(),
+                    "{}: parameter {} has no schema",
+                    at,
+                    name
+                );
+                if parameter["in"] == "path" {
+                    assert!(
+                        templated.contains(name),
+                        "{}: {} is not in the path",
+                        at,
+                        name
+                    );
+                    assert_eq!(
+                        parameter["required"], true,
+                        "{}: {} must be required",
+                        at, name
+                    );
+                }
+            }
+            for name in &templated {
+                assert!(
+                    parameters
+                        .iter()
+                        .any(|parameter| parameter["in"] == "path" && parameter["name"] == *name),
+                    "{}: path parameter {} is not declared",
+                    at,
+                    name
+                );
+            }
+        }
+    }
+}
+
+fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get("$ref") {
+                refs.push(reference.clone());
+            }
+            object.values().for_each(|value| collect_refs(value, refs));
+        }
+        Value::Array(array) => array.iter().for_each(|value| collect_refs(value, refs)),
+        _ => {}
+    }
+}
+
+//every mounted version also gets a document with only its own tree (plus the ops routes)
+#[tokio::test]
+async fn each_version_has_its_own_document() {
+    let app = app_with(|config| config.swagger_enabled = true);
+    let paths = |document: &Value| -> Vec<String> {
+        document["paths"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    };
+
+    let v1 = send(&app, get("/api-docs/v1/openapi.json")).await.json();
+    assert_eq!(v1["info"]["version"], "0.0.1 (API v1)");
+    let v1_paths = paths(&v1);
+    assert!(v1_paths.contains(&"/api/v1/sample/{path}".to_string()));
+    assert!(!v1_paths.iter().any(|path| path.starts_with("/api/v2")));
+
+    let v2 = send(&app, get("/api-docs/v2/openapi.json")).await.json();
+    assert_eq!(v2["info"]["version"], "0.0.1 (API v2)");
+    let v2_paths = paths(&v2);
+    assert!(v2_paths.contains(&"/api/v2/messages/{id}".to_string()));
+    assert!(!v2_paths.iter().any(|path| path.starts_with("/api/v1")));
+
+    //the sample endpoints are v1-only
+    assert_eq!(
+        send(&app, get("/api/v2/sample/1/list")).await.status,
+        StatusCode::NOT_FOUND
+    );
+}
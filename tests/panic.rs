@@ -0,0 +1,70 @@
+mod common;
+
+use axum::Router;
+use axum::http::{Method, StatusCode};
+use common::{app_with, get, request, send};
+
+//GET /panic is a dev-only route
+fn app() -> Router {
+    app_with(|config| config.dev_mode = true)
+}
+
+#[tokio::test]
+async fn a_panicking_handler_answers_with_a_json_500_and_the_request_id() {
+    let app = app();
+    let response = send(
+        &app,
+        request(Method::GET, "/panic")
+            .header("x-request-id", "panic-test")
+            .body(axum::body::Body::empty())
+            .unwrap(),
+    )
+    .await;
+    let body = response.assert_error(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_SERVER_ERROR");
+    assert_eq!(body["request_id"], "panic-test");
+    assert_eq!(response.header("x-request-id"), Some("panic-test"));
+    //the payload stays in the log
+    assert!(!response.text().contains("GET /panic was requested"));
+}
+
+#[tokio::test]
+async fn panics_are_counted() {
+    let app = app();
+    send(&app, get("/panic")).await;
+    send(&app, get("/panic")).await;
+    let metrics = send(&app, get("/metrics")).await;
+    assert!(
+        metrics.text().contains("\nhttp_panics_total 2\n"),
+        "{}",
+        metrics.text()
+    );
+    //other errors don't carry a request id
+    let body = send(&app, get("/_error/503")).await.json();
+    assert!(body.get("request_id").is_none(), "{}", body);
+}
+
+#[tokio::test]
+async fn a_panicking_middleware_answers_with_a_json_500() {
+    let app = app();
+    let response = send(
+        &app,
+        request(Method::GET, "/panic/middleware")
+            .header("x-request-id", "middleware-panic-test")
+            .body(axum::body::Body::empty())
+            .unwrap(),
+    )
+    .await;
+    let body = response.assert_error(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_SERVER_ERROR");
+    assert_eq!(body["request_id"], "middleware-panic-test");
+
+    //over a real connection, which stays open for the next request
+    let addr = common::serve(app).await;
+    let answer = common::send_raw(
+        addr,
+        "GET /panic/middleware HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(answer.starts_with("HTTP/1.1 500"), "{}", answer);
+    assert!(answer.contains("HTTP/1.1 200"), "{}", answer);
+    assert!(answer.ends_with("pong"), "{}", answer);
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::IntoResponse,
+    routing::{any, get},
+};
+use axum_middleware_mytutorial::config::Config;
+use common::{app_with, post_json, request, send};
+use serde_json::json;
+
+//upstream hits per path, shared with the test
+type Hits = Arc<AtomicUsize>;
+
+//an upstream on an ephemeral port: /echo/* answers with what it received, /flaky with a
+//503 for the first two hits, /down always with a 503, /legacy with a Proxy-Connection
+//header (not in the proxy's own hop-by-hop list)
+async fn upstream() -> (SocketAddr, Hits) {
+    async fn echo(method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        Json(json!({
+            "method": method.as_str(),
+            "uri": uri.to_string(),
+            "x_forwarded_for": header("x-forwarded-for"),
+            "x_custom": header("x-custom"),
+            "x_hop": header("x-hop"),
+            "body": String::from_utf8_lossy(&body),
+        }))
+    }
+    async fn flaky(State(hits): State<Hits>) -> StatusCode {
+        if hits.fetch_add(1, Ordering::SeqCst) < 2 {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::OK
+        }
+    }
+    async fn down(State(hits): State<Hits>) -> StatusCode {
+        hits.fetch_add(1, Ordering::SeqCst);
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+    async fn legacy() -> impl IntoResponse {
+        [("proxy-connection", "keep-alive"), ("x-custom", "kept")]
+    }
+    let hits = Hits::default();
+    let router = Router::new()
+        .route("/base/legacy", get(legacy))
+        .route("/base/echo/*rest", any(echo))
+        .route("/base/flaky", any(flaky))
+        .route("/base/down", get(down))
+        .with_state(hits.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (addr, hits)
+}
+
+fn proxied(addr: SocketAddr, configure: impl FnOnce(&mut Config)) -> Router {
+    app_with(|config| {
+        config.proxy_upstream = Some(format!("http://{}/base/", addr).parse().unwrap());
+        config.proxy_timeout = Duration::from_secs(5);
+        configure(config);
+    })
+}
+
+#[tokio::test]
+async fn requests_are_forwarded_without_hop_by_hop_headers() {
+    let (addr, _) = upstream().await;
+    let app = proxied(addr, |_| {});
+    let response = send(
+        &app,
+        request(Method::PUT, "/proxy/echo/a%20b?x=1")
+            .header("x-custom", "kept")
+            .header("connection", "x-hop")
+            .header("x-hop", "dropped")
+            .body(Body::from("payload"))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let body = response.json();
+    assert_eq!(body["method"], "PUT");
+    assert_eq!(body["uri"], "/base/echo/a%20b?x=1");
+    assert_eq!(body["body"], "payload");
+    assert_eq!(body["x_custom"], "kept");
+    assert!(body["x_hop"].is_null(), "{}", body);
+    assert_eq!(body["x_forwarded_for"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn idempotent_requests_are_retried_but_posts_are_not() {
+    let (addr, hits) = upstream().await;
+    let app = proxied(addr, |_| {});
+    let response = send(&app, common::get("/proxy/flaky")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    let (addr, hits) = upstream().await;
+    let app = proxied(addr, |_| {});
+    let response = send(&app, post_json("/proxy/flaky", "{}")).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn the_circuit_breaker_opens_after_repeated_failures() {
+    let (addr, hits) = upstream().await;
+    let app = proxied(addr, |config| {
+        config.proxy_retries = 0;
+        config.proxy_breaker_threshold = 2;
+        config.proxy_breaker_cooldown = Duration::from_secs(60);
+    });
+    for _ in 0..2 {
+        let response = send(&app, common::get("/proxy/down")).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+    let response = send(&app, common::get("/proxy/down")).await;
+    response.assert_error(StatusCode::SERVICE_UNAVAILABLE, "UPSTREAM_CIRCUIT_OPEN");
+    assert!(response.header("retry-after").is_some());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn an_unreachable_upstream_is_a_502() {
+    //a port nothing listens on any more
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let app = proxied(addr, |config| config.proxy_retries = 0);
+    let response = send(&app, common::get("/proxy/echo/x")).await;
+    response.assert_error(StatusCode::BAD_GATEWAY, "UPSTREAM_UNAVAILABLE");
+}
+
+//what the proxy passes on still goes through the response hop-by-hop filter
+#[tokio::test]
+async fn hop_by_hop_headers_are_stripped_from_responses() {
+    let (addr, _) = upstream().await;
+    let app = proxied(addr, |_| {});
+    let response = send(&app, common::get("/proxy/legacy")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("x-custom"), Some("kept"));
+    assert_eq!(response.header("proxy-connection"), None);
+    assert_eq!(response.header("connection"), None);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{Method, StatusCode, header},
+};
+use axum_middleware_mytutorial::{build_router, config::Config, reload::LiveApp, state::AppState};
+use common::{post_json, request, send};
+use tokio::sync::oneshot;
+
+const BODY: &str = r#"{"name":"a","message":"reload"}"#;
+
+//one test: the reload reads the process environment
+#[tokio::test]
+async fn a_request_in_flight_during_a_reload_finishes_on_the_old_state() {
+    let mut config = Config::from_env();
+    config.sample_query_default = "before-reload".to_string();
+    let state = Arc::new(AppState::builder().config(config).build());
+    let live = LiveApp::new(state.clone(), build_router(state));
+
+    //taken like server::serve does for each request; its body arrives after the reload
+    let router = live.router();
+    let (send_body, body) = oneshot::channel::<&'static str>();
+    let body =
+        futures_util::stream::once(
+            async move { Ok::<_, Infallible>(Bytes::from(body.await.unwrap())) },
+        );
+    let in_flight = tokio::spawn(async move {
+        let request = request(Method::POST, "/api/v1/sample/186")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(body))
+            .unwrap();
+        send(&router, request).await
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    //safe: no other test in this binary reads the environment
+    unsafe { std::env::set_var("APP_SAMPLE_QUERY_DEFAULT", "after-reload") };
+    live.reload(build_router);
+    unsafe { std::env::remove_var("APP_SAMPLE_QUERY_DEFAULT") };
+    assert_eq!(live.state().config.sample_query_default, "after-reload");
+
+    send_body.send(BODY).unwrap();
+    let response = in_flight.await.unwrap();
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    assert!(
+        response.text().contains("query: before-reload,"),
+        "{}",
+        response.text()
+    );
+    //requests arriving after the reload see the new config
+    let response = send(&live.router(), post_json("/api/v1/sample/187", BODY)).await;
+    assert!(
+        response.text().contains("query: after-reload,"),
+        "{}",
+        response.text()
+    );
+}
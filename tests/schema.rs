@@ -0,0 +1,128 @@
+mod common;
+
+use axum::{
+    Json, Router, extract::Path, http::StatusCode, middleware::from_fn_with_state, routing::get,
+};
+use axum_middleware_mytutorial::schema::{
+    ResponseValidation, SchemaValidator, response_schema_middleware,
+};
+use common::{app_with, get as get_request, post_json, send};
+use serde_json::{Value, json};
+use utoipa::openapi::OpenApi;
+
+//GET /items/{id} documented as an Item with a required string `message`
+fn spec() -> OpenApi {
+    serde_json::from_value(json!({
+        "openapi": "3.0.3",
+        "info": { "title": "items", "version": "1" },
+        "paths": {
+            "/items/{id}": {
+                "get": {
+                    "responses": {
+                        "200": {
+                            "description": "OK",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/Item" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                "Item": {
+                    "type": "object",
+                    "required": ["message"],
+                    "properties": { "message": { "type": "string" } }
+                }
+            }
+        }
+    }))
+    .unwrap()
+}
+
+//a handler that drifted from the spec in the ways the id names
+async fn item(Path(id): Path<String>) -> Json<Value> {
+    Json(match id.as_str() {
+        "extra" => json!({ "message": "a", "extra": 1 }),
+        "unlisted" => json!({ "message": "a", "unlisted": true }),
+        "missing" => json!({}),
+        "wrong" => json!({ "message": 1 }),
+        _ => json!({ "message": "a" }),
+    })
+}
+
+fn items(mode: ResponseValidation) -> Router {
+    Router::new()
+        .route("/items/:id", get(item))
+        .route_layer(from_fn_with_state(
+            SchemaValidator::new(mode, &spec()),
+            response_schema_middleware,
+        ))
+}
+
+#[tokio::test]
+async fn drifted_responses_fail_validation() {
+    let app = items(ResponseValidation::Fail);
+    assert_eq!(
+        send(&app, get_request("/items/ok")).await.status,
+        StatusCode::OK
+    );
+    for (id, error) in [
+        ("extra", "$.extra: field is not in the schema"),
+        ("missing", "$.message: required field is missing"),
+        ("wrong", "$.message: expected string, got 1"),
+    ] {
+        let body = send(&app, get_request(&format!("/items/{}", id)))
+            .await
+            .assert_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "RESPONSE_SCHEMA_MISMATCH",
+            );
+        assert_eq!(body["message"], error);
+    }
+    //?fields= may leave out required fields
+    assert_eq!(
+        send(&app, get_request("/items/missing?fields=message"))
+            .await
+            .status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn logged_mismatches_are_passed_through() {
+    common::logs();
+    let app = items(ResponseValidation::Log);
+    let response = send(&app, get_request("/items/unlisted")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["unlisted"], true);
+    let logs = common::logs();
+    assert!(
+        logs.lines()
+            .any(|line| line.contains("does not match the documented schema")
+                && line.contains("$.unlisted: field is not in the schema")),
+        "{}",
+        logs
+    );
+}
+
+//the app's own handlers match ApiDoc
+#[tokio::test]
+async fn sample_responses_match_the_api_document() {
+    let app = app_with(|config| config.response_validation = ResponseValidation::Fail);
+    let body = r#"{"name":"a","message":"b"}"#;
+    for request in [
+        post_json("/api/v1/sample/147", body),
+        post_json("/api/v1/sample/147?echo_headers=true", body),
+        post_json("/api/v1/sample/147?fields=message", body),
+        get_request("/api/v1/sample/147/list"),
+        get_request("/api/v1/sample/147/page"),
+    ] {
+        let response = send(&app, request).await;
+        assert!(response.status.is_success(), "{}", response.text());
+    }
+}
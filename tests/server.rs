@@ -0,0 +1,143 @@
+mod common;
+
+use std::{
+    future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum_middleware_mytutorial::{
+    build_router,
+    config::Config,
+    reload::LiveApp,
+    server::{self, ServerOptions},
+    state::AppState,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+//server::serve on an ephemeral port with `options`, until the test ends
+async fn serve(options: ServerOptions) -> std::net::SocketAddr {
+    let state = Arc::new(AppState::builder().config(Config::from_env()).build());
+    let app = Arc::new(LiveApp::new(state.clone(), build_router(state)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(
+        listener,
+        app,
+        options,
+        None,
+        future::pending(),
+    ));
+    addr
+}
+
+#[tokio::test]
+async fn idle_keepalive_connections_are_closed_after_the_header_read_timeout() {
+    let addr = serve(ServerOptions {
+        keepalive: Duration::from_secs(75),
+        header_read_timeout: Duration::from_secs(1),
+        max_connections: 0,
+    })
+    .await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let started_at = Instant::now();
+    //the connection stays open after the response until the server gives up waiting
+    //for a next request
+    let mut answer = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut answer))
+        .await
+        .expect("the idle connection was kept open")
+        .unwrap();
+    let answer = String::from_utf8_lossy(&answer);
+    assert!(answer.starts_with("HTTP/1.1 200"), "{}", answer);
+    assert!(started_at.elapsed() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn keepalive_can_be_turned_off() {
+    let addr = serve(ServerOptions {
+        keepalive: Duration::ZERO,
+        header_read_timeout: Duration::from_secs(30),
+        max_connections: 0,
+    })
+    .await;
+    let raw = "GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let answer = tokio::time::timeout(Duration::from_secs(5), common::send_raw(addr, raw))
+        .await
+        .expect("the connection was kept alive");
+    assert!(answer.starts_with("HTTP/1.1 200"), "{}", answer);
+    assert!(
+        answer.to_ascii_lowercase().contains("connection: close"),
+        "{}",
+        answer
+    );
+}
+
+#[tokio::test]
+async fn unsupported_http_versions_get_a_json_505() {
+    let addr = serve(ServerOptions {
+        keepalive: Duration::from_secs(75),
+        header_read_timeout: Duration::from_secs(5),
+        max_connections: 0,
+    })
+    .await;
+    for (raw, version) in [
+        (
+            "GET /healthz HTTP/1.2\r\nHost: localhost\r\n\r\n",
+            "HTTP/1.2",
+        ),
+        ("GET /healthz\r\n", "HTTP/0.9"),
+    ] {
+        let answer = tokio::time::timeout(Duration::from_secs(5), common::send_raw(addr, raw))
+            .await
+            .expect("the connection was not closed");
+        assert!(answer.starts_with("HTTP/1.1 505"), "{}", answer);
+        let body = answer.split("\r\n\r\n").nth(1).unwrap_or_default();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["code"], "HTTP_VERSION_NOT_SUPPORTED");
+        assert_eq!(
+            body["message"],
+            format!("{} is not supported, use HTTP/1.0 or HTTP/1.1", version)
+        );
+    }
+
+    let raw = "GET /healthz HTTP/1.0\r\nHost: localhost\r\n\r\n";
+    let answer = common::send_raw(addr, raw).await;
+    assert!(answer.starts_with("HTTP/1.0 200"), "{}", answer);
+}
+
+#[tokio::test]
+async fn connections_past_the_cap_wait_for_a_free_slot() {
+    let addr = serve(ServerOptions {
+        keepalive: Duration::from_secs(75),
+        header_read_timeout: Duration::from_secs(30),
+        max_connections: 1,
+    })
+    .await;
+    let request = b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    first.write_all(request).await.unwrap();
+    let mut buffer = [0u8; 1024];
+    let read = first.read(&mut buffer).await.unwrap();
+    assert!(buffer[..read].starts_with(b"HTTP/1.1 200"));
+
+    //connects (the kernel backlog accepts it) but isn't served while the first is open
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    second.write_all(request).await.unwrap();
+    let waiting = tokio::time::timeout(Duration::from_millis(300), second.read(&mut buffer)).await;
+    assert!(waiting.is_err(), "the second connection was served");
+
+    drop(first);
+    let read = tokio::time::timeout(Duration::from_secs(5), second.read(&mut buffer))
+        .await
+        .expect("the freed slot was not handed on")
+        .unwrap();
+    assert!(buffer[..read].starts_with(b"HTTP/1.1 200"));
+}
@@ -0,0 +1,295 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use axum_middleware_mytutorial::{config::Config, ratelimit::RouteRateLimit};
+use common::{app_with, request, send};
+
+const BOUNDARY: &str = "test-boundary";
+
+//multipart/form-data body with a plain field and a text file
+fn multipart(uri: &str, file: &str) -> Request<Body> {
+    multipart_typed(uri, "text/plain", file.as_bytes())
+}
+
+//the same with a file of any declared type
+fn multipart_typed(uri: &str, content_type: &str, file: &[u8]) -> Request<Body> {
+    let mut body = format!(
+        "preamble\r\n--{b}\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nplain field\r\n--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"upload\"\r\nContent-Type: {content_type}\r\n\r\n",
+        b = BOUNDARY,
+        content_type = content_type
+    )
+    .into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    request(Method::POST, uri)
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+//a fresh UPLOAD_DIR per test (tests run in parallel)
+fn upload_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("upload-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn configured(dir: &Path, configure: impl FnOnce(&mut Config)) -> axum::Router {
+    let dir = dir.to_path_buf();
+    app_with(move |config| {
+        config.upload_dir = dir;
+        configure(config);
+    })
+}
+
+#[tokio::test]
+async fn both_upload_routes_parse_the_same_parts() {
+    let dir = upload_dir("parse");
+    let app = configured(&dir, |_| {});
+    let inspected = send(&app, multipart("/api/v1/sample/1/upload", "hello file")).await;
+    assert_eq!(inspected.status, StatusCode::OK, "{}", inspected.text());
+    let stored = send(&app, multipart("/api/v1/upload", "hello file")).await;
+    assert_eq!(stored.status, StatusCode::CREATED, "{}", stored.text());
+
+    let (inspected, stored) = (inspected.json(), stored.json());
+    //the plain field is not a file
+    assert_eq!(inspected.as_array().unwrap().len(), 1, "{}", inspected);
+    assert_eq!(stored.as_array().unwrap().len(), 1, "{}", stored);
+    for field in ["name", "filename", "content_type", "length", "sha256"] {
+        assert_eq!(inspected[0][field], stored[0][field], "{}", field);
+    }
+    assert_eq!(inspected[0]["length"], 10);
+    let id = stored[0]["id"].as_str().unwrap();
+    assert_eq!(std::fs::read(dir.join(id)).unwrap(), b"hello file");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn the_storing_upload_route_is_rate_limited() {
+    let dir = upload_dir("rate");
+    let app = configured(&dir, |config| {
+        config.route_rate_limits = vec![RouteRateLimit::parse("/api/v1/upload=1").unwrap()];
+    });
+    let first = send(&app, multipart("/api/v1/upload", "one")).await;
+    assert_eq!(first.status, StatusCode::CREATED, "{}", first.text());
+    send(&app, multipart("/api/v1/upload", "two"))
+        .await
+        .assert_error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn multipart_bodies_with_too_many_parts_are_rejected() {
+    let dir = upload_dir("fields");
+    //the plain field and the file are two parts
+    let app = configured(&dir, |config| config.max_upload_fields = 1);
+    for uri in ["/api/v1/sample/1/upload", "/api/v1/upload"] {
+        send(&app, multipart(uri, "hello file"))
+            .await
+            .assert_error(StatusCode::BAD_REQUEST, "TOO_MANY_FIELDS");
+    }
+    let app = configured(&dir, |config| config.max_upload_fields = 2);
+    let response = send(&app, multipart("/api/v1/upload", "hello file")).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn a_part_over_the_field_limit_is_rejected_without_leaving_a_file() {
+    let dir = upload_dir("field-bytes");
+    let app = configured(&dir, |config| config.max_field_bytes = 64);
+    for uri in ["/api/v1/sample/1/upload", "/api/v1/upload"] {
+        send(&app, multipart(uri, &"x".repeat(65)))
+            .await
+            .assert_error(StatusCode::PAYLOAD_TOO_LARGE, "FIELD_TOO_LARGE");
+    }
+    let left = std::fs::read_dir(&dir).map_or(0, |entries| entries.count());
+    assert_eq!(left, 0);
+    let response = send(&app, multipart("/api/v1/upload", &"x".repeat(64))).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn only_allowed_file_types_are_accepted() {
+    let dir = upload_dir("types");
+    let app = configured(&dir, |config| {
+        config.upload_allowed_types = vec!["image/*".to_string(), "text/plain".to_string()];
+    });
+    let png = b"\x89PNG\r\n\x1a\n rest of the image";
+    for uri in ["/api/v1/sample/1/upload", "/api/v1/upload"] {
+        let response = send(&app, multipart_typed(uri, "image/png", png)).await;
+        assert!(response.status.is_success(), "{}", response.text());
+        assert_eq!(response.json()[0]["content_type"], "image/png");
+
+        let error = send(&app, multipart_typed(uri, "application/pdf", b"%PDF-1.7"))
+            .await
+            .assert_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "DISALLOWED_FILE_TYPE");
+        assert_eq!(
+            error["message"],
+            r#"part "file": application/pdf is not allowed"#
+        );
+        //the declared type is allowed, the content is a zip
+        let error = send(&app, multipart_typed(uri, "image/png", b"PK\x03\x04zip"))
+            .await
+            .assert_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "DISALLOWED_FILE_TYPE");
+        assert_eq!(
+            error["message"],
+            r#"part "file": declared image/png, content looks like application/zip"#
+        );
+    }
+    //only the PNG was stored
+    let stored = std::fs::read_dir(&dir).map_or(0, |entries| entries.count());
+    assert_eq!(stored, 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn a_large_file_is_stored_with_its_size_and_checksum() {
+    let dir = upload_dir("large");
+    let app = configured(&dir, |_| {});
+    let file: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let response = send(&app, multipart_typed("/api/v1/upload", "text/plain", &file)).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+    let stored = &response.json()[0];
+    assert_eq!(stored["filename"], "upload");
+    assert_eq!(stored["length"], file.len());
+    assert_eq!(stored["sha256"], common::sha256_hex(&file));
+    let id = stored["id"].as_str().unwrap();
+    assert_eq!(std::fs::read(dir.join(id)).unwrap(), file);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn files_are_downloaded_as_attachments_with_ranges() {
+    let dir = upload_dir("files");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("report.txt"), "0123456789").unwrap();
+    let app = configured(&dir, |config| config.files_dir = dir.clone());
+
+    let whole = send(&app, common::get("/api/v1/files/report.txt")).await;
+    assert_eq!(whole.status, StatusCode::OK, "{}", whole.text());
+    assert_eq!(whole.text(), "0123456789");
+    assert_eq!(
+        whole.header("content-disposition"),
+        Some("attachment; filename=\"report.txt\"")
+    );
+    assert_eq!(whole.header("accept-ranges"), Some("bytes"));
+
+    let ranged = |range: &'static str| {
+        request(Method::GET, "/api/v1/files/report.txt")
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let partial = send(&app, ranged("bytes=2-4")).await;
+    assert_eq!(partial.status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(partial.header("content-range"), Some("bytes 2-4/10"));
+    assert_eq!(partial.text(), "234");
+    let suffix = send(&app, ranged("bytes=-3")).await;
+    assert_eq!(suffix.text(), "789");
+    let outside = send(&app, ranged("bytes=10-")).await;
+    outside.assert_error(StatusCode::RANGE_NOT_SATISFIABLE, "RANGE_NOT_SATISFIABLE");
+    assert_eq!(outside.header("content-range"), Some("bytes */10"));
+
+    for name in ["..%2Freport.txt", ".hidden", "a%5Cb"] {
+        send(&app, common::get(&format!("/api/v1/files/{}", name)))
+            .await
+            .assert_error(StatusCode::BAD_REQUEST, "INVALID_FILE_NAME");
+    }
+    send(&app, common::get("/api/v1/files/missing.txt"))
+        .await
+        .assert_error(StatusCode::NOT_FOUND, "NOT_FOUND");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+//the handler's future is dropped by the timeout: the finished part goes too
+#[tokio::test]
+async fn an_upload_cut_off_by_the_timeout_leaves_no_files() {
+    use futures_util::{StreamExt, stream};
+
+    let dir = upload_dir("timeout");
+    let app = configured(&dir, |config| {
+        config.request_timeout = std::time::Duration::from_secs(1)
+    });
+    let head = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"one\"\r\nContent-Type: text/plain\r\n\r\ncomplete\r\n--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"two\"\r\nContent-Type: text/plain\r\n\r\nhalf of it",
+        b = BOUNDARY
+    );
+    let stalled =
+        stream::once(async move { Ok::<_, std::io::Error>(head) }).chain(stream::pending());
+    let response = send(
+        &app,
+        request(Method::POST, "/api/v1/upload")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from_stream(stalled))
+            .unwrap(),
+    )
+    .await;
+    response.assert_error(StatusCode::REQUEST_TIMEOUT, "BODY_READ_TIMEOUT");
+    let left: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert!(left.is_empty(), "{:?}", left);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn a_dropped_upload_is_never_served_and_leaves_no_files() {
+    use futures_util::{StreamExt, stream};
+
+    let dir = upload_dir("dropped");
+    let app = configured(&dir, |_| {});
+    let head = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"big\"\r\nContent-Type: text/plain\r\n\r\nthe first chunk of many",
+        b = BOUNDARY
+    );
+    let stalled =
+        stream::once(async move { Ok::<_, std::io::Error>(head) }).chain(stream::pending());
+    let upload = request(Method::POST, "/api/v1/upload")
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from_stream(stalled))
+        .unwrap();
+    let running = tokio::spawn({
+        let app = app.clone();
+        async move { send(&app, upload).await }
+    });
+
+    //the part being written is hidden from GET /files/:name
+    let partial = loop {
+        if let Some(entry) = std::fs::read_dir(&dir)
+            .ok()
+            .and_then(|mut entries| entries.next())
+        {
+            break entry.unwrap().file_name().into_string().unwrap();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    };
+    assert!(
+        partial.starts_with('.') && partial.ends_with(".part"),
+        "{}",
+        partial
+    );
+    send(&app, common::get(&format!("/api/v1/files/{}", partial)))
+        .await
+        .assert_error(StatusCode::BAD_REQUEST, "INVALID_FILE_NAME");
+
+    //a client that goes away drops the handler's future
+    running.abort();
+    let _ = running.await;
+    let left: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert!(left.is_empty(), "{:?}", left);
+    let _ = std::fs::remove_dir_all(&dir);
+}